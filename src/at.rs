@@ -0,0 +1,576 @@
+// 基于目录 fd 的 *at 系统调用封装
+//
+// 深层目录树的绝对路径很容易超过 PATH_MAX，逐级持有目录 fd、只用单个名称调用 openat/
+// fstatat/unlinkat/renameat 等，可以处理任意长度的路径；遍历中遇到的软链接一律不跟随。
+// 修改操作的父目录经 open_parent 打开，路径中任何一级是软链接都会被拒绝，检查与操作之间
+// 即使有路径分量被替换成软链接，也不会作用到别处。
+
+use std::ffi::{CString, OsStr, OsString};
+use std::fs;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Component, Path, PathBuf};
+
+const DIR_FLAGS: libc::c_int = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+
+fn cstr(name: &OsStr) -> io::Result<CString> {
+    CString::new(name.as_bytes()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a NUL byte"))
+}
+
+fn cvt(ret: libc::c_int) -> io::Result<libc::c_int> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+fn open_raw(dirfd: RawFd, name: &OsStr, flags: libc::c_int, mode: libc::mode_t) -> io::Result<OwnedFd> {
+    let name = cstr(name)?;
+    let fd = cvt(unsafe { libc::openat(dirfd, name.as_ptr(), flags, mode as libc::c_uint) })?;
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+#[derive(Clone, Copy)]
+pub struct Stat(libc::stat);
+
+// 各字段的宽度随架构而不同（如 aarch64 上 st_nlink 为 u32），访问器统一转换；在 x86_64 上部分转换是多余的
+#[allow(clippy::unnecessary_cast)]
+impl Stat {
+    fn kind(&self) -> libc::mode_t {
+        self.0.st_mode & libc::S_IFMT
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind() == libc::S_IFDIR
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.kind() == libc::S_IFLNK
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.kind() == libc::S_IFREG
+    }
+
+    // FIFO、套接字与设备节点
+    pub fn is_node(&self) -> bool {
+        matches!(self.kind(), libc::S_IFIFO | libc::S_IFSOCK | libc::S_IFCHR | libc::S_IFBLK)
+    }
+
+    pub fn rdev(&self) -> u64 {
+        self.0.st_rdev as u64
+    }
+
+    pub fn len(&self) -> u64 {
+        self.0.st_size as u64
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.0.st_mode
+    }
+
+    pub fn dev(&self) -> u64 {
+        self.0.st_dev as u64
+    }
+
+    pub fn ino(&self) -> u64 {
+        self.0.st_ino as u64
+    }
+
+    pub fn nlink(&self) -> u64 {
+        self.0.st_nlink as u64
+    }
+
+    // 实际占用的磁盘空间（st_blocks 以 512 字节为单位，与文件系统块大小无关）
+    pub fn allocated(&self) -> u64 {
+        self.0.st_blocks as u64 * 512
+    }
+}
+
+fn symlink_in_path(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("refusing to follow symbolic link in parent directories of '{}'", path.display()),
+    )
+}
+
+// openat2(RESOLVE_NO_SYMLINKS)：内核保证解析过程中不经过任何软链接
+fn openat2_no_symlinks(path: &Path) -> io::Result<OwnedFd> {
+    let name = cstr(path.as_os_str())?;
+    let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+    how.flags = DIR_FLAGS as u64;
+    how.resolve = libc::RESOLVE_NO_SYMLINKS;
+    let fd = unsafe {
+        libc::syscall(libc::SYS_openat2, libc::AT_FDCWD, name.as_ptr(), &how as *const libc::open_how, std::mem::size_of::<libc::open_how>())
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+// 不支持 openat2（内核 < 5.6、seccomp 拦截）或路径超过 PATH_MAX 时，逐级以 O_PATH|O_NOFOLLOW 打开
+fn open_no_symlinks_walk(path: &Path) -> io::Result<OwnedFd> {
+    const PATH_FLAGS: libc::c_int = libc::O_PATH | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+    let start = if path.is_absolute() { "/" } else { "." };
+    let mut fd = open_raw(libc::AT_FDCWD, OsStr::new(start), PATH_FLAGS, 0)?;
+    for component in path.components() {
+        let name = match component {
+            Component::Normal(name) => name,
+            Component::ParentDir => OsStr::new(".."),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => continue,
+        };
+        fd = match open_raw(fd.as_raw_fd(), name, PATH_FLAGS, 0) {
+            Ok(next) => next,
+            // O_PATH|O_NOFOLLOW 会打开软链接本身，随后 O_DIRECTORY 报 ENOTDIR
+            Err(e) if e.raw_os_error() == Some(libc::ENOTDIR) => {
+                let dir = Dir { fd };
+                return Err(if dir.stat(name).map(|st| st.is_symlink()).unwrap_or(false) { symlink_in_path(path) } else { e });
+            }
+            Err(e) => return Err(e),
+        };
+    }
+    // O_PATH 描述符不能 fsync 或读目录，换成普通描述符；无读权限时保留 O_PATH
+    match open_raw(fd.as_raw_fd(), OsStr::new("."), DIR_FLAGS, 0) {
+        Ok(readable) => Ok(readable),
+        Err(e) if e.raw_os_error() == Some(libc::EACCES) => Ok(fd),
+        Err(e) => Err(e),
+    }
+}
+
+
+pub struct Dir {
+    fd: OwnedFd,
+}
+
+impl Dir {
+    // 路径过长（ENAMETOOLONG）时从根目录或当前目录逐级 openat
+    pub fn open(path: &Path) -> io::Result<Dir> {
+        match open_raw(libc::AT_FDCWD, path.as_os_str(), DIR_FLAGS, 0) {
+            Ok(fd) => Ok(Dir { fd }),
+            Err(e) if e.raw_os_error() == Some(libc::ENAMETOOLONG) => {
+                let mut dir = Dir::open(Path::new(if path.is_absolute() { "/" } else { "." }))?;
+                for component in path.components() {
+                    match component {
+                        Component::Normal(name) => dir = dir.open_dir_follow(name)?,
+                        Component::ParentDir => dir = dir.open_dir_follow(OsStr::new(".."))?,
+                        Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+                    }
+                }
+                Ok(dir)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // 打开 path 的父目录，返回 (父目录, 最后一级名称)；父目录路径中不允许出现软链接
+    pub fn open_parent(path: &Path) -> io::Result<(Dir, OsString)> {
+        let name = path.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("'{}' has no file name", path.display()))
+        })?;
+        let parent = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        Ok((Dir::open_no_symlinks(parent)?, name.to_os_string()))
+    }
+
+    pub fn open_no_symlinks(path: &Path) -> io::Result<Dir> {
+        let fd = match openat2_no_symlinks(path) {
+            Ok(fd) => fd,
+            Err(e) if e.raw_os_error() == Some(libc::ELOOP) => return Err(symlink_in_path(path)),
+            Err(e) if matches!(e.raw_os_error(), Some(libc::ENOSYS | libc::EPERM | libc::ENAMETOOLONG)) => {
+                open_no_symlinks_walk(path)?
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(Dir { fd })
+    }
+
+    fn open_dir_follow(&self, name: &OsStr) -> io::Result<Dir> {
+        Ok(Dir { fd: open_raw(self.fd.as_raw_fd(), name, DIR_FLAGS, 0)? })
+    }
+
+    pub fn open_dir(&self, name: &OsStr) -> io::Result<Dir> {
+        Ok(Dir { fd: open_raw(self.fd.as_raw_fd(), name, DIR_FLAGS | libc::O_NOFOLLOW, 0)? })
+    }
+
+    pub fn try_clone(&self) -> io::Result<Dir> {
+        Ok(Dir { fd: self.fd.try_clone()? })
+    }
+
+    pub fn metadata(&self) -> io::Result<Stat> {
+        let mut st: libc::stat = unsafe { std::mem::zeroed() };
+        cvt(unsafe { libc::fstat(self.fd.as_raw_fd(), &mut st) })?;
+        Ok(Stat(st))
+    }
+
+    pub fn stat(&self, name: &OsStr) -> io::Result<Stat> {
+        let name = cstr(name)?;
+        let mut st: libc::stat = unsafe { std::mem::zeroed() };
+        cvt(unsafe { libc::fstatat(self.fd.as_raw_fd(), name.as_ptr(), &mut st, libc::AT_SYMLINK_NOFOLLOW) })?;
+        Ok(Stat(st))
+    }
+
+    // 读出全部目录项（不含 . 和 ..）；对复制出的 fd 调用 fdopendir，不影响 self
+    pub fn entries(&self) -> io::Result<Vec<OsString>> {
+        Ok(self.typed_entries()?.into_iter().map(|(name, _)| name).collect())
+    }
+
+    // 同 entries，附带 readdir 给出的 d_type（DT_UNKNOWN 表示文件系统不提供，需要 stat）
+    pub fn typed_entries(&self) -> io::Result<Vec<(OsString, u8)>> {
+        let fd = cvt(unsafe { libc::fcntl(self.fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 0) })?;
+        let dirp = unsafe { libc::fdopendir(fd) };
+        if dirp.is_null() {
+            let err = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(err);
+        }
+        unsafe { libc::rewinddir(dirp) };
+
+        let mut names = Vec::new();
+        let result = loop {
+            unsafe { *libc::__errno_location() = 0 };
+            let entry = unsafe { libc::readdir(dirp) };
+            if entry.is_null() {
+                let err = io::Error::last_os_error();
+                break if err.raw_os_error() == Some(0) { Ok(()) } else { Err(err) };
+            }
+            let name = unsafe { std::ffi::CStr::from_ptr((*entry).d_name.as_ptr()) }.to_bytes();
+            if name != b"." && name != b".." {
+                names.push((OsString::from_vec(name.to_vec()), unsafe { (*entry).d_type }));
+            }
+        };
+        unsafe { libc::closedir(dirp) };
+        result.map(|_| names)
+    }
+
+    pub fn open_file(&self, name: &OsStr) -> io::Result<fs::File> {
+        let fd = open_raw(self.fd.as_raw_fd(), name, libc::O_RDONLY | libc::O_NOFOLLOW | libc::O_CLOEXEC, 0)?;
+        Ok(fs::File::from(fd))
+    }
+
+    // 以 O_EXCL 新建可读写文件，已存在（包括悬空软链接）时失败
+    pub fn create_file(&self, name: &OsStr, mode: u32) -> io::Result<fs::File> {
+        let flags = libc::O_RDWR | libc::O_CREAT | libc::O_EXCL | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+        let fd = open_raw(self.fd.as_raw_fd(), name, flags, mode as libc::mode_t)?;
+        Ok(fs::File::from(fd))
+    }
+
+    pub fn create_dir(&self, name: &OsStr, mode: u32) -> io::Result<()> {
+        let name = cstr(name)?;
+        cvt(unsafe { libc::mkdirat(self.fd.as_raw_fd(), name.as_ptr(), mode as libc::mode_t) })?;
+        Ok(())
+    }
+
+    // mode 含文件类型位；权限受 umask 影响，需要时再 set_mode
+    pub fn mknod(&self, name: &OsStr, mode: u32, rdev: u64) -> io::Result<()> {
+        let name = cstr(name)?;
+        cvt(unsafe { libc::mknodat(self.fd.as_raw_fd(), name.as_ptr(), mode as libc::mode_t, rdev as libc::dev_t) })?;
+        Ok(())
+    }
+
+    pub fn remove_file(&self, name: &OsStr) -> io::Result<()> {
+        let name = cstr(name)?;
+        cvt(unsafe { libc::unlinkat(self.fd.as_raw_fd(), name.as_ptr(), 0) })?;
+        Ok(())
+    }
+
+    pub fn remove_dir(&self, name: &OsStr) -> io::Result<()> {
+        let name = cstr(name)?;
+        cvt(unsafe { libc::unlinkat(self.fd.as_raw_fd(), name.as_ptr(), libc::AT_REMOVEDIR) })?;
+        Ok(())
+    }
+
+    pub fn read_link(&self, name: &OsStr) -> io::Result<PathBuf> {
+        let name = cstr(name)?;
+        let mut buf = vec![0u8; 256];
+        loop {
+            let n = unsafe {
+                libc::readlinkat(self.fd.as_raw_fd(), name.as_ptr(), buf.as_mut_ptr() as *mut libc::c_char, buf.len())
+            };
+            if n < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if (n as usize) < buf.len() {
+                buf.truncate(n as usize);
+                return Ok(PathBuf::from(OsString::from_vec(buf)));
+            }
+            buf.resize(buf.len() * 2, 0);
+        }
+    }
+
+    pub fn symlink(&self, target: &Path, name: &OsStr) -> io::Result<()> {
+        let target = cstr(target.as_os_str())?;
+        let name = cstr(name)?;
+        cvt(unsafe { libc::symlinkat(target.as_ptr(), self.fd.as_raw_fd(), name.as_ptr()) })?;
+        Ok(())
+    }
+
+    // 为 name（可以是相对本目录的多级路径）在 to 中创建硬链接 to_name
+    pub fn link(&self, name: &Path, to: &Dir, to_name: &OsStr) -> io::Result<()> {
+        let name = cstr(name.as_os_str())?;
+        let to_name = cstr(to_name)?;
+        cvt(unsafe { libc::linkat(self.fd.as_raw_fd(), name.as_ptr(), to.fd.as_raw_fd(), to_name.as_ptr(), 0) })?;
+        Ok(())
+    }
+
+    pub fn rename(&self, name: &OsStr, to: &Dir, to_name: &OsStr) -> io::Result<()> {
+        let name = cstr(name)?;
+        let to_name = cstr(to_name)?;
+        cvt(unsafe { libc::renameat(self.fd.as_raw_fd(), name.as_ptr(), to.fd.as_raw_fd(), to_name.as_ptr()) })?;
+        Ok(())
+    }
+
+    // chmod 不跟随软链接：经 O_PATH 描述符的 /proc/self/fd 路径修改（软链接本身没有权限位，直接跳过）
+    pub fn set_mode(&self, name: &OsStr, mode: u32) -> io::Result<()> {
+        let fd = open_raw(self.fd.as_raw_fd(), name, libc::O_PATH | libc::O_NOFOLLOW | libc::O_CLOEXEC, 0)?;
+        let mut st: libc::stat = unsafe { std::mem::zeroed() };
+        cvt(unsafe { libc::fstat(fd.as_raw_fd(), &mut st) })?;
+        if Stat(st).is_symlink() {
+            return Ok(());
+        }
+        let proc_path = cstr(OsStr::new(&format!("/proc/self/fd/{}", fd.as_raw_fd())))?;
+        cvt(unsafe { libc::chmod(proc_path.as_ptr(), (mode & 0o7777) as libc::mode_t) })?;
+        Ok(())
+    }
+
+    // chattr 属性（FS_IOC_GETFLAGS）；只用于普通文件与目录，打开时不跟随软链接、不阻塞
+    pub fn flags(&self, name: &OsStr) -> io::Result<u32> {
+        let fd = open_raw(self.fd.as_raw_fd(), name, libc::O_RDONLY | libc::O_NONBLOCK | libc::O_NOCTTY | libc::O_NOFOLLOW | libc::O_CLOEXEC, 0)?;
+        // 内核按 int 读写，与 ioctl 号中声明的 long 无关
+        let mut flags: libc::c_int = 0;
+        cvt(unsafe { libc::ioctl(fd.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) })?;
+        Ok(flags as u32)
+    }
+
+    pub fn set_flags(&self, name: &OsStr, flags: u32) -> io::Result<()> {
+        let fd = open_raw(self.fd.as_raw_fd(), name, libc::O_RDONLY | libc::O_NONBLOCK | libc::O_NOCTTY | libc::O_NOFOLLOW | libc::O_CLOEXEC, 0)?;
+        let flags = flags as libc::c_int;
+        cvt(unsafe { libc::ioctl(fd.as_raw_fd(), libc::FS_IOC_SETFLAGS, &flags) })?;
+        Ok(())
+    }
+
+    pub fn sync(&self) -> io::Result<()> {
+        cvt(unsafe { libc::fsync(self.fd.as_raw_fd()) })?;
+        Ok(())
+    }
+
+    // 删除 name 指向的文件或整棵目录树（purge）
+    pub fn remove_tree(&self, name: &OsStr, max_depth: usize) -> io::Result<()> {
+        if !self.stat(name)?.is_dir() {
+            return self.remove_file(name);
+        }
+        let root = self.open_dir(name)?;
+        walk(&root, max_depth, |event| match event {
+            Event::Entry { parent, name, stat, .. } if !stat.is_dir() => parent.remove_file(name),
+            Event::Entry { .. } => Ok(()),
+            Event::Leave { parent, name, .. } => parent.remove_dir(name),
+            Event::Unreadable { error, .. } => Err(error),
+        })?;
+        drop(root);
+        self.remove_dir(name)
+    }
+}
+
+pub fn remove_tree(path: &Path, max_depth: usize) -> io::Result<()> {
+    let (parent, name) = Dir::open_parent(path)?;
+    parent.remove_tree(&name, max_depth)
+}
+
+pub enum Event<'a> {
+    // 目录项（目录在进入前报告）；rel 为相对遍历根的路径，仅用于显示和清单
+    Entry { parent: &'a Dir, name: &'a OsStr, rel: &'a Path, stat: &'a Stat },
+    // 目录 name 的内容已全部报告
+    Leave { parent: &'a Dir, name: &'a OsStr },
+    // 已报告的目录无法打开或读取，不会再进入它，也不会有对应的 Leave
+    Unreadable { error: io::Error },
+}
+
+struct Frame {
+    dir: Dir,
+    name: OsString,
+    rel: PathBuf,
+    entries: std::vec::IntoIter<OsString>,
+}
+
+// 深度优先遍历 root，不跟随软链接；每层持有一个目录 fd，深度受 max_depth 限制
+pub fn walk(root: &Dir, max_depth: usize, f: impl FnMut(Event) -> io::Result<()>) -> io::Result<()> {
+    walk_pruned(root, max_depth, |_, _| false, f)
+}
+
+// 同 walk，但 prune 返回 true 的目录只报告 Entry，不进入，也没有对应的 Leave
+pub fn walk_pruned(root: &Dir, max_depth: usize, mut prune: impl FnMut(&Path, &Stat) -> bool,
+                   mut f: impl FnMut(Event) -> io::Result<()>) -> io::Result<()> {
+    let mut stack: Vec<Frame> = vec![Frame {
+        dir: root.try_clone()?,
+        name: OsString::new(),
+        rel: PathBuf::new(),
+        entries: root.entries()?.into_iter(),
+    }];
+
+    loop {
+        let depth = stack.len();
+        let Some(frame) = stack.last_mut() else {
+            break;
+        };
+        let Some(name) = frame.entries.next() else {
+            let done = stack.pop().unwrap();
+            if let Some(parent) = stack.last() {
+                f(Event::Leave { parent: &parent.dir, name: &done.name })?;
+            }
+            continue;
+        };
+
+        let stat = match frame.dir.stat(&name) {
+            Ok(stat) => stat,
+            // 遍历期间被其他进程删除
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let rel = frame.rel.join(&name);
+        f(Event::Entry { parent: &frame.dir, name: &name, rel: &rel, stat: &stat })?;
+        if !stat.is_dir() || prune(&rel, &stat) {
+            continue;
+        }
+
+        if depth > max_depth {
+            return Err(io::Error::other(format!("Directory depth exceeds safety limit ({})", max_depth)));
+        }
+        match frame.dir.open_dir(&name).and_then(|dir| Ok((dir.entries()?, dir))) {
+            Ok((entries, dir)) => stack.push(Frame { dir, name, rel, entries: entries.into_iter() }),
+            Err(error) => f(Event::Unreadable { error })?,
+        }
+    }
+    Ok(())
+}
+
+// 遍历每层占用一个 fd，把软上限提到硬上限，避免深层目录树耗尽描述符
+pub fn raise_fd_limit() {
+    let mut limit: libc::rlimit = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } == 0 && limit.rlim_cur < limit.rlim_max {
+        limit.rlim_cur = limit.rlim_max;
+        unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch_dir;
+
+    const LEVELS: usize = 24;
+
+    // 每级 200 字节的名称，24 级之后路径超过 PATH_MAX（4096）
+    fn level() -> OsString {
+        OsString::from("d".repeat(200))
+    }
+
+    fn build_deep_tree(root: &Path) -> PathBuf {
+        let mut dir = Dir::open(root).unwrap();
+        let mut path = root.to_path_buf();
+        for _ in 0..LEVELS {
+            dir.create_dir(&level(), 0o755).unwrap();
+            dir = dir.open_dir(&level()).unwrap();
+            path.push(level());
+        }
+        dir.create_file(OsStr::new("leaf"), 0o644).unwrap();
+        path
+    }
+
+    #[test]
+    fn opens_walks_and_removes_trees_beyond_path_max() {
+        let root = scratch_dir("at-deep");
+        let deepest = build_deep_tree(&root);
+        assert!(deepest.as_os_str().len() > libc::PATH_MAX as usize);
+        assert!(fs::metadata(&deepest).is_err());
+        assert_eq!(Dir::open(&deepest).unwrap().entries().unwrap(), [OsString::from("leaf")]);
+
+        let (mut entries, mut leaves, mut longest) = (0, 0, 0);
+        walk(&Dir::open(&root).unwrap(), LEVELS + 1, |event| {
+            match event {
+                Event::Entry { rel, .. } => {
+                    entries += 1;
+                    longest = longest.max(rel.as_os_str().len());
+                }
+                Event::Leave { .. } => leaves += 1,
+                Event::Unreadable { error } => return Err(error),
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!((entries, leaves), (LEVELS + 1, LEVELS));
+        assert!(longest > libc::PATH_MAX as usize);
+
+        remove_tree(&root.join(level()), LEVELS + 1).unwrap();
+        assert!(fs::read_dir(&root).unwrap().next().is_none());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn walk_stops_at_depth_limit_and_skips_pruned_dirs() {
+        let root = scratch_dir("at-limit");
+        build_deep_tree(&root);
+        let dir = Dir::open(&root).unwrap();
+        let err = walk(&dir, 5, |_| Ok(())).unwrap_err();
+        assert!(err.to_string().contains("depth exceeds safety limit (5)"));
+
+        // 剪掉第二级：只报告前两级目录，不进入也没有 Leave
+        let mut events = Vec::new();
+        walk_pruned(&dir, 5, |rel, _| rel.components().count() == 2, |event| {
+            events.push(match event {
+                Event::Entry { rel, .. } => format!("entry {}", rel.components().count()),
+                Event::Leave { .. } => "leave".to_string(),
+                Event::Unreadable { error } => return Err(error),
+            });
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(events, ["entry 1", "entry 2", "leave"]);
+
+        remove_tree(&root.join(level()), LEVELS + 1).unwrap();
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn refuses_symlinks_in_parent_directories() {
+        let root = scratch_dir("at-symlink");
+        fs::create_dir(root.join("real")).unwrap();
+        fs::write(root.join("real/file"), "x").unwrap();
+        std::os::unix::fs::symlink("real", root.join("link")).unwrap();
+
+        let (parent, name) = Dir::open_parent(&root.join("real/file")).unwrap();
+        assert_eq!((parent.stat(&name).unwrap().len(), name), (1, OsString::from("file")));
+        for err in [
+            Dir::open_parent(&root.join("link/file")).err().unwrap(),
+            Dir::open_no_symlinks(&root.join("link")).err().unwrap(),
+            // openat2 不可用时的逐级回退同样拒绝
+            open_no_symlinks_walk(&root.join("link")).err().unwrap(),
+        ] {
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        // 最后一级是软链接时只打开其父目录，不跟随
+        let (parent, name) = Dir::open_parent(&root.join("link")).unwrap();
+        assert!(parent.stat(&name).unwrap().is_symlink());
+        assert!(parent.open_dir(&name).is_err());
+        assert!(parent.open_file(&name).is_err());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn create_file_is_exclusive_and_does_not_follow_symlinks() {
+        let root = scratch_dir("at-create");
+        let dir = Dir::open(&root).unwrap();
+        fs::write(root.join("victim"), "keep").unwrap();
+        dir.symlink(Path::new("victim"), OsStr::new("planted")).unwrap();
+        dir.symlink(Path::new("missing"), OsStr::new("dangling")).unwrap();
+        for name in ["victim", "planted", "dangling"] {
+            assert_eq!(dir.create_file(OsStr::new(name), 0o600).err().unwrap().kind(), io::ErrorKind::AlreadyExists);
+        }
+        assert!(root.join("missing").symlink_metadata().is_err());
+        assert_eq!(fs::read_to_string(root.join("victim")).unwrap(), "keep");
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,134 @@
+// 不可变（chattr +i）与仅追加（chattr +a）属性
+//
+// 带这两个属性的文件无法 rename 或删除，目录中的条目也无法移走，逐项移动会在目录中途失败、把一项拆成两半。
+// 计划阶段通过 FS_IOC_GETFLAGS 找出它们：默认跳过该项；root 可以用 --clear-attrs 在移动前清除，
+// 原属性记入元数据，恢复时重新设置。目标所在的目录带这些属性时只报告，不代为清除。
+
+use crate::{at, decode_path, encode_path, scan};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// linux/fs.h
+const FS_IMMUTABLE_FL: u32 = 0x10;
+const FS_APPEND_FL: u32 = 0x20;
+const LOCK_FLAGS: u32 = FS_IMMUTABLE_FL | FS_APPEND_FL;
+
+// 报告中逐个列出的条目数上限
+const MAX_LISTED: usize = 5;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Locked {
+    // 相对删除目标的路径，目标本身为空
+    pub path: String,
+    // 路径不是合法 UTF-8 时，上面一项只是替换后的显示形式，原始字节以 base64 保存在这里
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_b64: Option<String>,
+    // 清除前的全部属性，恢复时原样设置
+    pub flags: u32,
+}
+
+impl Locked {
+    fn new(rel: &Path, flags: u32) -> Locked {
+        let (path, path_b64) = encode_path(rel);
+        Locked { path, path_b64, flags }
+    }
+
+    fn resolve(&self, root: &Path) -> PathBuf {
+        let rel = decode_path(&self.path, self.path_b64.as_deref());
+        if rel.as_os_str().is_empty() { root.to_path_buf() } else { root.join(rel) }
+    }
+}
+
+pub fn describe(flags: u32) -> &'static str {
+    match (flags & FS_IMMUTABLE_FL != 0, flags & FS_APPEND_FL != 0) {
+        (true, true) => "immutable, append-only",
+        (true, false) => "immutable",
+        _ => "append-only",
+    }
+}
+
+// root 下的前几项加剩余数量
+pub fn summary(root: &Path, locked: &[Locked]) -> String {
+    let mut text = locked.iter().take(MAX_LISTED)
+        .map(|l| format!("{} ({})", l.resolve(root).display(), describe(l.flags)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if locked.len() > MAX_LISTED {
+        text.push_str(&format!(" and {} more", locked.len() - MAX_LISTED));
+    }
+    text
+}
+
+// 只检查普通文件与目录；不支持属性的文件系统或无法打开的条目按没有属性处理
+fn check(dir: &at::Dir, name: &OsStr, stat: &at::Stat) -> Option<u32> {
+    if !stat.is_file() && !stat.is_dir() {
+        return None;
+    }
+    dir.flags(name).ok().filter(|f| f & LOCK_FLAGS != 0)
+}
+
+// 目标本身，以及按扫描结果逐项移动时的每个条目；未扫描的目录整体 rename，其中的条目不受影响
+pub fn find(path: &Path, tree: Option<&scan::Tree>) -> io::Result<Vec<Locked>> {
+    let (parent, name) = at::Dir::open_parent(path)?;
+    let mut locked = Vec::new();
+    if let Some(flags) = check(&parent, &name, &parent.stat(&name)?) {
+        locked.push(Locked::new(Path::new(""), flags));
+    }
+    let Some(tree) = tree else {
+        return Ok(locked);
+    };
+
+    // 无法打开的目录记为 None，跳过其中的条目
+    let mut stack = vec![Some(parent.open_dir(&name)?)];
+    let mut rel = PathBuf::new();
+    for step in &tree.steps {
+        match step {
+            scan::Step::Dir(name, stat) | scan::Step::Item(name, stat) => {
+                let dir = stack.last().unwrap().as_ref();
+                if let Some(flags) = dir.and_then(|d| check(d, name, stat)) {
+                    locked.push(Locked::new(&rel.join(name), flags));
+                }
+                if matches!(step, scan::Step::Dir(..)) {
+                    stack.push(dir.and_then(|d| d.open_dir(name).ok()));
+                    rel.push(name);
+                }
+            }
+            scan::Step::Leave => {
+                stack.pop();
+                rel.pop();
+            }
+            scan::Step::Mount => {}
+        }
+    }
+    Ok(locked)
+}
+
+// 目标所在目录的属性：带有时无法从中移走任何条目
+pub fn parent_flags(path: &Path) -> Option<u32> {
+    let parent = path.parent().filter(|p| *p != Path::new("/"))?;
+    let (dir, name) = at::Dir::open_parent(parent).ok()?;
+    check(&dir, &name, &dir.stat(&name).ok()?)
+}
+
+fn set(root: &Path, entry: &Locked, flags: u32) -> io::Result<()> {
+    let (dir, name) = at::Dir::open_parent(&entry.resolve(root))?;
+    dir.set_flags(&name, flags)
+}
+
+// 清除 root 下各条目的不可变与仅追加属性；中途失败时恢复已清除的条目
+pub fn clear(root: &Path, locked: &[Locked]) -> io::Result<()> {
+    for (i, entry) in locked.iter().enumerate() {
+        if let Err(e) = set(root, entry, entry.flags & !LOCK_FLAGS) {
+            reapply(root, &locked[..i]);
+            return Err(io::Error::new(e.kind(), format!("failed to clear attributes of {}: {}", entry.resolve(root).display(), e)));
+        }
+    }
+    Ok(())
+}
+
+// 按记录重新设置属性，返回无法设置的条目与原因
+pub fn reapply(root: &Path, locked: &[Locked]) -> Vec<String> {
+    locked.iter().filter_map(|entry| set(root, entry, entry.flags).err().map(|e| format!("{}: {}", entry.resolve(root).display(), e))).collect()
+}
@@ -0,0 +1,267 @@
+// 大批量删除熔断：滑动时间窗口内移入回收站的项目数或字节数超过上限时断开
+//
+// 失控的脚本或被入侵的账号可能在几分钟内把成千上万个文件移进回收站。熔断断开后，没有终端的删除一律暂停，
+// 直到有人在终端中确认（删除时回答提示，或运行 `srm status --reset`）。断开与复位都以 SECURITY 级别记入审计日志。
+// 计数按秒聚合保存在存储目录下的 breaker.state 中，只在持有独占锁时写入；上限可以在 breaker.json 中调整。
+
+use crate::{atomic_write, log_event, rules};
+use bytesize::ByteSize;
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_FILE: &str = "breaker.json";
+pub const STATE_FILE: &str = "breaker.state";
+
+// 默认窗口与上限：日常清理远达不到，批量误删在造成大范围影响前就会被拦下
+pub const DEFAULT_WINDOW_SECS: u64 = 600;
+pub const DEFAULT_MAX_ITEMS: u64 = 2000;
+pub const DEFAULT_MAX_BYTES: u64 = 200 * 1000 * 1000 * 1000;
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    enabled: Option<bool>,
+    window_secs: Option<u64>,
+    max_items: Option<u64>,
+    max_bytes: Option<rules::Size>,
+}
+
+pub struct Config {
+    pub enabled: bool,
+    pub window_secs: u64,
+    pub max_items: u64,
+    pub max_bytes: u64,
+}
+
+impl Config {
+    // 配置文件不存在时使用默认值；无法解析时拒绝执行删除，避免熔断静默失效
+    fn load(base: &Path) -> Result<Config, String> {
+        let path = base.join(CONFIG_FILE);
+        let content = match fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => "{}".into(),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        let invalid = |what: String| format!("Invalid {}: {}", path.display(), what);
+        let raw: RawConfig = serde_json::from_str(&content).map_err(|e| invalid(e.to_string()))?;
+        let config = Config {
+            enabled: raw.enabled.unwrap_or(true),
+            window_secs: raw.window_secs.unwrap_or(DEFAULT_WINDOW_SECS),
+            max_items: raw.max_items.unwrap_or(DEFAULT_MAX_ITEMS),
+            max_bytes: match &raw.max_bytes {
+                Some(size) => size.bytes().map_err(|e| invalid(format!("max_bytes: {}", e)))?,
+                None => DEFAULT_MAX_BYTES,
+            },
+        };
+        if config.window_secs == 0 || config.max_items == 0 || config.max_bytes == 0 {
+            return Err(invalid("window_secs, max_items and max_bytes must be greater than zero".into()));
+        }
+        Ok(config)
+    }
+
+    pub fn window(&self) -> String {
+        match self.window_secs {
+            s if s % 3600 == 0 => format!("{}h", s / 3600),
+            s if s % 60 == 0 => format!("{}m", s / 60),
+            s => format!("{}s", s),
+        }
+    }
+}
+
+// 断开的时间与原因
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Trip {
+    pub since: DateTime<Utc>,
+    pub reason: String,
+}
+
+impl std::fmt::Display for Trip {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "since {}: {}", self.since.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S"), self.reason)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct State {
+    // [Unix 秒, 项目数, 字节数]，只保留窗口内的部分
+    #[serde(default)]
+    buckets: Vec<(i64, u64, u64)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    open: Option<Trip>,
+}
+
+pub struct Breaker {
+    path: PathBuf,
+    pub config: Config,
+    state: State,
+}
+
+impl Breaker {
+    // 状态文件损坏时按已断开处理：计数丢失后无法判断是否仍在大批量删除
+    pub fn load(base: &Path) -> Result<Breaker, String> {
+        let config = Config::load(base)?;
+        let path = base.join(STATE_FILE);
+        let state = match fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| State {
+                buckets: Vec::new(),
+                open: Some(Trip { since: Utc::now(), reason: format!("{} is unreadable ({})", path.display(), e) }),
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => State::default(),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        Ok(Breaker { path, config, state })
+    }
+
+    // 关闭熔断（enabled: false）时从不拦截
+    pub fn tripped(&self) -> Option<&Trip> {
+        self.state.open.as_ref().filter(|_| self.config.enabled)
+    }
+
+    // 窗口内的项目数与字节数
+    pub fn totals(&self) -> (u64, u64) {
+        let start = Utc::now().timestamp() - self.config.window_secs as i64;
+        self.state.buckets.iter()
+            .filter(|(ts, _, _)| *ts > start)
+            .fold((0, 0), |(items, bytes), (_, i, b)| (items + i, bytes + b))
+    }
+
+    // 记录一项删除；本次使计数越过上限时断开并返回断开信息
+    pub fn record(&mut self, bytes: u64) -> Option<Trip> {
+        if !self.config.enabled {
+            return None;
+        }
+        let now = Utc::now().timestamp();
+        let start = now - self.config.window_secs as i64;
+        self.state.buckets.retain(|(ts, _, _)| *ts > start);
+        match self.state.buckets.last_mut() {
+            Some((ts, i, b)) if *ts == now => {
+                *i += 1;
+                *b += bytes;
+            }
+            _ => self.state.buckets.push((now, 1, bytes)),
+        }
+        if self.state.open.is_some() {
+            return None;
+        }
+
+        let (items, total) = self.totals();
+        let reason = if items > self.config.max_items {
+            format!("{} items trashed in the last {} (limit {})", items, self.config.window(), self.config.max_items)
+        } else if total > self.config.max_bytes {
+            format!("{} trashed in the last {} (limit {})", ByteSize(total), self.config.window(), ByteSize(self.config.max_bytes))
+        } else {
+            return None;
+        };
+        let trip = Trip { since: Utc::now(), reason };
+        self.state.open = Some(trip.clone());
+        Some(trip)
+    }
+
+    // 人工确认后闭合，计数从零开始，否则下一项删除会立即再次断开
+    pub fn reset(&mut self, via: &str) {
+        let trip = self.state.open.take();
+        self.state = State::default();
+        log_event("SECURITY", "Circuit breaker reset", Some(json!({
+            "via": via,
+            "since": trip.as_ref().map(|t| t.since),
+            "reason": trip.map(|t| t.reason)
+        })));
+        if let Err(e) = self.save() {
+            eprintln!("⚠️  Failed to save circuit breaker state: {}", e);
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let content = serde_json::to_vec_pretty(&self.state).map_err(io::Error::other)?;
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        atomic_write(dir, STATE_FILE, &content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch_dir;
+
+    fn store(name: &str, config: &str) -> PathBuf {
+        let base = scratch_dir(name);
+        fs::write(base.join(CONFIG_FILE), config).unwrap();
+        base
+    }
+
+    #[test]
+    fn loads_config_with_defaults_and_rejects_bad_values() {
+        let base = scratch_dir("breaker-config");
+        let defaults = Config::load(&base).unwrap();
+        assert!(defaults.enabled);
+        assert_eq!((defaults.window_secs, defaults.max_items, defaults.max_bytes), (DEFAULT_WINDOW_SECS, DEFAULT_MAX_ITEMS, DEFAULT_MAX_BYTES));
+        assert_eq!(defaults.window(), "10m");
+
+        fs::write(base.join(CONFIG_FILE), r#"{"window_secs": 7200, "max_bytes": "1 MiB"}"#).unwrap();
+        let config = Config::load(&base).unwrap();
+        assert_eq!((config.window(), config.max_bytes), ("2h".to_string(), 1024 * 1024));
+        let errors: Vec<String> = [r#"{"max_items": 0}"#, r#"{"max_bytes": "lots"}"#, r#"{"windows": 60}"#, "{"].iter().map(|c| {
+            fs::write(base.join(CONFIG_FILE), c).unwrap();
+            Config::load(&base).err().unwrap_or_default()
+        }).collect();
+        let _ = fs::remove_dir_all(&base);
+        assert!(errors.iter().all(|e| e.starts_with("Invalid ")), "{:?}", errors);
+    }
+
+    #[test]
+    fn trips_on_items_or_bytes_within_the_window() {
+        let base = store("breaker-trip", r#"{"window_secs": 90, "max_items": 3, "max_bytes": 1000}"#);
+        let mut breaker = Breaker::load(&base).unwrap();
+        assert!(breaker.record(10).is_none() && breaker.record(10).is_none() && breaker.record(10).is_none());
+        let trip = breaker.record(10).unwrap();
+        assert_eq!(trip.reason, "4 items trashed in the last 90s (limit 3)");
+        // 断开后继续计数，但不重复断开
+        assert!(breaker.record(10).is_none());
+        assert_eq!(breaker.totals(), (5, 50));
+        breaker.save().unwrap();
+        assert_eq!(Breaker::load(&base).unwrap().tripped().map(|t| t.reason.clone()), Some(trip.reason));
+
+        // 复位后计数归零并保存
+        breaker.reset("test");
+        let reloaded = Breaker::load(&base).unwrap();
+        assert!(reloaded.tripped().is_none());
+        assert_eq!(reloaded.totals(), (0, 0));
+
+        let mut breaker = reloaded;
+        let trip = breaker.record(2000).unwrap();
+        let _ = fs::remove_dir_all(&base);
+        assert!(trip.reason.contains("trashed in the last 90s"), "{}", trip.reason);
+    }
+
+    #[test]
+    fn forgets_counts_outside_the_window() {
+        let base = store("breaker-window", r#"{"window_secs": 60, "max_items": 5}"#);
+        let now = Utc::now().timestamp();
+        let state = json!({ "buckets": [[now - 3600, 100, 100], [now - 61, 4, 0], [now - 30, 4, 0]] });
+        fs::write(base.join(STATE_FILE), state.to_string()).unwrap();
+        let mut breaker = Breaker::load(&base).unwrap();
+        assert_eq!(breaker.totals(), (4, 0));
+        assert!(breaker.record(0).is_none());
+        // 过期的桶在记录时丢弃
+        assert_eq!(breaker.state.buckets.len(), 2);
+        assert!(breaker.record(0).is_some());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn unreadable_state_counts_as_tripped_unless_disabled() {
+        let base = store("breaker-corrupt", "{}");
+        fs::write(base.join(STATE_FILE), "not json").unwrap();
+        let tripped = Breaker::load(&base).unwrap().tripped().map(|t| t.reason.clone());
+        fs::write(base.join(CONFIG_FILE), r#"{"enabled": false, "max_items": 1}"#).unwrap();
+        let mut disabled = Breaker::load(&base).unwrap();
+        let _ = fs::remove_dir_all(&base);
+        assert!(tripped.is_some_and(|r| r.contains("is unreadable")));
+        assert!(disabled.tripped().is_none());
+        assert!(disabled.record(1).is_none() && disabled.record(1).is_none());
+    }
+}
@@ -0,0 +1,175 @@
+// 正在被其他进程使用的文件：/proc/<pid>/fd 中打开的文件与 /proc/<pid>/maps 中映射的文件
+//
+// 进程继续写入已移入回收站的文件，或跨设备复制时读到写了一半的内容，删除看起来成功但结果不可靠。
+// 只能看到有权限读取的进程（非 root 时通常只有自己的进程），结果用于提醒或拒绝，不保证完整。
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+// 每个进程在报告中列出的路径数上限
+const MAX_PATHS: usize = 3;
+
+// 已删除但仍打开的文件，链接目标带有此后缀
+const DELETED_SUFFIX: &[u8] = b" (deleted)";
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Holder {
+    pub pid: u32,
+    pub command: String,
+    // 目标下被打开或映射的文件数
+    pub files: usize,
+    // 其中的前几个
+    pub paths: Vec<String>,
+}
+
+impl std::fmt::Display for Holder {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "PID {} ({})", self.pid, self.command)
+    }
+}
+
+struct Process {
+    command: String,
+    files: BTreeSet<PathBuf>,
+}
+
+// 一次扫描所有进程的结果，计划中的各个目标共用
+pub struct Snapshot {
+    processes: BTreeMap<u32, Process>,
+}
+
+fn absolute(bytes: &[u8]) -> Option<PathBuf> {
+    if !bytes.starts_with(b"/") || bytes.ends_with(DELETED_SUFFIX) {
+        return None;
+    }
+    Some(PathBuf::from(OsStr::from_bytes(bytes)))
+}
+
+// 套接字、管道、匿名 inode 等不是路径，直接忽略
+fn open_files(proc_dir: &Path, files: &mut BTreeSet<PathBuf>) {
+    for entry in fs::read_dir(proc_dir.join("fd")).into_iter().flatten().flatten() {
+        if let Some(path) = fs::read_link(entry.path()).ok().and_then(|t| absolute(t.as_os_str().as_bytes())) {
+            files.insert(path);
+        }
+    }
+}
+
+// maps 每行为 "地址 权限 偏移 设备 inode 路径"，前五列不含 '/'
+fn mapped_files(proc_dir: &Path, files: &mut BTreeSet<PathBuf>) {
+    let Ok(content) = fs::read(proc_dir.join("maps")) else {
+        return;
+    };
+    for line in content.split(|&b| b == b'\n') {
+        if let Some(path) = line.iter().position(|&b| b == b'/').and_then(|i| absolute(&line[i..])) {
+            files.insert(path);
+        }
+    }
+}
+
+pub fn snapshot() -> Snapshot {
+    let me = std::process::id();
+    let mut processes = BTreeMap::new();
+    for entry in fs::read_dir("/proc").into_iter().flatten().flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|n| n.parse::<u32>().ok()) else {
+            continue;
+        };
+        if pid == me {
+            continue;
+        }
+        let proc_dir = entry.path();
+        let mut files = BTreeSet::new();
+        open_files(&proc_dir, &mut files);
+        mapped_files(&proc_dir, &mut files);
+        if files.is_empty() {
+            continue;
+        }
+        let command = fs::read_to_string(proc_dir.join("comm")).map(|c| c.trim_end().to_string()).unwrap_or_else(|_| "?".into());
+        processes.insert(pid, Process { command, files });
+    }
+    Snapshot { processes }
+}
+
+impl Snapshot {
+    // 打开或映射了 target 本身或其下文件的进程
+    pub fn holders(&self, target: &Path) -> Vec<Holder> {
+        self.processes
+            .iter()
+            .filter_map(|(&pid, p)| {
+                let matched: Vec<&PathBuf> = p.files.iter().filter(|f| f.starts_with(target)).collect();
+                (!matched.is_empty()).then(|| Holder {
+                    pid,
+                    command: p.command.clone(),
+                    files: matched.len(),
+                    paths: matched.iter().take(MAX_PATHS).map(|f| f.display().to_string()).collect(),
+                })
+            })
+            .collect()
+    }
+}
+
+pub fn describe(holders: &[Holder]) -> String {
+    holders.iter().map(|h| h.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch_dir;
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn parses_fd_links_and_maps() {
+        let proc_dir = scratch_dir("busy-proc");
+        fs::create_dir_all(proc_dir.join("fd")).unwrap();
+        std::os::unix::fs::symlink("/data/open file", proc_dir.join("fd/3")).unwrap();
+        std::os::unix::fs::symlink("socket:[12345]", proc_dir.join("fd/4")).unwrap();
+        std::os::unix::fs::symlink("/data/gone (deleted)", proc_dir.join("fd/5")).unwrap();
+        fs::write(proc_dir.join("maps"), concat!(
+            "55d0c000-55d0d000 r--p 00000000 08:01 131 /usr/lib/libc.so.6\n",
+            "7ffd0000-7ffd1000 rw-p 00000000 00:00 0 [stack]\n",
+            "7f000000-7f001000 r--p 00000000 08:01 132 /data/mapped with spaces\n",
+            "7f001000-7f002000 rw-s 00000000 00:01 5 /memfd:x (deleted)\n",
+        )).unwrap();
+
+        let mut files = BTreeSet::new();
+        open_files(&proc_dir, &mut files);
+        mapped_files(&proc_dir, &mut files);
+        let _ = fs::remove_dir_all(&proc_dir);
+        let files: Vec<&str> = files.iter().map(|f| f.to_str().unwrap()).collect();
+        assert_eq!(files, ["/data/mapped with spaces", "/data/open file", "/usr/lib/libc.so.6"]);
+    }
+
+    #[test]
+    fn matches_holders_under_a_target() {
+        let process = |command: &str, files: &[&str]| Process { command: command.into(), files: files.iter().map(PathBuf::from).collect() };
+        let snapshot = Snapshot {
+            processes: BTreeMap::from([
+                (10, process("vim", &["/w/a", "/w/b", "/w/c", "/w/d", "/x"])),
+                (20, process("less", &["/wx/a"])),
+            ]),
+        };
+        let holders = snapshot.holders(Path::new("/w"));
+        assert_eq!(holders.len(), 1);
+        assert_eq!((holders[0].pid, holders[0].files, holders[0].paths.len()), (10, 4, MAX_PATHS));
+        assert_eq!(describe(&snapshot.holders(Path::new("/"))), "PID 10 (vim), PID 20 (less)");
+        assert!(snapshot.holders(Path::new("/w/e")).is_empty());
+    }
+
+    #[test]
+    fn finds_processes_holding_files_open() {
+        let dir = scratch_dir("busy-live");
+        fs::write(dir.join("held"), "x").unwrap();
+        let mut child = Command::new("sleep").arg("30").stdin(fs::File::open(dir.join("held")).unwrap())
+            .stdout(Stdio::null()).spawn().unwrap();
+        let holders = snapshot().holders(&dir);
+        let _ = child.kill();
+        let _ = child.wait();
+        let _ = fs::remove_dir_all(&dir);
+        let holder = holders.iter().find(|h| h.pid == child.id()).unwrap();
+        assert_eq!((holder.command.as_str(), holder.paths.clone()), ("sleep", vec![dir.join("held").display().to_string()]));
+    }
+}
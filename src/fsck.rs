@@ -0,0 +1,422 @@
+// srm fsck：检查并（可选）修复回收站存储的不一致
+//
+// list_all_meta 只会跳过指向缺失回收项的元数据；这里反向检查 trash/ 中没有元数据的孤儿项、
+// atomic_save_meta 崩溃遗留的 *.meta.<PID>-<序号>.tmp、重复短ID以及大小不符等问题。
+
+use crate::{
+    atomic_save_meta, compute_checksum, generate_short_id, log_event,
+    parse_meta, path_usage, remove_meta, save_manifest, secure_create_dir, store_entries, tmp_target, FileMeta, FileType,
+    DEFAULT_EXPIRE_DAYS, META_SCHEMA_VERSION,
+};
+use chrono::{Duration, TimeZone, Utc};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+struct Fsck {
+    repair: bool,
+    quarantine_dir: PathBuf,
+    // 发现的问题类型，按发现顺序
+    issues: Vec<String>,
+    fixed: usize,
+    // 元数据可读且回收项存在的条目数
+    items: usize,
+}
+
+impl Fsck {
+    fn issue(&mut self, kind: &str, detail: &str) {
+        self.issues.push(kind.to_string());
+        println!("⚠️  [{}] {}", kind, detail);
+    }
+
+    fn fixed(&mut self, kind: &str, detail: &str, fix: &str) {
+        self.fixed += 1;
+        println!("   🔧 {}", fix);
+        log_event("WARN", "Fsck repair", Some(json!({
+            "action": "fsck",
+            "issue": kind,
+            "detail": detail,
+            "fix": fix
+        })));
+    }
+
+    fn failed(&self, fix: &str, err: io::Error) {
+        eprintln!("   ❌ {} failed: {}", fix, err);
+    }
+
+    // 无法恢复的条目移入 .srm/quarantine/<子目录>，保留现场供人工处理
+    fn quarantine(&self, path: &Path, sub: &str) -> io::Result<PathBuf> {
+        let dir = self.quarantine_dir.join(sub);
+        secure_create_dir(&dir)?;
+        let name = path.file_name().unwrap_or_default();
+        let mut dest = dir.join(name);
+        let mut counter = 1;
+        while dest.symlink_metadata().is_ok() {
+            dest = dir.join(format!("{}.{}", name.to_string_lossy(), counter));
+            counter += 1;
+        }
+        fs::rename(path, &dest)?;
+        Ok(dest)
+    }
+}
+
+// 回收ID格式为 "{20位纳秒时间戳}-{哈希}"，旧版为 "{原文件名}_{纳秒时间戳}"；
+// 返回 (可用作原路径的名称, 纳秒时间戳)，新格式不含原文件名，以回收ID本身代替
+fn parse_trash_id(trash_id: &str) -> Option<(&str, i64)> {
+    if let Some((ts, hash)) = trash_id.split_once('-') {
+        if ts.len() == 20 && ts.bytes().all(|b| b.is_ascii_digit()) && hash.len() == 8
+            && hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Some((trash_id, ts.parse().ok()?));
+        }
+    }
+    let (name, ts) = trash_id.rsplit_once('_')?;
+    if name.is_empty() || ts.len() < 10 || !ts.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((name, ts.parse().ok()?))
+}
+
+
+// 含分片子目录中的条目
+fn sorted_entries(dir: &Path) -> Vec<PathBuf> {
+    let mut entries = store_entries(dir);
+    entries.sort();
+    entries
+}
+
+pub fn handle_fsck(repair: bool, base: &Path, trash_dir: &Path, meta_dir: &Path) {
+    let ck = check(repair, base, trash_dir, meta_dir);
+    log_event("INFO", "Fsck completed", Some(json!({
+        "action": "fsck",
+        "repair": ck.repair,
+        "issues": ck.issues.len(),
+        "fixed": ck.fixed
+    })));
+
+    if ck.issues.is_empty() {
+        println!("\n✅ Store is consistent ({} item(s))", ck.items);
+    } else if ck.repair {
+        println!("\n✅ Fsck completed ({} issue(s) found, {} fixed)", ck.issues.len(), ck.fixed);
+    } else {
+        println!("\n⚠️  {} issue(s) found. Run `srm fsck --repair` to fix them.", ck.issues.len());
+    }
+
+    if ck.issues.len() > ck.fixed {
+        std::process::exit(1);
+    }
+}
+
+fn check(repair: bool, base: &Path, trash_dir: &Path, meta_dir: &Path) -> Fsck {
+    let mut ck = Fsck {
+        repair,
+        quarantine_dir: base.join("quarantine"),
+        issues: Vec::new(),
+        fixed: 0,
+        items: 0,
+    };
+    let mut metas: BTreeMap<String, FileMeta> = BTreeMap::new();
+    let mut manifests = Vec::new();
+
+    println!("🔍 Checking metadata in {}", meta_dir.display());
+    for path in sorted_entries(meta_dir) {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()).map(str::to_string) else {
+            let detail = format!("non UTF-8 file name in meta/: {}", path.display());
+            ck.issue("unexpected-file", &detail);
+            if ck.repair {
+                match ck.quarantine(&path, "meta") {
+                    Ok(dest) => ck.fixed("unexpected-file", &detail, &format!("quarantined to {}", dest.display())),
+                    Err(e) => ck.failed("quarantine", e),
+                }
+            }
+            continue;
+        };
+
+        let leftover = tmp_target(&name);
+        if let Some(trash_id) = leftover.and_then(|t| t.strip_suffix(".meta")) {
+            let final_path = path.with_file_name(format!("{}.meta", trash_id));
+            let detail = format!("leftover temp metadata: {}", name);
+            ck.issue("stale-tmp", &detail);
+            if !ck.repair {
+                continue;
+            }
+            let promotable = fs::read_to_string(&path)
+                .ok()
+                .and_then(|c| parse_meta(&c).ok())
+                .map(|(meta, _)| meta)
+                .filter(|m| !final_path.exists() && m.trash(trash_dir).symlink_metadata().is_ok());
+            match promotable {
+                Some(meta) => match fs::rename(&path, &final_path) {
+                    Ok(()) => {
+                        ck.fixed("stale-tmp", &detail, &format!("promoted to {}.meta", trash_id));
+                        metas.insert(trash_id.to_string(), meta);
+                    }
+                    Err(e) => ck.failed("promote", e),
+                },
+                None => match fs::remove_file(&path) {
+                    Ok(()) => ck.fixed("stale-tmp", &detail, "removed (superseded or unusable)"),
+                    Err(e) => ck.failed("remove", e),
+                },
+            }
+        } else if leftover.is_some_and(|t| t.ends_with(".manifest")) {
+            let detail = format!("leftover temp manifest: {}", name);
+            ck.issue("stale-tmp", &detail);
+            if ck.repair {
+                match fs::remove_file(&path) {
+                    Ok(()) => ck.fixed("stale-tmp", &detail, "removed"),
+                    Err(e) => ck.failed("remove", e),
+                }
+            }
+        } else if let Some(trash_id) = name.strip_suffix(".manifest") {
+            manifests.push((trash_id.to_string(), path.clone()));
+        } else if let Some(trash_id) = name.strip_suffix(".meta") {
+            let parsed = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|c| parse_meta(&c));
+            let meta = match parsed {
+                Ok((mut meta, mut upgraded)) => {
+                    // 旧版绝对路径改写为相对存储根目录
+                    let trash_path = meta.trash(trash_dir);
+                    if Path::new(&meta.trash_path).is_absolute() {
+                        if let Ok(rel) = trash_path.strip_prefix(base) {
+                            meta.set_trash(rel);
+                            upgraded = true;
+                        }
+                    }
+                    if upgraded && ck.repair && trash_path.symlink_metadata().is_ok() {
+                        let detail = format!("{} uses an older metadata format", name);
+                        ck.issue("old-schema", &detail);
+                        match atomic_save_meta(trash_id, &meta, meta_dir) {
+                            Ok(()) => ck.fixed("old-schema", &detail, &format!("upgraded to schema {} with store-relative paths", META_SCHEMA_VERSION)),
+                            Err(e) => ck.failed("upgrade", e),
+                        }
+                    }
+                    meta
+                }
+                Err(e) => {
+                    let detail = format!("unreadable metadata {}: {}", name, e);
+                    ck.issue("corrupt-meta", &detail);
+                    if ck.repair {
+                        match ck.quarantine(&path, "meta") {
+                            Ok(dest) => ck.fixed("corrupt-meta", &detail, &format!("quarantined to {}", dest.display())),
+                            Err(e) => ck.failed("quarantine", e),
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            if meta.trash(trash_dir).symlink_metadata().is_err() {
+                let detail = format!("{} ({}) points to missing {}", meta.short_id, trash_id, meta.trash_path);
+                ck.issue("dangling-meta", &detail);
+                if ck.repair {
+                    remove_meta(trash_id, meta_dir);
+                    ck.fixed("dangling-meta", &detail, "removed metadata");
+                }
+                continue;
+            }
+            metas.insert(trash_id.to_string(), meta);
+        } else {
+            let detail = format!("unexpected file in meta/: {}", name);
+            ck.issue("unexpected-file", &detail);
+            if ck.repair {
+                match ck.quarantine(&path, "meta") {
+                    Ok(dest) => ck.fixed("unexpected-file", &detail, &format!("quarantined to {}", dest.display())),
+                    Err(e) => ck.failed("quarantine", e),
+                }
+            }
+        }
+    }
+
+    let stale_manifests = manifests
+        .iter()
+        .filter(|(id, path)| !metas.contains_key(id) && path.exists());
+    for (trash_id, path) in stale_manifests {
+        let detail = format!("manifest without metadata: {}.manifest", trash_id);
+        ck.issue("stale-manifest", &detail);
+        if ck.repair {
+            match fs::remove_file(path) {
+                Ok(()) => ck.fixed("stale-manifest", &detail, "removed"),
+                Err(e) => ck.failed("remove", e),
+            }
+        }
+    }
+
+    for (trash_id, meta) in metas.iter_mut() {
+        let trash_path = meta.trash(trash_dir);
+        let usage = match path_usage(&trash_path) {
+            Ok(usage) => usage,
+            Err(e) => {
+                ck.issue("unreadable-item", &format!("{} ({}): {}", meta.short_id, trash_id, e));
+                continue;
+            }
+        };
+        let size = usage.apparent;
+        // 旧元数据没有 allocated_bytes，不算作不一致
+        if size != meta.size_bytes || meta.allocated_bytes.is_some_and(|a| a != usage.allocated) {
+            let detail = format!("{} ({}) records {} bytes ({} on disk), trash holds {} ({} on disk)", meta.short_id, trash_id,
+                                 meta.size_bytes, meta.allocated(), size, usage.allocated);
+            ck.issue("size-mismatch", &detail);
+            if ck.repair {
+                meta.size_bytes = size;
+                meta.allocated_bytes = Some(usage.allocated);
+                match atomic_save_meta(trash_id, meta, meta_dir) {
+                    Ok(()) => ck.fixed("size-mismatch", &detail, "updated recorded size"),
+                    Err(e) => ck.failed("update", e),
+                }
+            }
+        }
+    }
+
+    let mut by_short_id: HashMap<String, Vec<String>> = HashMap::new();
+    for (trash_id, meta) in &metas {
+        by_short_id.entry(meta.short_id.clone()).or_default().push(trash_id.clone());
+    }
+    let mut taken: HashSet<String> = by_short_id.keys().cloned().collect();
+    let mut duplicates: Vec<_> = by_short_id.into_iter().filter(|(id, ids)| ids.len() > 1 || id.is_empty()).collect();
+    duplicates.sort();
+    for (short_id, mut trash_ids) in duplicates {
+        // 删除时间最早的一项保留原短ID，其余重新分配
+        trash_ids.sort_by(|a, b| metas[a].delete_time.cmp(&metas[b].delete_time).then(a.cmp(b)));
+        let keep = if short_id.is_empty() { 0 } else { 1 };
+        for trash_id in trash_ids.into_iter().skip(keep) {
+            let detail = format!("short ID '{}' is also used by {}", short_id, trash_id);
+            ck.issue("duplicate-id", &detail);
+            if ck.repair {
+                let meta = metas.get_mut(&trash_id).unwrap();
+                let new_id = generate_short_id(&trash_id, meta.file_type, &mut taken);
+                meta.short_id = new_id.clone();
+                match atomic_save_meta(&trash_id, meta, meta_dir) {
+                    Ok(()) => ck.fixed("duplicate-id", &detail, &format!("reassigned short ID {}", new_id)),
+                    Err(e) => ck.failed("reassign", e),
+                }
+            }
+        }
+    }
+
+    println!("🔍 Checking trash entries in {}", trash_dir.display());
+    let referenced: HashSet<PathBuf> = metas.values().map(|m| m.trash(trash_dir)).collect();
+    for path in sorted_entries(trash_dir) {
+        if referenced.contains(&path) {
+            continue;
+        }
+        let detail = format!("trash entry without metadata: {}", path.display());
+        ck.issue("orphan", &detail);
+        if !ck.repair {
+            continue;
+        }
+
+        match adopt_orphan(&path, base, meta_dir, &mut taken) {
+            Ok(Some(meta)) => ck.fixed("orphan", &detail, &format!(
+                "adopted as {} (original name '{}'; restore with `srm res -t <dir> {}`)",
+                meta.short_id, meta.original_path, meta.short_id)),
+            Ok(None) => match ck.quarantine(&path, "trash") {
+                Ok(dest) => ck.fixed("orphan", &detail, &format!("unrecognised trash ID, quarantined to {}", dest.display())),
+                Err(e) => ck.failed("quarantine", e),
+            },
+            Err(e) => ck.failed("adopt", e),
+        }
+    }
+
+    ck.items = metas.len();
+    ck
+}
+
+// 根据 "{name}_{nanos}" 形式的回收ID为孤儿项合成元数据；无法识别时返回 None
+fn adopt_orphan(path: &Path, base: &Path, meta_dir: &Path, taken: &mut HashSet<String>) -> io::Result<Option<FileMeta>> {
+    let Some(trash_id) = path.file_name().and_then(|n| n.to_str()) else {
+        return Ok(None);
+    };
+    let Some((name, nanos)) = parse_trash_id(trash_id) else {
+        return Ok(None);
+    };
+
+    let meta = fs::symlink_metadata(path)?;
+    let file_type = FileType::of(&meta);
+    let (checksum, manifest) = compute_checksum(path, file_type)?;
+    if let Some(bytes) = manifest {
+        save_manifest(trash_id, &bytes, meta_dir)?;
+    }
+
+    let usage = path_usage(path)?;
+    let short_id = generate_short_id(trash_id, file_type, taken);
+    let delete_time = Utc.timestamp_nanos(nanos);
+    let mut file_meta = FileMeta {
+        schema_version: META_SCHEMA_VERSION,
+        original_path: name.to_string(),
+        trash_path: String::new(),
+        original_path_b64: None,
+        trash_path_b64: None,
+        delete_time,
+        expire_time: delete_time + Duration::days(DEFAULT_EXPIRE_DAYS),
+        expire_days: DEFAULT_EXPIRE_DAYS,
+        file_type,
+        rdev: FileType::device(&meta),
+        permissions: Some(meta.permissions().mode()),
+        uid: Some(meta.uid()),
+        gid: Some(meta.gid()),
+        short_id,
+        size_bytes: usage.apparent,
+        allocated_bytes: Some(usage.allocated),
+        checksum: Some(checksum),
+        git: None,
+        mounts: Vec::new(),
+        attrs: Vec::new(),
+    };
+    file_meta.set_trash(path.strip_prefix(base).unwrap_or(path));
+    atomic_save_meta(trash_id, &file_meta, meta_dir)?;
+    Ok(Some(file_meta))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta_file;
+
+    #[test]
+    fn parses_trash_ids() {
+        assert_eq!(parse_trash_id("01738238430123456789-1a2b3c4d"), Some(("01738238430123456789-1a2b3c4d", 1738238430123456789)));
+        assert_eq!(parse_trash_id("my_notes.txt_1738238430123456789"), Some(("my_notes.txt", 1738238430123456789)));
+        assert_eq!(parse_trash_id("notes_123"), None);
+        assert_eq!(parse_trash_id("_1738238430123456789"), None);
+        assert_eq!(parse_trash_id("01738238430123456789-xyz"), None);
+    }
+
+    #[test]
+    fn finds_and_repairs_orphans_and_corrupt_metadata() {
+        let base = std::env::temp_dir().join(format!("srm-test-{}-fsck", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let (trash_dir, meta_dir) = (base.join("trash"), base.join("meta"));
+        fs::create_dir_all(&trash_dir).unwrap();
+        fs::create_dir_all(&meta_dir).unwrap();
+        let adoptable = "notes.txt_1738238430123456789";
+        fs::write(trash_dir.join(adoptable), "hi").unwrap();
+        fs::write(trash_dir.join("junk"), "?").unwrap();
+        fs::write(meta_dir.join("broken.meta"), "{ not json").unwrap();
+        fs::write(meta_dir.join("gone.meta.12-3.tmp"), "{}").unwrap();
+
+        let mut issues = check(false, &base, &trash_dir, &meta_dir).issues;
+        issues.sort();
+        assert_eq!(issues, ["corrupt-meta", "orphan", "orphan", "stale-tmp"]);
+        assert!(meta_dir.join("broken.meta").exists() && trash_dir.join("junk").exists());
+
+        let repaired = check(true, &base, &trash_dir, &meta_dir);
+        assert_eq!((repaired.issues.len(), repaired.fixed), (4, 4));
+        assert!(base.join("quarantine/meta/broken.meta").exists());
+        assert!(base.join("quarantine/trash/junk").exists());
+        assert!(!meta_dir.join("gone.meta.12-3.tmp").exists());
+        let (adopted, _) = parse_meta(&fs::read_to_string(meta_file(&meta_dir, adoptable, "meta")).unwrap()).unwrap();
+        assert_eq!((adopted.original_path.as_str(), adopted.size_bytes), ("notes.txt", 2));
+        assert_eq!(adopted.delete_time, Utc.timestamp_nanos(1738238430123456789));
+
+        let clean = check(false, &base, &trash_dir, &meta_dir);
+        assert_eq!((clean.issues.len(), clean.items), (0, 1));
+
+        fs::remove_file(trash_dir.join(adoptable)).unwrap();
+        let dangling = check(false, &base, &trash_dir, &meta_dir).issues;
+        let _ = fs::remove_dir_all(&base);
+        assert_eq!(dangling, ["dangling-meta"]);
+    }
+}
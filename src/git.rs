@@ -0,0 +1,1368 @@
+// Git 工作区检查：删除的目录是或包含 git 工作区时，找出尚未保存到别处的工作
+//
+// 直接读取 .git（不依赖 git 命令）：索引与工作区比较得到修改/删除的文件，索引与 HEAD 比较得到
+// 已暂存未提交的文件，遍历工作区得到未跟踪文件（遵循 .gitignore），读取 stash 记录与各分支相对
+// 上游的提交数。只有 .git 目录本身也在删除范围内时，stash 与分支才会随之丢失，才需要检查。
+
+use crate::{plan, rules};
+use flate2::read::ZlibDecoder;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+// 统计领先提交数时最多遍历的提交数，超过时按下限报告
+const MAX_WALK_COMMITS: usize = 100_000;
+// 摘要中列出的分支数
+const MAX_LISTED_BRANCHES: usize = 5;
+// 标记仓库所在目录的条目名（工作区的 .git、裸仓库的 HEAD）
+pub const NAMES: [&str; 2] = [".git", "HEAD"];
+// 未扫描的目录（整体 rename）只在前几层中查找嵌套的仓库
+pub const UNSCANNED_DEPTH: usize = 4;
+
+type Oid = [u8; 20];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Branch {
+    pub name: String,
+    // 上游分支；没有上游（也没有同名的远程分支）时为 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+    // 领先上游的提交数；没有上游时为该分支独有的提交数
+    pub ahead: usize,
+    // 遍历达到上限，实际数目不少于 ahead
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Repo {
+    // 工作区（裸仓库为仓库目录）
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub head: Option<String>,
+    pub modified: usize,
+    pub staged: usize,
+    pub untracked: usize,
+    pub stashes: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unpushed: Vec<Branch>,
+    // 无法完整读取的部分（按存在未保存工作处理）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    pub repos: Vec<Repo>,
+}
+
+impl Repo {
+    pub fn unsaved(&self) -> bool {
+        self.modified + self.staged + self.untracked + self.stashes > 0 || !self.unpushed.is_empty() || !self.errors.is_empty()
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        for (count, what) in [(self.modified, "modified"), (self.staged, "staged"), (self.untracked, "untracked")] {
+            if count > 0 {
+                parts.push(format!("{} {}", count, what));
+            }
+        }
+        if self.stashes > 0 {
+            parts.push(format!("{} stash{}", self.stashes, if self.stashes == 1 { "" } else { "es" }));
+        }
+        if !self.unpushed.is_empty() {
+            let mut branches: Vec<String> = self.unpushed.iter().take(MAX_LISTED_BRANCHES).map(|b| {
+                let plus = if b.truncated { "+" } else { "" };
+                match &b.upstream {
+                    Some(u) => format!("{} +{}{} vs {}", b.name, b.ahead, plus, u),
+                    None => format!("{} ({}{} commit(s), no upstream)", b.name, b.ahead, plus),
+                }
+            }).collect();
+            if self.unpushed.len() > MAX_LISTED_BRANCHES {
+                branches.push(format!("{} more", self.unpushed.len() - MAX_LISTED_BRANCHES));
+            }
+            parts.push(format!("unpushed: {}", branches.join(", ")));
+        }
+        parts.extend(self.errors.iter().map(|e| format!("unreadable: {}", e)));
+        if parts.is_empty() {
+            parts.push("clean".into());
+        }
+        format!("{}: {}", self.path, parts.join(", "))
+    }
+}
+
+impl Summary {
+    pub fn unsaved(&self) -> bool {
+        self.repos.iter().any(Repo::unsaved)
+    }
+
+    // 只列出有未保存工作的仓库
+    pub fn describe(&self) -> String {
+        self.repos.iter().filter(|r| r.unsaved()).map(Repo::describe).collect::<Vec<_>>().join("; ")
+    }
+}
+
+// ---------- 对象库 ----------
+
+struct Pack {
+    idx: Mmap,
+    data: Mmap,
+    count: usize,
+}
+
+struct Objects {
+    dirs: Vec<PathBuf>,
+    packs: Vec<Pack>,
+    // 第一个损坏对象的错误，由 analyze 报告
+    corrupt: RefCell<Option<String>>,
+}
+
+fn hex(oid: &Oid) -> String {
+    oid.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_hex(text: &str) -> Option<Oid> {
+    let text = text.trim();
+    if text.len() != 40 {
+        return None;
+    }
+    let mut oid = [0u8; 20];
+    for (i, byte) in oid.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(oid)
+}
+
+// 包中声明的大小不可信，预分配设上限
+const MAX_PREALLOC: usize = 1 << 20;
+
+fn inflate(data: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(limit.min(MAX_PREALLOC));
+    ZlibDecoder::new(data).take(limit as u64).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.to_string())
+}
+
+impl Pack {
+    fn open(idx_path: &Path) -> io::Result<Pack> {
+        let idx = unsafe { Mmap::map(&fs::File::open(idx_path)?)? };
+        let data = unsafe { Mmap::map(&fs::File::open(idx_path.with_extension("pack"))?)? };
+        // 只支持第 2 版索引（git 1.5.2 起的默认格式）
+        if idx.len() < 8 + 256 * 4 || idx[..4] != [0xff, b't', b'O', b'c'] || idx[4..8] != [0, 0, 0, 2] {
+            return Err(invalid("unsupported pack index"));
+        }
+        let count = u32::from_be_bytes(idx[8 + 255 * 4..8 + 256 * 4].try_into().unwrap()) as usize;
+        if idx.len() < 8 + 256 * 4 + count * 28 {
+            return Err(invalid("truncated pack index"));
+        }
+        Ok(Pack { idx, data, count })
+    }
+
+    fn offset_of(&self, oid: &Oid) -> Option<u64> {
+        let fanout = |i: usize| u32::from_be_bytes(self.idx[8 + i * 4..12 + i * 4].try_into().unwrap()) as usize;
+        // 损坏的扇出表不能让查找越界
+        let hi = fanout(oid[0] as usize).min(self.count);
+        let lo = if oid[0] == 0 { 0 } else { fanout(oid[0] as usize - 1).min(hi) };
+        let names = 8 + 256 * 4;
+        let (mut lo, mut hi) = (lo, hi);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            match self.idx[names + mid * 20..names + mid * 20 + 20].cmp(oid) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => {
+                    let offsets = names + self.count * 24;
+                    let small = u32::from_be_bytes(self.idx[offsets + mid * 4..offsets + mid * 4 + 4].try_into().unwrap());
+                    if small & 0x8000_0000 == 0 {
+                        return Some(small as u64);
+                    }
+                    let large = offsets + self.count * 4 + (small & 0x7fff_ffff) as usize * 8;
+                    return self.idx.get(large..large + 8).map(|b| u64::from_be_bytes(b.try_into().unwrap()));
+                }
+            }
+        }
+        None
+    }
+
+    // 读取偏移处的对象，沿增量链展开；返回 (类型, 内容)
+    fn read_at(&self, objects: &Objects, offset: u64, depth: usize) -> io::Result<(u8, Vec<u8>)> {
+        if depth > 64 {
+            return Err(invalid("delta chain too deep"));
+        }
+        let data = &self.data[..];
+        let mut pos = offset as usize;
+        let byte = *data.get(pos).ok_or_else(|| invalid("bad pack offset"))?;
+        let kind = (byte >> 4) & 7;
+        let mut size = (byte & 0x0f) as usize;
+        let mut shift = 4;
+        let mut more = byte & 0x80 != 0;
+        pos += 1;
+        while more {
+            let b = *data.get(pos).ok_or_else(|| invalid("truncated pack"))?;
+            if shift >= usize::BITS {
+                return Err(invalid("bad pack object size"));
+            }
+            size |= ((b & 0x7f) as usize) << shift;
+            shift += 7;
+            more = b & 0x80 != 0;
+            pos += 1;
+        }
+        match kind {
+            1..=4 => Ok((kind, inflate(&data[pos..], size)?)),
+            6 => {
+                let mut b = *data.get(pos).ok_or_else(|| invalid("truncated pack"))?;
+                pos += 1;
+                let mut back = (b & 0x7f) as u64;
+                while b & 0x80 != 0 {
+                    b = *data.get(pos).ok_or_else(|| invalid("truncated pack"))?;
+                    pos += 1;
+                    // 基址不可能超过当前偏移，超出即为损坏
+                    if back >= offset {
+                        return Err(invalid("bad delta base"));
+                    }
+                    back = ((back + 1) << 7) | (b & 0x7f) as u64;
+                }
+                let (base_kind, base) = self.read_at(objects, offset.checked_sub(back).ok_or_else(|| invalid("bad delta base"))?, depth + 1)?;
+                Ok((base_kind, apply_delta(&base, &inflate(&data[pos..], size)?)?))
+            }
+            7 => {
+                let base_oid: Oid = data.get(pos..pos + 20).ok_or_else(|| invalid("truncated pack"))?.try_into().unwrap();
+                let (base_kind, base) = objects.read_depth(&base_oid, depth + 1).ok_or_else(|| invalid("missing delta base"))?;
+                Ok((base_kind, apply_delta(&base, &inflate(&data[pos + 20..], size)?)?))
+            }
+            _ => Err(invalid("unknown pack object type")),
+        }
+    }
+}
+
+fn apply_delta(base: &[u8], delta: &[u8]) -> io::Result<Vec<u8>> {
+    let mut pos = 0;
+    let mut varint = || {
+        let mut value = 0usize;
+        let mut shift = 0;
+        loop {
+            let b = *delta.get(pos)?;
+            pos += 1;
+            if shift >= usize::BITS {
+                return None;
+            }
+            value |= ((b & 0x7f) as usize) << shift;
+            shift += 7;
+            if b & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+    };
+    let _base_size = varint().ok_or_else(|| invalid("bad delta"))?;
+    let result_size = varint().ok_or_else(|| invalid("bad delta"))?;
+    let mut out = Vec::with_capacity(result_size.min(MAX_PREALLOC));
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        if op & 0x80 != 0 {
+            let mut field = |bits: std::ops::Range<u8>| {
+                let mut value = 0usize;
+                for (i, bit) in bits.enumerate() {
+                    if op & (1 << bit) != 0 {
+                        value |= (*delta.get(pos)? as usize) << (8 * i);
+                        pos += 1;
+                    }
+                }
+                Some(value)
+            };
+            let offset = field(0..4).ok_or_else(|| invalid("bad delta copy"))?;
+            let size = match field(4..7).ok_or_else(|| invalid("bad delta copy"))? {
+                0 => 0x10000,
+                n => n,
+            };
+            let end = offset.checked_add(size).ok_or_else(|| invalid("bad delta copy"))?;
+            out.extend_from_slice(base.get(offset..end).ok_or_else(|| invalid("bad delta copy"))?);
+        } else if op != 0 {
+            out.extend_from_slice(delta.get(pos..pos + op as usize).ok_or_else(|| invalid("bad delta insert"))?);
+            pos += op as usize;
+        } else {
+            return Err(invalid("bad delta opcode"));
+        }
+        if out.len() > result_size {
+            return Err(invalid("bad delta size"));
+        }
+    }
+    if out.len() != result_size {
+        return Err(invalid("bad delta size"));
+    }
+    Ok(out)
+}
+
+impl Objects {
+    fn open(common: &Path) -> Objects {
+        let dir = common.join("objects");
+        let mut dirs = vec![dir.clone()];
+        // 借用其他对象库（git clone --shared / --reference）
+        if let Ok(alternates) = fs::read_to_string(dir.join("info/alternates")) {
+            dirs.extend(alternates.lines().filter(|l| !l.is_empty() && !l.starts_with('#')).map(|l| dir.join(l)));
+        }
+        let mut packs = Vec::new();
+        for d in &dirs {
+            if let Ok(entries) = fs::read_dir(d.join("pack")) {
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    if path.extension() == Some(OsStr::new("idx")) {
+                        if let Ok(pack) = Pack::open(&path) {
+                            packs.push(pack);
+                        }
+                    }
+                }
+            }
+        }
+        Objects { dirs, packs, corrupt: RefCell::new(None) }
+    }
+
+    fn read(&self, oid: &Oid) -> Option<(u8, Vec<u8>)> {
+        self.read_depth(oid, 0)
+    }
+
+    // depth 沿 REF_DELTA 链传递，防止自引用的基址无限递归
+    fn read_depth(&self, oid: &Oid, depth: usize) -> Option<(u8, Vec<u8>)> {
+        let name = hex(oid);
+        for dir in &self.dirs {
+            if let Ok(raw) = fs::read(dir.join(&name[..2]).join(&name[2..])) {
+                let mut content = Vec::new();
+                ZlibDecoder::new(&raw[..]).read_to_end(&mut content).ok()?;
+                let nul = content.iter().position(|&b| b == 0)?;
+                let kind = match content.split(|&b| b == b' ').next()? {
+                    b"commit" => 1,
+                    b"tree" => 2,
+                    b"blob" => 3,
+                    b"tag" => 4,
+                    _ => return None,
+                };
+                return Some((kind, content[nul + 1..].to_vec()));
+            }
+        }
+        let (pack, offset) = self.packs.iter().find_map(|p| Some((p, p.offset_of(oid)?)))?;
+        match pack.read_at(self, offset, depth) {
+            Ok(object) => Some(object),
+            Err(e) => {
+                self.corrupt.borrow_mut().get_or_insert_with(|| format!("object {}: {}", hex(oid), e));
+                None
+            }
+        }
+    }
+
+    fn parents(&self, oid: &Oid) -> Option<Vec<Oid>> {
+        let (kind, body) = self.read(oid)?;
+        if kind != 1 {
+            return None;
+        }
+        let text = String::from_utf8_lossy(&body);
+        Some(text.lines().take_while(|l| !l.is_empty()).filter_map(|l| l.strip_prefix("parent ").and_then(parse_hex)).collect())
+    }
+
+    fn commit_tree(&self, oid: &Oid) -> Option<Oid> {
+        let (kind, body) = self.read(oid)?;
+        if kind != 1 {
+            return None;
+        }
+        String::from_utf8_lossy(&body).lines().next()?.strip_prefix("tree ").and_then(parse_hex)
+    }
+
+    // 展开树对象：路径 -> 对象 ID（子模块记录为提交 ID）
+    fn flatten_tree(&self, tree: &Oid, prefix: &[u8], out: &mut HashMap<Vec<u8>, Oid>) -> Option<()> {
+        let (kind, body) = self.read(tree)?;
+        if kind != 2 {
+            return None;
+        }
+        let mut rest = &body[..];
+        while !rest.is_empty() {
+            let space = rest.iter().position(|&b| b == b' ')?;
+            let nul = rest.iter().position(|&b| b == 0)?;
+            let mode = &rest[..space];
+            let name = &rest[space + 1..nul];
+            let oid: Oid = rest.get(nul + 1..nul + 21)?.try_into().ok()?;
+            rest = &rest[nul + 21..];
+            let mut path = prefix.to_vec();
+            if !path.is_empty() {
+                path.push(b'/');
+            }
+            path.extend_from_slice(name);
+            if mode == b"40000" {
+                self.flatten_tree(&oid, &path, out)?;
+            } else {
+                out.insert(path, oid);
+            }
+        }
+        Some(())
+    }
+}
+
+// ---------- 引用 ----------
+
+struct Refs {
+    // 完整引用名 -> 提交
+    refs: HashMap<String, Oid>,
+}
+
+impl Refs {
+    fn load(common: &Path) -> Refs {
+        let mut refs = HashMap::new();
+        if let Ok(packed) = fs::read_to_string(common.join("packed-refs")) {
+            for line in packed.lines().filter(|l| !l.starts_with('#') && !l.starts_with('^')) {
+                if let Some((oid, name)) = line.split_once(' ').and_then(|(o, n)| Some((parse_hex(o)?, n))) {
+                    refs.insert(name.to_string(), oid);
+                }
+            }
+        }
+        // 松散引用覆盖打包引用
+        let mut stack = vec![PathBuf::from("refs")];
+        while let Some(rel) = stack.pop() {
+            let Ok(entries) = fs::read_dir(common.join(&rel)) else { continue };
+            for entry in entries.flatten() {
+                let rel = rel.join(entry.file_name());
+                if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    stack.push(rel);
+                } else if let Some(oid) = fs::read_to_string(entry.path()).ok().and_then(|c| parse_hex(&c)) {
+                    refs.insert(rel.to_string_lossy().into_owned(), oid);
+                }
+            }
+        }
+        Refs { refs }
+    }
+
+    fn get(&self, name: &str) -> Option<Oid> {
+        self.refs.get(name).copied()
+    }
+
+    fn with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, Oid)> {
+        self.refs.iter().filter_map(move |(name, oid)| name.strip_prefix(prefix).map(|n| (n, *oid)))
+    }
+}
+
+// .git/config 中的 [branch "x"] remote/merge 与已配置的远程
+struct Config {
+    branches: HashMap<String, (String, String)>,
+    remotes: Vec<String>,
+}
+
+fn read_config(common: &Path) -> Config {
+    let mut config = Config { branches: HashMap::new(), remotes: Vec::new() };
+    let content = fs::read_to_string(common.join("config")).unwrap_or_default();
+    let mut section: Option<(String, String)> = None;
+    let mut partial: HashMap<String, (Option<String>, Option<String>)> = HashMap::new();
+    for line in content.lines().map(str::trim) {
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = header.split_once(' ').map(|(kind, name)| (kind.to_ascii_lowercase(), name.trim().trim_matches('"').to_string()));
+            if let Some(("remote", name)) = section.as_ref().map(|(k, n)| (k.as_str(), n.as_str())) {
+                config.remotes.push(name.to_string());
+            }
+            continue;
+        }
+        let Some(("branch", branch)) = section.as_ref().map(|(k, n)| (k.as_str(), n.as_str())) else { continue };
+        if let Some((key, value)) = line.split_once('=') {
+            let entry = partial.entry(branch.to_string()).or_default();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "remote" => entry.0 = Some(value.trim().to_string()),
+                "merge" => entry.1 = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+    }
+    for (branch, (remote, merge)) in partial {
+        if let (Some(remote), Some(merge)) = (remote, merge) {
+            config.branches.insert(branch, (remote, merge));
+        }
+    }
+    config
+}
+
+// 从 tips 可达的全部提交（有上限）
+fn ancestry(objects: &Objects, tips: impl IntoIterator<Item = Oid>) -> HashSet<Oid> {
+    let mut seen = HashSet::new();
+    let mut queue: VecDeque<Oid> = tips.into_iter().collect();
+    while let Some(oid) = queue.pop_front() {
+        if seen.len() >= MAX_WALK_COMMITS {
+            break;
+        }
+        if seen.insert(oid) {
+            queue.extend(objects.parents(&oid).unwrap_or_default());
+        }
+    }
+    seen
+}
+
+// 从 tip 可达、不在 excluded 中的提交数；第二项表示达到遍历上限
+fn count_ahead(objects: &Objects, tip: Oid, excluded: &HashSet<Oid>) -> (usize, bool) {
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([tip]);
+    while let Some(oid) = queue.pop_front() {
+        if excluded.contains(&oid) || !seen.insert(oid) {
+            continue;
+        }
+        if seen.len() >= MAX_WALK_COMMITS {
+            return (seen.len(), true);
+        }
+        queue.extend(objects.parents(&oid).unwrap_or_default());
+    }
+    (seen.len(), false)
+}
+
+fn unpushed_branches(common: &Path, objects: &Objects, refs: &Refs) -> Vec<Branch> {
+    let config = read_config(common);
+    let mut on_remotes: Option<HashSet<Oid>> = None;
+    let mut branches = Vec::new();
+    let mut locals: Vec<(&str, Oid)> = refs.with_prefix("refs/heads/").collect();
+    locals.sort();
+    for (name, tip) in locals {
+        // 配置的上游；没有时退回同名的远程跟踪分支
+        let upstream = match config.branches.get(name) {
+            Some((remote, merge)) if remote == "." => Some(merge.clone()),
+            Some((remote, merge)) => Some(format!("refs/remotes/{}/{}", remote, merge.strip_prefix("refs/heads/").unwrap_or(merge))),
+            None => config.remotes.iter().map(|r| format!("refs/remotes/{}/{}", r, name)).find(|r| refs.get(r).is_some()),
+        };
+        let upstream_tip = upstream.as_deref().and_then(|u| refs.get(u));
+        if upstream_tip == Some(tip) {
+            continue;
+        }
+        // 没有上游时只统计尚未出现在任何远程跟踪分支上的提交
+        let (ahead, truncated) = match upstream_tip {
+            Some(base) => count_ahead(objects, tip, &ancestry(objects, [base])),
+            None => {
+                let excluded = on_remotes.get_or_insert_with(|| ancestry(objects, refs.with_prefix("refs/remotes/").map(|(_, oid)| oid)));
+                count_ahead(objects, tip, excluded)
+            }
+        };
+        if ahead > 0 {
+            let upstream = upstream_tip.and(upstream).map(|u| u.trim_start_matches("refs/remotes/").trim_start_matches("refs/heads/").to_string());
+            branches.push(Branch { name: name.to_string(), upstream, ahead, truncated });
+        }
+    }
+    branches
+}
+
+fn count_stashes(common: &Path, refs: &Refs) -> usize {
+    match fs::read_to_string(common.join("logs/refs/stash")) {
+        Ok(log) => log.lines().filter(|l| !l.is_empty()).count(),
+        Err(_) => usize::from(refs.get("refs/stash").is_some()),
+    }
+}
+
+// ---------- 索引 ----------
+
+struct IndexEntry {
+    path: Vec<u8>,
+    oid: Oid,
+    mode: u32,
+    size: u32,
+    mtime: (u32, u32),
+    stage: u16,
+    // assume-unchanged / skip-worktree：git 不检查工作区中的这些文件
+    skip: bool,
+}
+
+fn read_index(gitdir: &Path) -> io::Result<Vec<IndexEntry>> {
+    let data = match fs::read(gitdir.join("index")) {
+        Ok(d) => d,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    if data.len() < 12 || &data[..4] != b"DIRC" {
+        return Err(invalid("not a git index"));
+    }
+    let version = u32::from_be_bytes(data[4..8].try_into().unwrap());
+    if !(2..=4).contains(&version) {
+        return Err(invalid("unsupported index version"));
+    }
+    let count = u32::from_be_bytes(data[8..12].try_into().unwrap()) as usize;
+    let be32 = |pos: usize| data.get(pos..pos + 4).map(|b| u32::from_be_bytes(b.try_into().unwrap()));
+    let mut entries = Vec::with_capacity(count);
+    let mut pos = 12;
+    let mut previous: Vec<u8> = Vec::new();
+    for _ in 0..count {
+        let start = pos;
+        let truncated = || invalid("truncated git index");
+        let mtime = (be32(pos + 8).ok_or_else(truncated)?, be32(pos + 12).ok_or_else(truncated)?);
+        let mode = be32(pos + 24).ok_or_else(truncated)?;
+        let size = be32(pos + 36).ok_or_else(truncated)?;
+        let oid: Oid = data.get(pos + 40..pos + 60).ok_or_else(truncated)?.try_into().unwrap();
+        let flags = u16::from_be_bytes(data.get(pos + 60..pos + 62).ok_or_else(truncated)?.try_into().unwrap());
+        pos += 62;
+        let mut skip = flags & 0x8000 != 0;
+        if flags & 0x4000 != 0 && version >= 3 {
+            let extended = u16::from_be_bytes(data.get(pos..pos + 2).ok_or_else(truncated)?.try_into().unwrap());
+            skip |= extended & 0x4000 != 0;
+            pos += 2;
+        }
+        let path = if version == 4 {
+            // 路径前缀压缩：先去掉上一条路径末尾的若干字节，再接上本条的后缀
+            let mut strip = 0usize;
+            loop {
+                let b = *data.get(pos).ok_or_else(truncated)?;
+                pos += 1;
+                strip = (strip << 7) | (b & 0x7f) as usize;
+                if b & 0x80 == 0 {
+                    break;
+                }
+                strip += 1;
+            }
+            let nul = data[pos..].iter().position(|&b| b == 0).ok_or_else(truncated)?;
+            let mut path = previous[..previous.len().saturating_sub(strip)].to_vec();
+            path.extend_from_slice(&data[pos..pos + nul]);
+            pos += nul + 1;
+            path
+        } else {
+            let nul = data[pos..].iter().position(|&b| b == 0).ok_or_else(truncated)?;
+            let path = data[pos..pos + nul].to_vec();
+            // 条目按 8 字节对齐，至少有一个 NUL
+            pos = start + (pos + nul - start + 8) / 8 * 8;
+            path
+        };
+        previous = path.clone();
+        entries.push(IndexEntry { path, oid, mode, size, mtime, stage: (flags >> 12) & 3, skip });
+    }
+    Ok(entries)
+}
+
+fn blob_id(content: &[u8]) -> Oid {
+    let mut hasher = sha1_smol::Sha1::new();
+    hasher.update(format!("blob {}\0", content.len()).as_bytes());
+    hasher.update(content);
+    hasher.digest().bytes()
+}
+
+// 同 blob_id，分块读取文件，不把整个文件读入内存；读到的长度与 len 不符时按不一致处理
+fn file_blob_id(path: &Path, len: u64) -> io::Result<Oid> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = sha1_smol::Sha1::new();
+    hasher.update(format!("blob {}\0", len).as_bytes());
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut total = 0u64;
+    loop {
+        match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                hasher.update(&buf[..n]);
+                total += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    if total != len {
+        return Err(invalid("file changed while reading"));
+    }
+    Ok(hasher.digest().bytes())
+}
+
+// 工作区中的文件与索引不一致（含被删除、类型或可执行位变化）
+fn worktree_differs(worktree: &Path, entry: &IndexEntry) -> bool {
+    let path = worktree.join(OsStr::from_bytes(&entry.path));
+    let Ok(meta) = fs::symlink_metadata(&path) else { return true };
+    let kind = entry.mode >> 12;
+    let is_link = meta.file_type().is_symlink();
+    if (kind == 0o12) != is_link || (kind == 0o10 && !meta.is_file()) {
+        return true;
+    }
+    if kind == 0o10 && ((entry.mode & 0o100 != 0) != (meta.permissions().mode() & 0o100 != 0)) {
+        return true;
+    }
+    if meta.len() as u32 != entry.size {
+        return true;
+    }
+    if (meta.mtime() as u32, meta.mtime_nsec() as u32) == entry.mtime {
+        return false;
+    }
+    // 时间戳变化不代表内容变化，按 git 的方式计算对象 ID 比较
+    let oid = if is_link { fs::read_link(&path).map(|t| blob_id(t.as_os_str().as_bytes())) } else { file_blob_id(&path, meta.len()) };
+    oid.map_or(true, |oid| oid != entry.oid)
+}
+
+// ---------- .gitignore ----------
+
+struct IgnorePattern {
+    // 模式所在目录（相对工作区）
+    base: Vec<String>,
+    // 含 `/` 的模式相对 base 锚定，按组件匹配；否则只匹配最后一个组件
+    parts: Vec<String>,
+    anchored: bool,
+    negated: bool,
+    dir_only: bool,
+}
+
+fn parse_ignore(content: &str, base: &[String]) -> Vec<IgnorePattern> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_end_matches([' ', '\r']);
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            let parts = line.trim_start_matches('/').split('/').filter(|p| !p.is_empty()).map(String::from).collect();
+            Some(IgnorePattern { base: base.to_vec(), parts, anchored, negated, dir_only })
+        })
+        .collect()
+}
+
+impl IgnorePattern {
+    fn matches(&self, rel: &[String], is_dir: bool) -> bool {
+        if (self.dir_only && !is_dir) || !rel.starts_with(&self.base) {
+            return false;
+        }
+        let rel = &rel[self.base.len()..];
+        if self.anchored {
+            rules::match_glob(&self.parts, rel)
+        } else {
+            let pattern: Vec<char> = self.parts.first().map(|p| p.chars().collect()).unwrap_or_default();
+            rel.last().is_some_and(|name| rules::match_component(&pattern, &name.chars().collect::<Vec<_>>()))
+        }
+    }
+}
+
+fn ignored(patterns: &[IgnorePattern], rel: &[String], is_dir: bool) -> bool {
+    // 后出现的模式优先
+    patterns.iter().rev().find(|p| p.matches(rel, is_dir)).is_some_and(|p| !p.negated)
+}
+
+// 工作区中既不在索引中、也未被忽略的文件数；被忽略的目录与嵌套仓库不进入
+fn count_untracked(worktree: &Path, scope: &Path, gitdir: &Path, tracked: &HashSet<&[u8]>) -> usize {
+    let mut patterns = Vec::new();
+    if let Some(home) = std::env::var_os("HOME") {
+        let global = std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from).unwrap_or_else(|| Path::new(&home).join(".config"));
+        patterns.extend(parse_ignore(&fs::read_to_string(global.join("git/ignore")).unwrap_or_default(), &[]));
+    }
+    patterns.extend(parse_ignore(&fs::read_to_string(gitdir.join("info/exclude")).unwrap_or_default(), &[]));
+
+    // 先加载工作区根到 scope 之间各级目录的 .gitignore
+    let scope_rel: Vec<String> = scope.strip_prefix(worktree).map(|r| {
+        r.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect()
+    }).unwrap_or_default();
+    for depth in 0..scope_rel.len() {
+        let dir = worktree.join(scope_rel[..depth].iter().collect::<PathBuf>());
+        patterns.extend(parse_ignore(&fs::read_to_string(dir.join(".gitignore")).unwrap_or_default(), &scope_rel[..depth]));
+    }
+
+    // 各目录的模式带有所在目录，只作用于该目录之下，可以一直累积；深层目录的模式在后，优先级更高
+    let mut count = 0;
+    let mut stack = vec![scope_rel];
+    while let Some(rel) = stack.pop() {
+        let dir = worktree.join(rel.iter().collect::<PathBuf>());
+        patterns.extend(parse_ignore(&fs::read_to_string(dir.join(".gitignore")).unwrap_or_default(), &rel));
+        let Ok(entries) = fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            if name == ".git" {
+                continue;
+            }
+            let mut child = rel.clone();
+            child.push(name.to_string_lossy().into_owned());
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            if ignored(&patterns, &child, is_dir) {
+                continue;
+            }
+            if is_dir {
+                if entry.path().join(".git").symlink_metadata().is_err() {
+                    stack.push(child);
+                }
+            } else if !tracked.contains(child.join("/").as_bytes()) {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+// ---------- 仓库 ----------
+
+struct Location {
+    // 工作区；裸仓库为 None
+    worktree: Option<PathBuf>,
+    gitdir: PathBuf,
+    // 只统计该路径之下的工作区文件
+    scope: PathBuf,
+    // .git 目录（分支、stash）也会被删除
+    owns_gitdir: bool,
+}
+
+// .git 可以是目录，也可以是 `gitdir: <路径>` 文件（git worktree、子模块）
+fn gitdir_of(dir: &Path) -> Option<(PathBuf, bool)> {
+    let dotgit = dir.join(".git");
+    let meta = fs::symlink_metadata(&dotgit).ok()?;
+    if meta.is_dir() {
+        return Some((dotgit, true));
+    }
+    let content = fs::read_to_string(&dotgit).ok()?;
+    let target = content.trim().strip_prefix("gitdir:")?.trim();
+    // 相对路径常以 `..` 开头，规范化后才能判断它是否位于被删除的目录之内
+    Some((plan::lexical(&dir.join(target)), false))
+}
+
+fn is_bare_repo(dir: &Path) -> bool {
+    dir.join("HEAD").is_file() && dir.join("objects").is_dir() && dir.join("refs").is_dir()
+}
+
+// nested 为目标之下名为 .git 或 HEAD 的条目（相对目标的路径，见 NAMES）；目标不是目录时为 None
+fn locate(target: &Path, nested: Option<&[PathBuf]>) -> Vec<Location> {
+    let is_dir = nested.is_some();
+    let mut found = Vec::new();
+    // 目标位于某个工作区之内（本身不是仓库）：只有目标之下的改动会丢失
+    let is_repo = is_dir && (gitdir_of(target).is_some() || is_bare_repo(target));
+    let enclosing = if is_repo { None } else { target.ancestors().skip(1).find(|d| d.join(".git").symlink_metadata().is_ok()) };
+    if let Some(worktree) = enclosing {
+        if let Some((gitdir, _)) = gitdir_of(worktree) {
+            found.push(Location { worktree: Some(worktree.to_path_buf()), gitdir, scope: target.to_path_buf(), owns_gitdir: false });
+        }
+    }
+    let Some(nested) = nested else {
+        return found;
+    };
+    // 只检查含有这些条目的目录；按路径排序后上级目录在前，裸仓库内部不再检查
+    let mut dirs: Vec<&Path> = nested.iter().filter_map(|p| p.parent()).collect();
+    dirs.sort();
+    dirs.dedup();
+    let mut bare: Vec<&Path> = Vec::new();
+    for rel in dirs {
+        if bare.iter().any(|b| rel.starts_with(b)) {
+            continue;
+        }
+        let dir = if rel.as_os_str().is_empty() { target.to_path_buf() } else { target.join(rel) };
+        if is_bare_repo(&dir) {
+            bare.push(rel);
+            found.push(Location { worktree: None, gitdir: dir.clone(), scope: dir, owns_gitdir: true });
+        } else if let Some((gitdir, owned)) = gitdir_of(&dir) {
+            let owns_gitdir = owned || gitdir.starts_with(target);
+            found.push(Location { worktree: Some(dir.clone()), gitdir, scope: dir, owns_gitdir });
+        }
+    }
+    found
+}
+
+fn analyze(location: &Location) -> Repo {
+    let shown = location.worktree.as_deref().unwrap_or(&location.gitdir);
+    let mut repo = Repo { path: shown.display().to_string(), ..Default::default() };
+    // git worktree 的 gitdir 中有 commondir 文件指向共享的仓库目录
+    let common = match fs::read_to_string(location.gitdir.join("commondir")) {
+        Ok(c) => location.gitdir.join(c.trim()),
+        Err(_) => location.gitdir.clone(),
+    };
+    if fs::read_to_string(common.join("config")).is_ok_and(|c| c.contains("objectformat")) {
+        repo.errors.push("unsupported object format".into());
+        return repo;
+    }
+    let objects = Objects::open(&common);
+    let refs = Refs::load(&common);
+
+    let head = fs::read_to_string(location.gitdir.join("HEAD")).unwrap_or_default();
+    let head_oid = match head.trim().strip_prefix("ref:") {
+        Some(name) => {
+            let name = name.trim();
+            repo.head = Some(name.trim_start_matches("refs/heads/").to_string());
+            refs.get(name)
+        }
+        None => {
+            let oid = parse_hex(&head);
+            repo.head = oid.map(|o| format!("detached at {}", &hex(&o)[..8]));
+            oid
+        }
+    };
+
+    if let Some(worktree) = &location.worktree {
+        match read_index(&location.gitdir) {
+            Ok(index) => {
+                let scope: Vec<u8> = location.scope.strip_prefix(worktree).map(|r| r.as_os_str().as_bytes().to_vec()).unwrap_or_default();
+                let in_scope = |path: &[u8]| scope.is_empty() || path == &scope[..] || (path.starts_with(&scope) && path.get(scope.len()) == Some(&b'/'));
+                let mut head_files = HashMap::new();
+                let head_ok = match head_oid {
+                    Some(oid) => objects.commit_tree(&oid).and_then(|t| objects.flatten_tree(&t, b"", &mut head_files)).is_some(),
+                    // 还没有提交：索引中的一切都是已暂存
+                    None => true,
+                };
+                if !head_ok {
+                    repo.errors.push("HEAD commit not readable".into());
+                }
+                let mut indexed = HashSet::new();
+                for entry in index.iter().filter(|e| in_scope(&e.path)) {
+                    indexed.insert(&entry.path[..]);
+                    if entry.stage != 0 {
+                        // 未解决的合并冲突，每个路径只计一次
+                        if entry.stage == 2 {
+                            repo.modified += 1;
+                        }
+                        continue;
+                    }
+                    if head_ok && head_files.get(&entry.path) != Some(&entry.oid) {
+                        repo.staged += 1;
+                    }
+                    // 子模块由它自己的仓库检查
+                    if entry.mode >> 12 != 0o16 && !entry.skip && worktree_differs(worktree, entry) {
+                        repo.modified += 1;
+                    }
+                }
+                if head_ok {
+                    repo.staged += head_files.keys().filter(|p| in_scope(p.as_slice()) && !indexed.contains(p.as_slice())).count();
+                }
+                repo.untracked = count_untracked(worktree, &location.scope, &location.gitdir, &indexed);
+            }
+            Err(e) => repo.errors.push(format!("index: {}", e)),
+        }
+    }
+
+    if location.owns_gitdir {
+        repo.stashes = count_stashes(&common, &refs);
+        repo.unpushed = unpushed_branches(&common, &objects, &refs);
+    }
+    if let Some(e) = objects.corrupt.take() {
+        repo.errors.push(e);
+    }
+    repo
+}
+
+// 目标是或包含 git 工作区（或位于工作区之内）时返回摘要；nested 同 locate
+pub fn inspect(target: &Path, nested: Option<&[PathBuf]>) -> Option<Summary> {
+    let locations = locate(target, nested);
+    if locations.is_empty() {
+        return None;
+    }
+    Some(Summary { repos: locations.iter().map(analyze).collect() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 按 git 的格式构造一条索引条目：固定字段 + 路径（路径部分由调用方按版本编码）
+    fn entry(mode: u32, size: u32, mtime: (u32, u32), fill: u8, flags: u16) -> Vec<u8> {
+        let mut e = Vec::new();
+        for field in [0, 0, mtime.0, mtime.1, 0, 0, mode, 0, 0, size] {
+            e.extend_from_slice(&u32::to_be_bytes(field));
+        }
+        e.extend_from_slice(&[fill; 20]);
+        e.extend_from_slice(&flags.to_be_bytes());
+        e
+    }
+
+    fn index(version: u32, entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = b"DIRC".to_vec();
+        data.extend_from_slice(&version.to_be_bytes());
+        data.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        for e in entries {
+            data.extend_from_slice(e);
+        }
+        // 校验和与扩展不影响解析
+        data.extend_from_slice(&[0; 20]);
+        data
+    }
+
+    // v2/v3：路径后补 NUL 使整条长度为 8 的倍数（至少一个 NUL）
+    fn padded(mut e: Vec<u8>, path: &str) -> Vec<u8> {
+        e.extend_from_slice(path.as_bytes());
+        let len = (e.len() + 8) / 8 * 8;
+        e.resize(len, 0);
+        e
+    }
+
+    // v4：去掉上一条路径末尾 strip 个字节后接上 suffix
+    fn compressed(mut e: Vec<u8>, strip: usize, suffix: &str) -> Vec<u8> {
+        let mut varint = vec![(strip & 0x7f) as u8];
+        let mut rest = strip >> 7;
+        while rest > 0 {
+            rest -= 1;
+            varint.insert(0, 0x80 | (rest & 0x7f) as u8);
+            rest >>= 7;
+        }
+        e.extend_from_slice(&varint);
+        e.extend_from_slice(suffix.as_bytes());
+        e.push(0);
+        e
+    }
+
+    fn read(name: &str, data: &[u8]) -> io::Result<Vec<IndexEntry>> {
+        let gitdir = std::env::temp_dir().join(format!("srm-test-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&gitdir)?;
+        fs::write(gitdir.join("index"), data)?;
+        let result = read_index(&gitdir);
+        let _ = fs::remove_dir_all(&gitdir);
+        result
+    }
+
+    fn paths(entries: &[IndexEntry]) -> Vec<String> {
+        entries.iter().map(|e| String::from_utf8_lossy(&e.path).into_owned()).collect()
+    }
+
+    #[test]
+    fn reads_v2_index() {
+        let data = index(2, &[
+            padded(entry(0o100644, 3, (1_700_000_000, 5), 0x11, 5), "a.txt"),
+            padded(entry(0o100755, 10, (1, 2), 0x22, 0x8000 | (2 << 12) | 9), "dir/b.sh"),
+            padded(entry(0o120000, 6, (3, 4), 0x33, 1), "c"),
+        ]);
+        let entries = read("v2", &data).unwrap();
+        assert_eq!(paths(&entries), ["a.txt", "dir/b.sh", "c"]);
+
+        let a = &entries[0];
+        assert_eq!((a.mode, a.size, a.mtime, a.stage, a.skip), (0o100644, 3, (1_700_000_000, 5), 0, false));
+        assert_eq!(a.oid, [0x11; 20]);
+        let b = &entries[1];
+        assert_eq!((b.mode, b.stage, b.skip), (0o100755, 2, true));
+        assert_eq!(entries[2].oid, [0x33; 20]);
+    }
+
+    #[test]
+    fn reads_v3_extended_flags() {
+        let mut skip_worktree = entry(0o100644, 1, (0, 0), 0x44, 0x4000 | 4);
+        skip_worktree.extend_from_slice(&0x4000u16.to_be_bytes());
+        let data = index(3, &[padded(skip_worktree, "skip"), padded(entry(0o100644, 1, (0, 0), 0x55, 4), "next")]);
+        let entries = read("v3", &data).unwrap();
+        assert_eq!(paths(&entries), ["skip", "next"]);
+        assert!(entries[0].skip);
+        assert!(!entries[1].skip);
+    }
+
+    #[test]
+    fn reads_v4_prefix_compression() {
+        let long = format!("deep/{}", "x".repeat(140));
+        let data = index(4, &[
+            compressed(entry(0o100644, 1, (0, 0), 1, 11), 0, "src/main.rs"),
+            compressed(entry(0o100644, 1, (0, 0), 2, 10), "main.rs".len(), "mod.rs"),
+            compressed(entry(0o100644, 1, (0, 0), 3, 10), "src/mod.rs".len(), "tests/a.rs"),
+            compressed(entry(0o100644, 1, (0, 0), 4, 0xfff), "tests/a.rs".len(), &long),
+            // 需要两个字节的长度编码
+            compressed(entry(0o100644, 1, (0, 0), 5, 8), 140, "y"),
+            compressed(entry(0o100644, 1, (0, 0), 6, 8), 0, "z"),
+        ]);
+        let entries = read("v4", &data).unwrap();
+        assert_eq!(paths(&entries), ["src/main.rs", "src/mod.rs", "tests/a.rs", long.as_str(), "deep/y", "deep/yz"]);
+        assert_eq!(entries.iter().map(|e| e.oid[0]).collect::<Vec<_>>(), [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn rejects_truncated_or_foreign_index() {
+        let mut data = index(2, &[padded(entry(0o100644, 3, (0, 0), 1, 5), "a.txt")]);
+        data.truncate(40);
+        assert!(read("truncated", &data).is_err());
+        assert!(read("version", &index(5, &[])).is_err());
+        assert!(read("magic", b"PACK\0\0\0\x02\0\0\0\0").is_err());
+        assert!(read_index(Path::new("/nonexistent/.git")).unwrap().is_empty());
+    }
+
+    // 每个测试独占的临时目录
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("srm-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        use flate2::write::ZlibEncoder;
+        use std::io::Write;
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn varint(mut n: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        loop {
+            let b = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                out.push(b);
+                return out;
+            }
+            out.push(b | 0x80);
+        }
+    }
+
+    // 增量：基对象大小、结果大小，然后是复制/插入指令
+    fn delta(base_size: usize, result_size: usize, ops: &[&[u8]]) -> Vec<u8> {
+        let mut d = varint(base_size);
+        d.extend(varint(result_size));
+        for op in ops {
+            d.extend_from_slice(op);
+        }
+        d
+    }
+
+    // 包中对象的头部：类型与解压后大小
+    fn object_header(kind: u8, size: usize) -> Vec<u8> {
+        let mut header = vec![(kind << 4) | (size & 0x0f) as u8];
+        let mut rest = size >> 4;
+        while rest > 0 {
+            *header.last_mut().unwrap() |= 0x80;
+            header.push((rest & 0x7f) as u8);
+            rest >>= 7;
+        }
+        header
+    }
+
+    // 写出 pack 与第 2 版 idx；objects 为 (对象 ID, 头部之后的原始字节)，返回各对象的偏移
+    fn write_pack(dir: &Path, objects: &[(Oid, Vec<u8>)]) -> Vec<u64> {
+        let mut pack = b"PACK\0\0\0\x02".to_vec();
+        pack.extend_from_slice(&(objects.len() as u32).to_be_bytes());
+        let mut offsets = Vec::new();
+        for (_, raw) in objects {
+            offsets.push(pack.len() as u64);
+            pack.extend_from_slice(raw);
+        }
+        let mut sorted: Vec<(Oid, u64)> = objects.iter().map(|(oid, _)| *oid).zip(offsets.iter().copied()).collect();
+        sorted.sort();
+        let mut idx = vec![0xff, b't', b'O', b'c', 0, 0, 0, 2];
+        for first in 0..256 {
+            let below = sorted.iter().filter(|(oid, _)| oid[0] as usize <= first).count() as u32;
+            idx.extend_from_slice(&below.to_be_bytes());
+        }
+        for (oid, _) in &sorted {
+            idx.extend_from_slice(oid);
+        }
+        idx.extend(std::iter::repeat_n(0, sorted.len() * 4));
+        for (_, offset) in &sorted {
+            idx.extend_from_slice(&(*offset as u32).to_be_bytes());
+        }
+        fs::write(dir.join("test.pack"), pack).unwrap();
+        fs::write(dir.join("test.idx"), idx).unwrap();
+        offsets
+    }
+
+    fn pack_objects(dir: &Path) -> Objects {
+        Objects { dirs: Vec::new(), packs: vec![Pack::open(&dir.join("test.idx")).unwrap()], corrupt: RefCell::new(None) }
+    }
+
+    fn oid(first: u8, fill: u8) -> Oid {
+        let mut oid = [fill; 20];
+        oid[0] = first;
+        oid
+    }
+
+    #[test]
+    fn applies_delta_copy_and_insert() {
+        let base = b"hello world";
+        // 复制 base[0..6]，插入 "there"，再复制 base[5..6]
+        let d = delta(11, 12, &[&[0x90, 6], &[5], b"there", &[0x91, 5, 1]]);
+        assert_eq!(apply_delta(base, &d).unwrap(), b"hello there ");
+        // 复制大小为 0 表示 0x10000
+        let big = vec![7u8; 0x10000];
+        assert_eq!(apply_delta(&big, &delta(0x10000, 0x10000, &[&[0x80]])).unwrap(), big);
+    }
+
+    #[test]
+    fn rejects_corrupt_delta() {
+        let base = b"hello world";
+        // 复制超出基对象
+        assert!(apply_delta(base, &delta(11, 6, &[&[0x91, 8, 6]])).is_err());
+        // 复制参数被截断
+        assert!(apply_delta(base, &delta(11, 6, &[&[0xb1, 0]])).is_err());
+        // 插入超出增量末尾
+        assert!(apply_delta(base, &delta(11, 5, &[&[5], b"abc"])).is_err());
+        // 结果与声明的大小不符
+        assert!(apply_delta(base, &delta(11, 7, &[&[0x90, 6]])).is_err());
+        assert!(apply_delta(base, &delta(11, 5, &[&[0x90, 6]])).is_err());
+        // 操作码 0 保留
+        assert!(apply_delta(base, &delta(11, 0, &[&[0]])).is_err());
+        // 超过 usize 的大小编码
+        assert!(apply_delta(base, &[0xff; 12]).is_err());
+    }
+
+    #[test]
+    fn finds_objects_through_fanout_and_resolves_deltas() {
+        let dir = scratch("pack");
+        let blob = b"first line\nsecond line\n".to_vec();
+        let changed = delta(blob.len(), 16, &[&[0x90, 11], &[5], b"third"]);
+        let blob_oid = oid(0x00, 1);
+        let ofs_oid = oid(0x7f, 2);
+        let ref_oid = oid(0xff, 3);
+
+        let mut raw_blob = object_header(3, blob.len());
+        raw_blob.extend(zlib(&blob));
+        // OFS_DELTA 的基址为相对当前对象的负偏移，这里紧接在 12 字节的包头与 blob 之后
+        let mut raw_ofs = object_header(6, changed.len());
+        raw_ofs.extend(varint(raw_blob.len()));
+        raw_ofs.extend(zlib(&changed));
+        let mut raw_ref = object_header(7, changed.len());
+        raw_ref.extend_from_slice(&blob_oid);
+        raw_ref.extend(zlib(&changed));
+        let offsets = write_pack(&dir, &[(blob_oid, raw_blob), (ofs_oid, raw_ofs), (ref_oid, raw_ref)]);
+        let objects = pack_objects(&dir);
+        let _ = fs::remove_dir_all(&dir);
+
+        let pack = &objects.packs[0];
+        assert_eq!(pack.offset_of(&blob_oid), Some(offsets[0]));
+        assert_eq!(pack.offset_of(&ofs_oid), Some(offsets[1]));
+        assert_eq!(pack.offset_of(&ref_oid), Some(offsets[2]));
+        assert_eq!(pack.offset_of(&oid(0x7f, 1)), None);
+        assert_eq!(pack.offset_of(&oid(0x80, 2)), None);
+
+        assert_eq!(objects.read(&blob_oid), Some((3, blob)));
+        assert_eq!(objects.read(&ofs_oid), Some((3, b"first line\nthird".to_vec())));
+        assert_eq!(objects.read(&ref_oid), Some((3, b"first line\nthird".to_vec())));
+        assert!(objects.corrupt.borrow().is_none());
+    }
+
+    #[test]
+    fn reports_corrupt_pack_objects() {
+        let dir = scratch("corrupt-pack");
+        let looped = oid(0x10, 1);
+        let truncated = oid(0x20, 2);
+        let oversized = oid(0x30, 3);
+        // 以自身为基对象的 REF_DELTA
+        let mut raw_loop = object_header(7, 4);
+        raw_loop.extend_from_slice(&looped);
+        raw_loop.extend(zlib(&delta(4, 4, &[&[0x90, 4]])));
+        // OFS_DELTA 的偏移编码在包末尾被截断
+        let raw_truncated = [object_header(6, 4), vec![0x80]].concat();
+        write_pack(&dir, &[(looped, raw_loop), (oversized, vec![0xff; 12]), (truncated, raw_truncated)]);
+        let objects = pack_objects(&dir);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(objects.read(&looped), None);
+        assert!(objects.corrupt.take().is_some_and(|e| e.contains("delta chain too deep")));
+        assert_eq!(objects.read(&oversized), None);
+        assert!(objects.corrupt.take().is_some_and(|e| e.contains("bad pack object size")));
+        assert_eq!(objects.read(&truncated), None);
+        assert!(objects.corrupt.take().is_some_and(|e| e.contains("truncated pack")));
+    }
+
+    // 松散对象：objects/xx/yyyy 中为 zlib 压缩的 "<类型> <长度>\0<内容>"
+    fn write_commit(objects: &Path, oid: Oid, parents: &[Oid]) {
+        let mut body = format!("tree {}\n", hex(&[0; 20]));
+        for parent in parents {
+            body.push_str(&format!("parent {}\n", hex(parent)));
+        }
+        body.push_str("\nmessage\n");
+        let name = hex(&oid);
+        fs::create_dir_all(objects.join(&name[..2])).unwrap();
+        fs::write(objects.join(&name[..2]).join(&name[2..]), zlib(format!("commit {}\0{}", body.len(), body).as_bytes())).unwrap();
+    }
+
+    fn write_ref(common: &Path, name: &str, oid: Oid) {
+        let path = common.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, hex(&oid) + "\n").unwrap();
+    }
+
+    #[test]
+    fn counts_unpushed_commits_per_branch() {
+        let common = scratch("unpushed");
+        let dir = common.join("objects");
+        // c1 <- c2 <- c3（main），c2 <- c4；c3 + c4 <- c5（合并）
+        let c: Vec<Oid> = (1..=5).map(|i| oid(i * 0x10, i)).collect();
+        write_commit(&dir, c[0], &[]);
+        write_commit(&dir, c[1], &[c[0]]);
+        write_commit(&dir, c[2], &[c[1]]);
+        write_commit(&dir, c[3], &[c[1]]);
+        write_commit(&dir, c[4], &[c[2], c[3]]);
+        fs::write(common.join("config"), concat!(
+            "[remote \"origin\"]\n\turl = /elsewhere\n",
+            "[branch \"main\"]\n\tremote = origin\n\tmerge = refs/heads/main\n",
+            "[branch \"follow\"]\n\tremote = .\n\tmerge = refs/heads/main\n",
+        )).unwrap();
+        write_ref(&common, "refs/remotes/origin/main", c[0]);
+        write_ref(&common, "refs/heads/main", c[2]);
+        write_ref(&common, "refs/heads/follow", c[2]);
+        write_ref(&common, "refs/heads/merge", c[4]);
+        write_ref(&common, "refs/heads/pushed", c[0]);
+        let objects = Objects::open(&common);
+        let refs = Refs::load(&common);
+        let branches = unpushed_branches(&common, &objects, &refs);
+        let excluded = HashSet::from([c[0], c[1]]);
+        let ahead = [count_ahead(&objects, c[4], &excluded), count_ahead(&objects, c[0], &excluded)];
+        let reachable = ancestry(&objects, [c[3]]);
+        let _ = fs::remove_dir_all(&common);
+
+        assert_eq!(ahead, [(3, false), (0, false)]);
+        assert_eq!(reachable, HashSet::from([c[0], c[1], c[3]]));
+
+        // follow 与上游 main 相同、pushed 已在远程上，都不列出
+        let branch = |name: &str, upstream: Option<&str>, ahead| Branch { name: name.into(), upstream: upstream.map(String::from), ahead, truncated: false };
+        assert_eq!(branches, [branch("main", Some("origin/main"), 2), branch("merge", None, 4)]);
+    }
+
+    fn ignore(lines: &str) -> Vec<IgnorePattern> {
+        parse_ignore(lines, &[])
+    }
+
+    fn rel(path: &str) -> Vec<String> {
+        path.split('/').map(String::from).collect()
+    }
+
+    #[test]
+    fn matches_ignore_patterns() {
+        let patterns = ignore("# comment\n\n*.log\n!keep.log\nbuild/\n/top.txt\ndocs/*.tmp\n\\!bang \n");
+        assert!(ignored(&patterns, &rel("a.log"), false));
+        assert!(ignored(&patterns, &rel("sub/deep/a.log"), false));
+        // 取反：后出现的模式优先
+        assert!(!ignored(&patterns, &rel("sub/keep.log"), false));
+        // 只匹配目录
+        assert!(ignored(&patterns, &rel("sub/build"), true));
+        assert!(!ignored(&patterns, &rel("build"), false));
+        // 锚定到所在目录
+        assert!(ignored(&patterns, &rel("top.txt"), false));
+        assert!(!ignored(&patterns, &rel("sub/top.txt"), false));
+        assert!(ignored(&patterns, &rel("docs/x.tmp"), false));
+        assert!(!ignored(&patterns, &rel("sub/docs/x.tmp"), false));
+        // 转义的 `!` 与行尾空格
+        assert!(ignored(&patterns, &rel("!bang"), false));
+
+        // 子目录中的 .gitignore 只作用于该目录之下
+        let nested = parse_ignore("/local\n", &rel("sub"));
+        assert!(ignored(&nested, &rel("sub/local"), false));
+        assert!(!ignored(&nested, &rel("local"), false));
+        assert!(!ignored(&nested, &rel("other/local"), false));
+    }
+
+    #[test]
+    fn locates_gitfile_worktrees_and_bare_repos() {
+        let root = scratch("locate");
+        // git worktree：.git 是指向主仓库 worktrees/ 下的文件
+        fs::create_dir_all(root.join("main/.git/worktrees/wt")).unwrap();
+        fs::create_dir_all(root.join("wt/src")).unwrap();
+        fs::write(root.join("wt/.git"), "gitdir: ../main/.git/worktrees/wt\n").unwrap();
+        let found = locate(&root.join("wt"), Some(&[PathBuf::from(".git")]));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].worktree.as_deref(), Some(root.join("wt").as_path()));
+        assert_eq!(found[0].gitdir, root.join("main/.git/worktrees/wt"));
+        assert!(!found[0].owns_gitdir);
+
+        // 工作区内的子目录：只统计该目录之下，不涉及分支与 stash
+        let found = locate(&root.join("wt/src"), Some(&[]));
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].worktree.as_deref(), found[0].scope.as_path()), (Some(root.join("wt").as_path()), root.join("wt/src").as_path()));
+        assert!(!found[0].owns_gitdir);
+
+        // 两者都在目标之下：主仓库拥有自己的 .git，worktree 的 gitdir 也在目标之内
+        let found = locate(&root, Some(&[PathBuf::from("main/.git"), PathBuf::from("wt/.git")]));
+        assert_eq!(found.iter().map(|l| (l.worktree.clone().unwrap(), l.owns_gitdir)).collect::<Vec<_>>(),
+                   [(root.join("main"), true), (root.join("wt"), true)]);
+
+        // 裸仓库：内部名为 HEAD 的条目（如 logs/HEAD）不再单独检查
+        for dir in ["bare.git/objects", "bare.git/refs", "bare.git/logs"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("bare.git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(root.join("bare.git/logs/HEAD"), "").unwrap();
+        let found = locate(&root, Some(&[PathBuf::from("bare.git/HEAD"), PathBuf::from("bare.git/logs/HEAD")]));
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].worktree.as_ref(), &found[0].gitdir), (None, &root.join("bare.git")));
+        assert!(found[0].owns_gitdir);
+        let found = locate(&root.join("bare.git"), Some(&[PathBuf::from("HEAD"), PathBuf::from("logs/HEAD")]));
+        assert_eq!((found.len(), &found[0].gitdir), (1, &root.join("bare.git")));
+        let _ = fs::remove_dir_all(&root);
+    }
+}
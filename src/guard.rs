@@ -0,0 +1,277 @@
+// 危险目标防护：在固定的系统保护路径之外，按规则拦下家目录、当前目录、挂载点、srm 自身与虚拟文件系统
+//
+// 每条命中都带有规则名与具体原因。`--allow <规则>` 单独放行一条规则，`-f` 只放行日常操作会碰到的规则（见 FORCE_RULES）；
+// 根目录与包含 srm 存储的目录无法移入回收站，任何选项都不放行。
+// 被检查的路径应已由 plan::resolve 解析（父目录不含软链接）。
+
+use crate::{mounts, srm_link_path, PROTECTED_PATHS};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+// 内容由内核生成的文件系统，移入回收站没有意义
+const VIRTUAL_FS: [&str; 17] = [
+    "proc", "sysfs", "devtmpfs", "devpts", "cgroup", "cgroup2", "debugfs", "tracefs", "securityfs",
+    "pstore", "bpf", "configfs", "fusectl", "mqueue", "hugetlbfs", "efivarfs", "binfmt_misc",
+];
+
+#[derive(ValueEnum, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    // 根目录，不可放行
+    #[value(skip)]
+    Root,
+    // /bin、/etc、/usr 等系统路径及其下的所有内容，包括指向它们的软链接
+    System,
+    // 根目录下的一级目录本身（/var、/opt、/home、/srv 等）
+    TopLevel,
+    // 任一用户的家目录，或包含 $HOME 的目录
+    Home,
+    // 当前工作目录及其上级目录
+    Cwd,
+    // 挂载点本身
+    Mount,
+    // srm 的存储、存储链接与 srm 可执行文件
+    Store,
+    // /proc、/sys、/dev 等虚拟文件系统上的内容
+    Virtual,
+    // 规则文件与 .srm-protect 标记中的 deny/confirm
+    Rules,
+    // 含未提交改动、stash 或未推送分支的 git 仓库：放行后不再询问，但仍延长保留期
+    Git,
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.to_possible_value() {
+            Some(v) => write!(f, "{}", v.get_name()),
+            None => write!(f, "root"),
+        }
+    }
+}
+
+pub struct Hit {
+    pub rule: Rule,
+    pub message: String,
+    // 无法放行（移动本身不可能完成）
+    pub hard: bool,
+}
+
+// -f 放行的规则：在项目根目录执行 `srm del -f .`、删除未推送的仓库这类日常情形。系统路径、家目录、挂载点、存储等必须用 --allow 逐条放行
+const FORCE_RULES: [Rule; 2] = [Rule::Cwd, Rule::Git];
+
+#[derive(Default)]
+pub struct Overrides {
+    pub force: bool,
+    pub allow: Vec<Rule>,
+}
+
+impl Overrides {
+    pub fn allows(&self, hit: &Hit) -> bool {
+        !hit.hard && (self.allow.contains(&hit.rule) || (self.force && FORCE_RULES.contains(&hit.rule)))
+    }
+}
+
+pub struct Guard {
+    cwd: Option<PathBuf>,
+    home: Option<PathBuf>,
+    // (用户名, 家目录)
+    homes: Vec<(String, PathBuf)>,
+    base: PathBuf,
+    link: PathBuf,
+    exe: Option<PathBuf>,
+    mounts: Vec<mounts::Mount>,
+}
+
+// /etc/passwd 中家目录存在的用户；家目录为根目录的系统账户不计
+fn read_homes() -> Vec<(String, PathBuf)> {
+    let content = fs::read_to_string("/etc/passwd").unwrap_or_default();
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            let (user, dir) = (fields.first()?, fields.get(5)?);
+            let dir = Path::new(dir).canonicalize().ok()?;
+            (dir != Path::new("/") && dir.is_dir()).then(|| (user.to_string(), dir))
+        })
+        .collect()
+}
+
+fn protected_prefix(path: &Path) -> Option<&'static str> {
+    PROTECTED_PATHS.iter().copied().find(|&p| path.starts_with(p))
+}
+
+impl Guard {
+    pub fn new(base: &Path, mounts: Vec<mounts::Mount>) -> Self {
+        Guard {
+            cwd: std::env::current_dir().and_then(|d| d.canonicalize()).ok(),
+            home: std::env::var_os("HOME").and_then(|h| Path::new(&h).canonicalize().ok()),
+            homes: read_homes(),
+            base: base.to_path_buf(),
+            link: srm_link_path(),
+            exe: std::env::current_exe().and_then(|e| e.canonicalize()).ok(),
+            mounts,
+        }
+    }
+
+    pub fn check(&self, path: &Path, meta: &fs::Metadata) -> Vec<Hit> {
+        let mut hits = Vec::new();
+        let mut hit = |rule: Rule, message: String, hard: bool| hits.push(Hit { rule, message, hard });
+
+        if path == Path::new("/") {
+            hit(Rule::Root, "Refusing to remove the root directory".into(), true);
+            return hits;
+        }
+
+        if let Some(p) = protected_prefix(path) {
+            hit(Rule::System, format!("Protected system path {}", p), false);
+        } else if path.parent() == Some(Path::new("/")) {
+            hit(Rule::TopLevel, format!("Top-level directory {}", path.display()), false);
+        }
+        if meta.file_type().is_symlink() {
+            // 相对目标按链接所在目录解析；能解析到实际位置时按实际位置判断，悬空链接按词法结果判断
+            if let Ok(target) = fs::read_link(path) {
+                let target = path.parent().unwrap_or(Path::new("/")).join(target);
+                let resolved = target.canonicalize().unwrap_or_else(|_| crate::plan::lexical(&target));
+                if let Some(p) = protected_prefix(&resolved) {
+                    hit(Rule::System, format!("Symlink targets protected path: {} (under {})", resolved.display(), p), false);
+                }
+            }
+        }
+
+        if let Some((user, _)) = self.homes.iter().find(|(_, dir)| dir == path) {
+            hit(Rule::Home, format!("Home directory of user '{}'", user), false);
+        } else if let Some(home) = self.home.as_deref().filter(|h| h.starts_with(path)) {
+            hit(Rule::Home, format!("Contains your home directory {}", home.display()), false);
+        }
+
+        if let Some(cwd) = self.cwd.as_deref().filter(|c| c.starts_with(path)) {
+            let message = if cwd == path {
+                "Current working directory".to_string()
+            } else {
+                format!("Contains the current working directory {}", cwd.display())
+            };
+            hit(Rule::Cwd, message, false);
+        }
+
+        // 不同设备说明是挂载点；bind mount 的设备号与上级相同，再查挂载表
+        let parent_dev = path.parent().and_then(|p| fs::metadata(p).ok()).map(|m| m.dev());
+        let mounted = self.mounts.iter().rev().find(|m| m.mount_point == path);
+        if !meta.file_type().is_symlink() && (mounted.is_some() || parent_dev.is_some_and(|d| d != meta.dev())) {
+            let message = match mounted.or_else(|| mounts::by_dev(&self.mounts, meta.dev())) {
+                Some(m) => format!("Mount point ({} from {})", m.fstype, m.source),
+                None => "Mount point".to_string(),
+            };
+            hit(Rule::Mount, message, false);
+        }
+
+        if self.base.starts_with(path) {
+            hit(Rule::Store, format!("Contains the srm store {}", self.base.display()), true);
+        } else if path.starts_with(&self.base) {
+            hit(Rule::Store, "Inside the srm store (use `srm restore`, `srm clean` or `srm empty`)".into(), false);
+        } else if path == self.link {
+            hit(Rule::Store, "Link to the srm store".into(), false);
+        } else if self.exe.as_deref() == Some(path) {
+            hit(Rule::Store, "The srm binary".into(), false);
+        }
+
+        if let Some(m) = mounts::by_dev(&self.mounts, meta.dev()).filter(|m| VIRTUAL_FS.contains(&m.fstype.as_str())) {
+            hit(Rule::Virtual, format!("On the {} virtual filesystem mounted at {}", m.fstype, m.mount_point.display()), false);
+        }
+
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch_dir;
+
+    fn rules(guard: &Guard, path: &Path) -> Vec<(Rule, bool)> {
+        let meta = fs::symlink_metadata(path).unwrap();
+        guard.check(path, &meta).into_iter().map(|h| (h.rule, h.hard)).collect()
+    }
+
+    fn scratch_guard(dir: &Path) -> Guard {
+        Guard {
+            cwd: Some(dir.join("home/u/work")),
+            home: Some(dir.join("home/u")),
+            homes: vec![("other".into(), dir.join("home/other"))],
+            base: dir.join("home/u/.srm"),
+            link: dir.join("srm-link"),
+            exe: Some(dir.join("bin/srm")),
+            mounts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn flags_system_and_top_level_paths() {
+        let dir = scratch_dir("guard-system");
+        std::os::unix::fs::symlink("/etc/passwd", dir.join("to-etc")).unwrap();
+        std::os::unix::fs::symlink("../../../../usr/nowhere", dir.join("dangling")).unwrap();
+        let guard = scratch_guard(&dir);
+        let root = guard.check(Path::new("/"), &fs::metadata("/").unwrap());
+        let found = [rules(&guard, Path::new("/etc")), rules(&guard, &dir.join("to-etc"))];
+        // /dev 是否为挂载点因环境而异
+        let top_level = rules(&guard, Path::new("/dev"));
+        // 悬空链接按词法结果判断：/tmp/srm-test-*/ 之上的 `..` 停在根目录
+        let dangling = rules(&guard, &dir.join("dangling"));
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(root.iter().map(|h| (h.rule, h.hard)).collect::<Vec<_>>(), [(Rule::Root, true)]);
+        assert_eq!(found, [vec![(Rule::System, false)], vec![(Rule::System, false)]]);
+        assert!(top_level.contains(&(Rule::TopLevel, false)));
+        assert_eq!(dangling, [(Rule::System, false)]);
+    }
+
+    #[test]
+    fn flags_homes_cwd_store_and_filesystems() {
+        let dir = scratch_dir("guard-rules");
+        for sub in ["home/u/work", "home/u/.srm/trash", "home/other", "bin", "plain"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        fs::write(dir.join("bin/srm"), "").unwrap();
+        fs::write(dir.join("srm-link"), "").unwrap();
+        let mut guard = scratch_guard(&dir);
+        let dev = fs::metadata(&dir).unwrap().dev();
+        guard.mounts = vec![
+            mounts::Mount { dev: 0, mount_point: dir.join("plain"), fstype: "ext4".into(), source: "/dev/x".into() },
+            mounts::Mount { dev, mount_point: dir.clone(), fstype: "proc".into(), source: "proc".into() },
+        ];
+        let check = |rel: &str| rules(&guard, &dir.join(rel));
+        let found = [
+            check("home"), check("home/u/work"), check("home/other"), check("home/u/.srm/trash"),
+            check("bin/srm"), check("srm-link"), check("plain"),
+        ];
+        let _ = fs::remove_dir_all(&dir);
+
+        // 测试把整个临时目录登记为虚拟文件系统，每一项都带 Virtual
+        let virt = (Rule::Virtual, false);
+        assert_eq!(found, [
+            vec![(Rule::Home, false), (Rule::Cwd, false), (Rule::Store, true), virt],
+            vec![(Rule::Cwd, false), virt],
+            vec![(Rule::Home, false), virt],
+            vec![(Rule::Store, false), virt],
+            vec![(Rule::Store, false), virt],
+            vec![(Rule::Store, false), virt],
+            vec![(Rule::Mount, false), virt],
+        ]);
+    }
+
+    #[test]
+    fn overrides_release_only_soft_rules() {
+        let hit = |rule, hard| Hit { rule, message: String::new(), hard };
+        let force = Overrides { force: true, allow: Vec::new() };
+        assert!(force.allows(&hit(Rule::Cwd, false)) && force.allows(&hit(Rule::Git, false)));
+        assert!(!force.allows(&hit(Rule::Home, false)) && !force.allows(&hit(Rule::Rules, false)));
+        let allow = Overrides { force: false, allow: vec![Rule::Store, Rule::Home] };
+        assert!(allow.allows(&hit(Rule::Home, false)) && !allow.allows(&hit(Rule::Cwd, false)));
+        // 无法放行的命中任何选项都不放行
+        assert!(!allow.allows(&hit(Rule::Store, true)));
+        assert_eq!(Rule::TopLevel.to_string(), "top-level");
+        assert_eq!(Rule::Root.to_string(), "root");
+    }
+}
@@ -0,0 +1,558 @@
+// srm import：把其他回收站中的项目并入当前存储
+//
+// 支持 FreeDesktop 回收站目录（files/ + info/*.trashinfo，gio trash 与 trash-cli 共用）、
+// trash-cli 在各卷顶层使用的 .Trash-$uid / .Trash/$uid，以及其他 srm 的 .srm 存储。
+// 项目被移动进 trash/（跨文件系统时走与删除相同的 reflink/复制+校验路径），来源信息转换为
+// FileMeta，保留原删除时间；与现有短ID冲突的重新分配。每个项目都记录一条 "File imported" 日志。
+
+use crate::{
+    atomic_save_meta, check_disk_space, encode_path, generate_short_id,
+    list_all_meta, load_all_meta, local_to_utc, lock, log_event, meta_file, new_trash_id, new_trash_path, path_usage, record_digest, remove_meta,
+    safe_move_hashed, safe_move_with_progress, save_manifest, shard_of, trash_rel, undo_partial_move, ChecksumMode, FileMeta, FileType, VerifyMode, INTERRUPTED,
+    META_SCHEMA_VERSION, PROGRESS_THRESHOLD_BYTES,
+};
+use bytesize::ByteSize;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use clap::ValueEnum;
+use serde_json::json;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ImportFormat {
+    /// Detect from the directory layout
+    Auto,
+    /// A trash directory with files/ and info/ (gio, KDE, trash-cli home trash)
+    Freedesktop,
+    /// A volume top directory holding .Trash-$uid or .Trash/$uid
+    TrashCli,
+    /// Another srm store (.srm with trash/ and meta/)
+    Srm,
+}
+
+impl std::fmt::Display for ImportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ImportFormat::Auto => write!(f, "auto"),
+            ImportFormat::Freedesktop => write!(f, "freedesktop"),
+            ImportFormat::TrashCli => write!(f, "trash-cli"),
+            ImportFormat::Srm => write!(f, "srm"),
+        }
+    }
+}
+
+enum Origin {
+    // 导入后删除的 .trashinfo
+    TrashInfo(PathBuf),
+    // 来源存储中的回收ID、元数据及其 meta/ 目录
+    Srm { trash_id: String, meta: Box<FileMeta>, meta_dir: PathBuf },
+}
+
+struct Candidate {
+    item: PathBuf,
+    original: PathBuf,
+    delete_time: DateTime<Utc>,
+    format: ImportFormat,
+    origin: Origin,
+}
+
+fn percent_decode(s: &str) -> Option<OsString> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(OsString::from_vec(out))
+}
+
+// 解析 .trashinfo：返回 Path（已解码）与 DeletionDate（本地时间，可能缺失）
+fn parse_trashinfo(content: &str) -> Option<(PathBuf, Option<NaiveDateTime>)> {
+    let mut in_section = false;
+    let mut path = None;
+    let mut date = None;
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_section = line == "[Trash Info]";
+            continue;
+        }
+        if !in_section {
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("Path=") {
+            path = percent_decode(value).map(PathBuf::from);
+        } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+            date = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S").ok();
+        }
+    }
+    path.map(|p| (p, date))
+}
+
+// 卷顶层回收站（$topdir/.Trash-$uid、$topdir/.Trash/$uid）中的相对路径以 $topdir 为基准
+fn trash_topdir(trash: &Path) -> Option<PathBuf> {
+    let name = trash.file_name()?.to_string_lossy();
+    if name.starts_with(".Trash-") {
+        return trash.parent().map(Path::to_path_buf);
+    }
+    let parent = trash.parent()?;
+    (parent.file_name()? == ".Trash").then(|| parent.parent().map(Path::to_path_buf)).flatten()
+}
+
+fn is_freedesktop_trash(dir: &Path) -> bool {
+    dir.join("info").is_dir() && dir.join("files").is_dir()
+}
+
+fn is_srm_store(dir: &Path) -> bool {
+    dir.join("meta").is_dir() && dir.join("trash").is_dir()
+}
+
+fn scan_freedesktop(trash: &Path, format: ImportFormat, out: &mut Vec<Candidate>, skipped: &mut Vec<(String, String)>) -> io::Result<()> {
+    let topdir = trash_topdir(trash);
+    for entry in fs::read_dir(trash.join("info"))? {
+        let info = entry?.path();
+        let Some(stem) = info.file_name().and_then(|n| n.as_bytes().strip_suffix(b".trashinfo")) else {
+            continue;
+        };
+        let item = trash.join("files").join(OsString::from_vec(stem.to_vec()));
+        if item.symlink_metadata().is_err() {
+            skipped.push((info.display().to_string(), "No matching entry in files/".into()));
+            continue;
+        }
+        let parsed = fs::read(&info).ok().and_then(|bytes| parse_trashinfo(&String::from_utf8_lossy(&bytes)));
+        let Some((path, date)) = parsed else {
+            skipped.push((info.display().to_string(), "Unreadable .trashinfo".into()));
+            continue;
+        };
+        let original = match (path.is_absolute(), &topdir) {
+            (true, _) => path,
+            (false, Some(top)) => top.join(path),
+            (false, None) => {
+                skipped.push((info.display().to_string(), "Relative Path= outside a volume trash".into()));
+                continue;
+            }
+        };
+        // 缺少 DeletionDate 时退而使用 .trashinfo 的修改时间
+        let delete_time = date.map(|d| local_to_utc(&d)).unwrap_or_else(|| {
+            fs::symlink_metadata(&info).and_then(|m| m.modified()).map(DateTime::<Utc>::from).unwrap_or_else(|_| Utc::now())
+        });
+        out.push(Candidate { item, original, delete_time, format, origin: Origin::TrashInfo(info) });
+    }
+    Ok(())
+}
+
+fn scan_volume(top: &Path, out: &mut Vec<Candidate>, skipped: &mut Vec<(String, String)>) -> io::Result<usize> {
+    let mut trashes = Vec::new();
+    for entry in fs::read_dir(top)?.flatten() {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with(".Trash-") && is_freedesktop_trash(&path) {
+            trashes.push(path);
+        }
+    }
+    if let Ok(entries) = fs::read_dir(top.join(".Trash")) {
+        trashes.extend(entries.flatten().map(|e| e.path()).filter(|p| is_freedesktop_trash(p)));
+    }
+    for trash in &trashes {
+        scan_freedesktop(trash, ImportFormat::TrashCli, out, skipped)?;
+    }
+    Ok(trashes.len())
+}
+
+fn scan_srm(store: &Path, out: &mut Vec<Candidate>) {
+    let (trash_dir, meta_dir) = (store.join("trash"), store.join("meta"));
+    for (trash_id, meta) in list_all_meta(&meta_dir, &trash_dir) {
+        out.push(Candidate {
+            item: meta.trash(&trash_dir),
+            original: meta.original(),
+            delete_time: meta.delete_time,
+            format: ImportFormat::Srm,
+            origin: Origin::Srm { trash_id, meta: Box::new(meta), meta_dir: meta_dir.clone() },
+        });
+    }
+}
+
+fn detect(source: &Path) -> Option<ImportFormat> {
+    if is_srm_store(source) {
+        Some(ImportFormat::Srm)
+    } else if is_freedesktop_trash(source) {
+        Some(ImportFormat::Freedesktop)
+    } else if source.join(".Trash").is_dir()
+        || fs::read_dir(source).ok()?.flatten().any(|e| e.file_name().to_string_lossy().starts_with(".Trash-"))
+    {
+        Some(ImportFormat::TrashCli)
+    } else {
+        None
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn handle_import(sources: Vec<PathBuf>, format: ImportFormat, expire_days: i64, dry_run: bool, verify: VerifyMode,
+                     checksum: bool, lock_timeout: std::time::Duration, base: &Path, trash_dir: &Path, meta_dir: &Path) {
+    let mut candidates = Vec::new();
+    let mut skipped: Vec<(String, String)> = Vec::new();
+    // 来源 srm 存储的锁在整个导入期间持有
+    let mut source_locks = Vec::new();
+
+    for source in &sources {
+        let Ok(source) = source.canonicalize() else {
+            eprintln!("❌  Source not found: {}", source.display());
+            std::process::exit(1);
+        };
+        if source == base {
+            eprintln!("❌  {} is the current store", source.display());
+            std::process::exit(1);
+        }
+        let fmt = match format {
+            ImportFormat::Auto => match detect(&source) {
+                Some(f) => f,
+                None => {
+                    eprintln!("❌  {} is not a recognised trash directory (use --from to choose the format)", source.display());
+                    std::process::exit(1);
+                }
+            },
+            f => f,
+        };
+        // 演练只读取来源存储，与来源的其他读者共享锁；实际导入要搬走其中的项目，需独占
+        let source_mode = if dry_run { lock::LockMode::Shared } else { lock::LockMode::Exclusive };
+        let result = match fmt {
+            ImportFormat::Srm => match lock::acquire(&source, source_mode, lock_timeout) {
+                Ok(l) => {
+                    source_locks.push(l);
+                    scan_srm(&source, &mut candidates);
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            },
+            ImportFormat::TrashCli => scan_volume(&source, &mut candidates, &mut skipped).and_then(|n| match n {
+                0 => Err(io::Error::other("no .Trash-$uid or .Trash/$uid directories found")),
+                _ => Ok(()),
+            }),
+            _ => scan_freedesktop(&source, fmt, &mut candidates, &mut skipped),
+        };
+        if let Err(e) = result {
+            eprintln!("❌  Cannot read {} as {} trash: {}", source.display(), fmt, e);
+            std::process::exit(1);
+        }
+    }
+    candidates.sort_by_key(|c| c.delete_time);
+
+    if candidates.is_empty() {
+        println!("ℹ️  Nothing to import");
+        for (path, reason) in &skipped {
+            println!("   ⚠️  {}: {}", path, reason);
+        }
+        return;
+    }
+
+    let existing = load_all_meta(meta_dir, trash_dir, true);
+    let mut short_ids: HashSet<String> = existing.values().map(|m| m.short_id.clone()).collect();
+    let mut sizes = Vec::with_capacity(candidates.len());
+    let mut total_bytes = 0;
+    let mut total_allocated = 0;
+    for c in &candidates {
+        let mut usage = path_usage(&c.item).unwrap_or_default();
+        if let Origin::Srm { meta, .. } = &c.origin {
+            usage.apparent = meta.size_bytes;
+        }
+        total_bytes += usage.apparent;
+        total_allocated += usage.allocated;
+        sizes.push(usage);
+    }
+
+    if dry_run {
+        println!("🔍 Would import {} item(s) ({}):", candidates.len(), ByteSize(total_bytes));
+        for (c, usage) in candidates.iter().zip(&sizes) {
+            println!("   {}  {}  [{}] ({})", c.delete_time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                     c.original.display(), ByteSize(usage.apparent), c.format);
+        }
+        for (path, reason) in &skipped {
+            println!("   ⚠️  {}: {}", path, reason);
+        }
+        return;
+    }
+
+    if let Err(e) = check_disk_space(trash_dir, total_allocated, false) {
+        eprintln!("❌  {}", e);
+        std::process::exit(1);
+    }
+
+    log_event("INFO", "Import command started", Some(json!({
+        "sources": sources.iter().map(|s| s.display().to_string()).collect::<Vec<_>>(),
+        "items_to_import": candidates.len(),
+        "total_size_bytes": total_bytes
+    })));
+    println!("📥 Importing {} item(s) ({} total)...", candidates.len(), ByteSize(total_bytes));
+
+    let mut imported = 0usize;
+    let mut reassigned = 0usize;
+    let mut failed: Vec<(String, String)> = Vec::new();
+    for (c, usage) in candidates.into_iter().zip(sizes) {
+        let size_bytes = usage.apparent;
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            println!("\n⚠️  Operation interrupted. Stopping...");
+            log_event("WARN", "User interrupted operation", None);
+            break;
+        }
+        let disk = match fs::symlink_metadata(&c.item) {
+            Ok(m) => m,
+            Err(e) => {
+                failed.push((c.original.display().to_string(), format!("{}", e)));
+                continue;
+            }
+        };
+        let file_type = FileType::of(&disk);
+
+        // 来源 srm 的回收ID和短ID尽量沿用，冲突时才重新分配
+        let (trash_id, previous_short_id) = match &c.origin {
+            Origin::Srm { trash_id, meta, .. } if !existing.contains_key(trash_id)
+                && [trash_dir.join(trash_id), trash_dir.join(shard_of(trash_id)).join(trash_id)].iter().all(|p| p.symlink_metadata().is_err()) => {
+                (trash_id.clone(), Some(meta.short_id.clone()))
+            }
+            Origin::Srm { meta, .. } => (new_trash_id(&c.original), Some(meta.short_id.clone())),
+            Origin::TrashInfo(_) => (new_trash_id(&c.original), None),
+        };
+        let short_id = match &previous_short_id {
+            Some(id) if !id.is_empty() && short_ids.insert(id.clone()) => id.clone(),
+            _ => generate_short_id(&trash_id, file_type, &mut short_ids),
+        };
+        if previous_short_id.as_ref().is_some_and(|id| *id != short_id) {
+            reassigned += 1;
+        }
+
+        let trash_path = match new_trash_path(trash_dir, &trash_id) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("❌ Failed '{}': {}", c.original.display(), e);
+                failed.push((c.original.display().to_string(), format!("{}", e)));
+                continue;
+            }
+        };
+        // srm 来源的项目沿用原有的摘要；其他来源在移动时计算，复制时按源数据计算
+        let hashing = if checksum && matches!(c.origin, Origin::TrashInfo(_)) { ChecksumMode::Always } else { ChecksumMode::Off };
+        let digest = match safe_move_hashed(&c.item, &trash_path, size_bytes > PROGRESS_THRESHOLD_BYTES, verify, hashing) {
+            Ok((_, digest)) => digest,
+            Err(e) => {
+                if file_type == FileType::Dir && trash_path.exists() {
+                    if let Err(undo_err) = undo_partial_move(&trash_path, &c.item, verify) {
+                        eprintln!("⚠️  Partial move of '{}' could not be undone ({}); remaining data is in {}",
+                                  c.item.display(), undo_err, trash_path.display());
+                    }
+                }
+                eprintln!("❌ Failed '{}': {}", c.original.display(), e);
+                failed.push((c.original.display().to_string(), format!("{}", e)));
+                continue;
+            }
+        };
+
+        // 跨文件系统复制后块分配会变化，按回收站中的副本重新计量
+        let allocated_bytes = if disk.dev() == trash_dir.metadata().map(|m| m.dev()).unwrap_or(0) {
+            usage.allocated
+        } else {
+            path_usage(&trash_path).map(|u| u.allocated).unwrap_or(usage.allocated)
+        };
+        let mut file_meta = match &c.origin {
+            Origin::Srm { meta, .. } => {
+                let mut meta = (**meta).clone();
+                meta.schema_version = META_SCHEMA_VERSION;
+                meta.allocated_bytes = Some(allocated_bytes);
+                meta
+            }
+            Origin::TrashInfo(_) => {
+                let (original_path, original_path_b64) = encode_path(&c.original);
+                // 过期时间从导入时起算，多年前删除的项目不会在下一次清理时立刻被清除
+                let checksum = record_digest(digest, &trash_id, meta_dir, &c.original);
+                FileMeta {
+                    schema_version: META_SCHEMA_VERSION,
+                    original_path,
+                    trash_path: String::new(),
+                    original_path_b64,
+                    trash_path_b64: None,
+                    delete_time: c.delete_time,
+                    expire_time: Utc::now() + Duration::days(expire_days),
+                    expire_days,
+                    file_type,
+                    rdev: FileType::device(&disk),
+                    permissions: Some(disk.permissions().mode()),
+                    uid: Some(disk.uid()),
+                    gid: Some(disk.gid()),
+                    short_id: String::new(),
+                    size_bytes,
+                    allocated_bytes: Some(allocated_bytes),
+                    checksum,
+                    git: None,
+                    mounts: Vec::new(),
+                    attrs: Vec::new(),
+                }
+            }
+        };
+        file_meta.short_id = short_id.clone();
+        file_meta.set_trash(&trash_rel(&trash_id));
+
+        if let Origin::Srm { trash_id: source_id, meta_dir: source_meta, .. } = &c.origin {
+            let manifest = meta_file(source_meta, source_id, "manifest");
+            if manifest.exists() {
+                let moved = fs::read(&manifest).and_then(|bytes| save_manifest(&trash_id, &bytes, meta_dir));
+                if let Err(e) = moved {
+                    eprintln!("⚠️  Failed to import manifest for '{}': {}", c.original.display(), e);
+                }
+            }
+        }
+        if let Err(e) = atomic_save_meta(&trash_id, &file_meta, meta_dir) {
+            eprintln!("⚠️  Failed to save metadata for '{}': {}", c.original.display(), e);
+            remove_meta(&trash_id, meta_dir);
+            let _ = safe_move_with_progress(&trash_path, &c.item, false, verify);
+            failed.push((c.original.display().to_string(), format!("Metadata save failed: {}", e)));
+            continue;
+        }
+        let source_entry = match &c.origin {
+            Origin::TrashInfo(info) => {
+                if let Err(e) = fs::remove_file(info) {
+                    eprintln!("⚠️  Imported '{}' but could not remove {}: {}", c.original.display(), info.display(), e);
+                }
+                info.display().to_string()
+            }
+            Origin::Srm { trash_id: source_id, meta_dir: source_meta, .. } => {
+                let source = meta_file(source_meta, source_id, "meta");
+                remove_meta(source_id, source_meta);
+                source.display().to_string()
+            }
+        };
+
+        let renamed = match &previous_short_id {
+            Some(prev) if *prev != short_id => format!(" (was {})", prev),
+            _ => String::new(),
+        };
+        println!("✅ {} → 🆔 {}{} [{}]", c.original.display(), short_id, renamed, ByteSize(size_bytes));
+        log_event("INFO", "File imported", Some(json!({
+            "action": "import",
+            "format": c.format.to_string(),
+            "source": source_entry,
+            "short_id": short_id,
+            "previous_short_id": previous_short_id,
+            "trash_id": trash_id,
+            "original_path": file_meta.original_path,
+            "original_path_b64": file_meta.original_path_b64,
+            "file_type": format!("{}", file_type),
+            "size_bytes": size_bytes,
+            "allocated_bytes": file_meta.allocated_bytes,
+            "checksum": file_meta.checksum,
+            "permissions": file_meta.permissions.map(|p| format!("{:o}", p & 0o777)),
+            "uid": file_meta.uid,
+            "gid": file_meta.gid,
+            "delete_time": file_meta.delete_time,
+            "expire_time": file_meta.expire_time,
+            "expire_days": file_meta.expire_days
+        })));
+        imported += 1;
+    }
+
+    log_event("INFO", "Import command completed", Some(json!({
+        "imported": imported,
+        "reassigned_short_ids": reassigned,
+        "skipped": skipped.len(),
+        "failed": failed.len()
+    })));
+
+    println!("\n✅ Import completed ({} imported, {} skipped, {} failed)", imported, skipped.len(), failed.len());
+    if reassigned > 0 {
+        println!("   {} short ID(s) were already in use and have been reassigned", reassigned);
+    }
+    for (path, reason) in skipped.iter().chain(&failed) {
+        println!("   ⚠️  {}: {}", path, reason);
+    }
+    if !failed.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch_dir;
+
+    fn trashinfo(trash: &Path, name: &str, content: &str) {
+        fs::create_dir_all(trash.join("info")).unwrap();
+        fs::create_dir_all(trash.join("files")).unwrap();
+        fs::write(trash.join("info").join(format!("{}.trashinfo", name)), content).unwrap();
+    }
+
+    #[test]
+    fn percent_decodes_raw_bytes() {
+        assert_eq!(percent_decode("/home/a%20b/%E6%96%87").unwrap(), OsString::from("/home/a b/文"));
+        // 非 UTF-8 字节原样保留
+        assert_eq!(percent_decode("/x%FFy").unwrap().as_bytes(), b"/x\xffy");
+        assert_eq!(percent_decode("100%"), None);
+        assert_eq!(percent_decode("%zz"), None);
+    }
+
+    #[test]
+    fn parses_trashinfo_section() {
+        let (path, date) = parse_trashinfo("[Trash Info]\nPath=/tmp/a%25b\nDeletionDate=2024-03-01T12:30:00\n").unwrap();
+        assert_eq!(path, Path::new("/tmp/a%b"));
+        assert_eq!(date.unwrap().to_string(), "2024-03-01 12:30:00");
+        // 其他节中的键被忽略；日期格式不对时视为缺失
+        let (path, date) = parse_trashinfo("[Other]\nPath=/wrong\n[Trash Info]\nPath=rel/x\nDeletionDate=yesterday\n").unwrap();
+        assert_eq!((path.as_path(), date), (Path::new("rel/x"), None));
+        assert!(parse_trashinfo("[Trash Info]\nDeletionDate=2024-03-01T12:30:00\n").is_none());
+    }
+
+    #[test]
+    fn finds_volume_trash_topdirs() {
+        assert_eq!(trash_topdir(Path::new("/mnt/usb/.Trash-1000")), Some(PathBuf::from("/mnt/usb")));
+        assert_eq!(trash_topdir(Path::new("/mnt/usb/.Trash/1000")), Some(PathBuf::from("/mnt/usb")));
+        assert_eq!(trash_topdir(Path::new("/home/u/.local/share/Trash")), None);
+    }
+
+    #[test]
+    fn scans_home_and_volume_trashes() {
+        let dir = scratch_dir("import-scan");
+        let home = dir.join("Trash");
+        trashinfo(&home, "a", "[Trash Info]\nPath=/home/u/a\nDeletionDate=2024-03-01T12:30:00\n");
+        fs::write(home.join("files/a"), "a").unwrap();
+        trashinfo(&home, "orphan", "[Trash Info]\nPath=/home/u/orphan\n");
+        trashinfo(&home, "rel", "[Trash Info]\nPath=rel\n");
+        fs::write(home.join("files/rel"), "r").unwrap();
+        fs::write(home.join("files/garbled"), "g").unwrap();
+        trashinfo(&home, "garbled", "not a trashinfo");
+        assert_eq!(detect(&home), Some(ImportFormat::Freedesktop));
+
+        let (mut out, mut skipped) = (Vec::new(), Vec::new());
+        scan_freedesktop(&home, ImportFormat::Freedesktop, &mut out, &mut skipped).unwrap();
+        assert_eq!(out.len(), 1);
+        assert_eq!((out[0].item.as_path(), out[0].original.as_path()), (home.join("files/a").as_path(), Path::new("/home/u/a")));
+        let date = NaiveDateTime::parse_from_str("2024-03-01T12:30:00", "%Y-%m-%dT%H:%M:%S").unwrap();
+        assert_eq!(out[0].delete_time, local_to_utc(&date));
+        skipped.sort();
+        let reasons: Vec<&str> = skipped.iter().map(|(_, r)| r.as_str()).collect();
+        assert_eq!(reasons, ["Unreadable .trashinfo", "No matching entry in files/", "Relative Path= outside a volume trash"]);
+
+        // 卷顶层回收站的相对路径以卷顶层为基准
+        let volume = dir.join("volume");
+        for trash in [volume.join(".Trash-1000"), volume.join(".Trash/1000")] {
+            trashinfo(&trash, "b", "[Trash Info]\nPath=docs/b\n");
+            fs::write(trash.join("files/b"), "b").unwrap();
+        }
+        assert_eq!(detect(&volume), Some(ImportFormat::TrashCli));
+        let (mut out, mut skipped) = (Vec::new(), Vec::new());
+        assert_eq!(scan_volume(&volume, &mut out, &mut skipped).unwrap(), 2);
+        assert!(skipped.is_empty());
+        assert!(out.iter().all(|c| c.original == volume.join("docs/b") && c.format == ImportFormat::TrashCli));
+        assert_eq!(detect(&dir), None);
+        fs::create_dir_all(dir.join("trash")).unwrap();
+        fs::create_dir_all(dir.join("meta")).unwrap();
+        assert_eq!(detect(&dir), Some(ImportFormat::Srm));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,116 @@
+// 回收站存储的进程间咨询锁：读操作持共享锁，修改操作持排他锁
+//
+// 锁文件为 .srm/srm.lock；排他锁持有者会把自己的 PID 写入其中，便于等待方提示。
+
+use fs2::FileExt;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+}
+
+// 析构时关闭文件，内核随之释放 flock
+pub struct StoreLock {
+    _file: fs::File,
+}
+
+fn holder_pid(file: &mut fs::File) -> Option<u32> {
+    let mut content = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut content).ok()?;
+    let pid: u32 = content.trim().parse().ok()?;
+    Path::new(&format!("/proc/{}", pid)).exists().then_some(pid)
+}
+
+pub fn acquire(base: &Path, mode: LockMode, timeout: Duration) -> io::Result<StoreLock> {
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o600)
+        .open(base.join("srm.lock"))?;
+
+    let start = Instant::now();
+    let mut announced = false;
+    loop {
+        let result = match mode {
+            LockMode::Shared => FileExt::try_lock_shared(&file),
+            LockMode::Exclusive => FileExt::try_lock_exclusive(&file),
+        };
+        match result {
+            Ok(()) => break,
+            Err(e) if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() => {}
+            Err(e) => return Err(e),
+        }
+
+        if start.elapsed() >= timeout {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("Timed out after {}s waiting for the store lock (use --lock-timeout to wait longer)", timeout.as_secs()),
+            ));
+        }
+        if !announced {
+            let holder = holder_pid(&mut file).map(|pid| format!(" (pid {})", pid)).unwrap_or_default();
+            eprintln!("⏳ Waiting for store lock held by another srm process{}... (timeout {}s)", holder, timeout.as_secs());
+            announced = true;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+        if crate::INTERRUPTED.load(std::sync::atomic::Ordering::Relaxed) {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "Interrupted while waiting for the store lock"));
+        }
+    }
+
+    if mode == LockMode::Exclusive {
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write!(file, "{}", std::process::id())?;
+    }
+    Ok(StoreLock { _file: file })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch_dir;
+
+    // flock 按打开的文件描述区分持有者，同一进程内的两次 acquire 也会互斥
+    #[test]
+    fn shared_locks_coexist_and_exclude_writers() {
+        let base = scratch_dir("lock-shared");
+        let first = acquire(&base, LockMode::Shared, Duration::ZERO).unwrap();
+        let second = acquire(&base, LockMode::Shared, Duration::ZERO).unwrap();
+        let err = acquire(&base, LockMode::Exclusive, Duration::ZERO).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        drop((first, second));
+        assert!(acquire(&base, LockMode::Exclusive, Duration::ZERO).is_ok());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn exclusive_lock_records_pid_and_times_out_waiters() {
+        let base = scratch_dir("lock-exclusive");
+        let held = acquire(&base, LockMode::Exclusive, Duration::ZERO).unwrap();
+        assert_eq!(fs::read_to_string(base.join("srm.lock")).unwrap(), std::process::id().to_string());
+
+        let start = Instant::now();
+        let err = acquire(&base, LockMode::Shared, Duration::from_millis(300)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() >= Duration::from_millis(300));
+
+        // 等待期间持有者释放后即可取得
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            drop(held);
+        });
+        assert!(acquire(&base, LockMode::Exclusive, Duration::from_secs(10)).is_ok());
+        release.join().unwrap();
+        let _ = fs::remove_dir_all(&base);
+    }
+}
@@ -1,25 +1,47 @@
-use clap::{Parser, Subcommand};
-use chrono::{Duration, Local, NaiveDateTime, TimeZone, LocalResult};
+mod at;
+mod attrs;
+mod breaker;
+mod busy;
+mod fsck;
+mod git;
+mod guard;
+mod import;
+mod lock;
+mod mounts;
+mod plan;
+mod rebuild;
+mod rm;
+mod rules;
+mod scan;
+mod store;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use chrono::{DateTime, Duration, Local, LocalResult, NaiveDateTime, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::io::{self, BufReader, BufRead, Read, Write};
+use std::io::{self, BufReader, BufRead, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::ffi::{OsStr, OsString};
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::{Instant, SystemTime};
-use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
 use md5::compute;
 use serde_json::json;
 use indicatif::{ProgressBar, ProgressStyle, MultiProgress};
 use memmap2::MmapOptions;
-use fs2;
-use libc;
 use std::cmp;
 use bytesize::ByteSize;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 
 static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+// atomic_write 临时文件名中的序号，同一进程内不重复
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
 
 const DEFAULT_EXPIRE_DAYS: i64 = 7;
+// 含未保存 git 工作的目录至少保留的天数
+const GIT_EXPIRE_DAYS: i64 = 30;
 const MAX_LOG_AGE_DAYS: i64 = 30;
 const PROTECTED_PATHS: [&str; 8] = ["/bin", "/sbin", "/etc", "/usr", "/lib", "/lib64", "/root", "/boot"];
 const SHORT_ID_LENGTH: usize = 6;
@@ -28,13 +50,24 @@ const PROGRESS_THRESHOLD_ITEMS: usize = 5;
 const MAX_RECURSION_DEPTH: usize = 1000;
 const MAX_FILE_SPACE_RATIO: f64 = 0.8;
 const MMAP_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+const VERIFY_THRESHOLD_BYTES: u64 = 16 * 1024 * 1024;
+const LOCK_TIMEOUT_SECS: u64 = 60;
+const META_SCHEMA_VERSION: u32 = 1;
+const LEGACY_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 
-fn get_srm_base() -> PathBuf {
+// 可执行文件同级的 .srm；`srm store move` 之后它是指向新位置的软链接
+fn srm_link_path() -> PathBuf {
     let exe_path = std::env::current_exe().expect("Failed to get srm executable path");
     let exe_dir = exe_path.parent().expect("Failed to get srm parent directory");
     exe_dir.join(".srm")
 }
 
+// 存储根目录的真实路径：修改操作按不跟随软链接的方式打开父目录，必须使用解析后的路径
+fn get_srm_base() -> PathBuf {
+    let link = srm_link_path();
+    link.canonicalize().unwrap_or(link)
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct LogEntry {
     timestamp: String,
@@ -54,10 +87,11 @@ fn secure_create_file(path: &Path) -> io::Result<std::fs::File> {
     let file = fs::OpenOptions::new()
         .create(true)
         .append(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
         .open(path)?;
-    let mut perms = fs::metadata(path)?.permissions();
-    perms.set_mode(0o600);
-    fs::set_permissions(path, perms)?;
+    // 已存在的文件可能是旧版本以默认权限创建的，经 fd 收紧
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
     Ok(file)
 }
 
@@ -84,6 +118,12 @@ fn log_event(level: &str, message: &str, details: Option<serde_json::Value>) {
     }
 }
 
+// 超过保留期仍要留下的记录：回收项还在 trash/ 中时，rebuild-meta 靠它的删除/导入记录重建元数据
+fn outlives_rotation(entry: &LogEntry, in_trash: &HashSet<String>) -> bool {
+    matches!(entry.message.as_str(), "File deleted" | "File imported")
+        && entry.details.as_ref().and_then(|d| d.get("trash_id")).and_then(|id| id.as_str()).is_some_and(|id| in_trash.contains(id))
+}
+
 fn rotate_logs(base: &Path) {
     let log_path = base.join("srm.log");
     if !log_path.exists() {
@@ -105,6 +145,8 @@ fn rotate_logs(base: &Path) {
 
     let mut reader = BufReader::new(src_file);
     let mut line = String::new();
+    // 只在遇到过期的删除记录时才列出回收站
+    let mut in_trash: Option<HashSet<String>> = None;
 
     while reader.read_line(&mut line).unwrap_or(0) > 0 {
         let trimmed = line.trim();
@@ -115,11 +157,11 @@ fn rotate_logs(base: &Path) {
 
         if let Ok(entry) = serde_json::from_str::<LogEntry>(trimmed) {
             if let Ok(ts) = NaiveDateTime::parse_from_str(&entry.timestamp, "%Y-%m-%d %H:%M:%S%.3f") {
-                match Local.from_local_datetime(&ts) {
-                    LocalResult::Single(dt) if dt >= cutoff => {
-                        let _ = writeln!(dst_file, "{}", trimmed);
-                    }
-                    _ => (),
+                let keep = local_to_utc(&ts) >= cutoff || outlives_rotation(&entry, in_trash.get_or_insert_with(|| {
+                    store_entries(&base.join("trash")).iter().filter_map(|p| Some(p.file_name()?.to_string_lossy().into_owned())).collect()
+                }));
+                if keep {
+                    let _ = writeln!(dst_file, "{}", trimmed);
                 }
             }
         }
@@ -130,47 +172,27 @@ fn rotate_logs(base: &Path) {
     let _ = fs::rename(&temp_log, &log_path);
 }
 
-fn same_filesystem(path1: &Path, path2: &Path) -> bool {
-    match (fs::metadata(path1), fs::metadata(path2)) {
-        (Ok(m1), Ok(m2)) => m1.dev() == m2.dev(),
-        _ => false,
-    }
-}
-
-fn canonicalize_safe(path: &Path) -> io::Result<PathBuf> {
-    let abs_path = if path.is_absolute() {
-        path.to_path_buf()
-    } else {
-        std::env::current_dir()?.join(path)
-    };
-    abs_path.canonicalize()
-}
-
 #[cfg(target_os = "linux")]
-fn try_reflink_copy(src: &Path, dst: &Path) -> io::Result<bool> {
+fn try_reflink_copy(src: &fs::File, dst: &fs::File) -> bool {
     use std::os::unix::io::AsRawFd;
-    let src_file = fs::File::open(src)?;
-    let dst_file = fs::File::create(dst)?;
 
     const FICLONE: u64 = 0x40049409;
     let ret = unsafe {
-        libc::ioctl(dst_file.as_raw_fd(), FICLONE as _, src_file.as_raw_fd())
+        libc::ioctl(dst.as_raw_fd(), FICLONE as _, src.as_raw_fd())
     };
 
-    Ok(ret == 0)
+    ret == 0
 }
 
 #[cfg(not(target_os = "linux"))]
-fn try_reflink_copy(_src: &Path, _dst: &Path) -> io::Result<bool> {
-    Ok(false)
+fn try_reflink_copy(_src: &fs::File, _dst: &fs::File) -> bool {
+    false
 }
 
-fn mmap_copy(src: &Path, dst: &Path, size: u64, show_progress: bool) -> io::Result<u64> {
-    let src_file = fs::File::open(src)?;
-    let mut dst_file = fs::File::create(dst)?;
+fn mmap_copy(src_file: &fs::File, dst_file: &mut fs::File, size: u64, show_progress: bool) -> io::Result<u64> {
     dst_file.set_len(size)?;
 
-    let mmap = unsafe { MmapOptions::new().map(&src_file)? };
+    let mmap = unsafe { MmapOptions::new().map(src_file)? };
     let mut offset = 0usize;
 
     let pb = if show_progress && size > PROGRESS_THRESHOLD_BYTES {
@@ -213,29 +235,65 @@ fn mmap_copy(src: &Path, dst: &Path, size: u64, show_progress: bool) -> io::Resu
     result
 }
 
-fn fast_file_copy(src: &Path, dst: &Path, show_progress: bool) -> io::Result<u64> {
-    let src_meta = fs::metadata(src)?;
-    let size = src_meta.len();
+// 只复制 SEEK_DATA 找到的数据段，空洞保持为空洞，副本的实际占用与源文件一致
+fn sparse_copy(src: &fs::File, dst: &fs::File, size: u64) -> io::Result<u64> {
+    use std::os::unix::fs::FileExt;
+    use std::os::unix::io::AsRawFd;
 
-    #[cfg(target_os = "linux")]
-    {
-        if try_reflink_copy(src, dst)? {
-            return Ok(size);
+    dst.set_len(size)?;
+    let fd = src.as_raw_fd();
+    let mut buffer = vec![0u8; MMAP_CHUNK_SIZE];
+    let mut offset = 0u64;
+    while offset < size {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "Operation interrupted"));
         }
+        let (start, end) = match unsafe { libc::lseek(fd, offset as libc::off_t, libc::SEEK_DATA) } {
+            data if data >= 0 => {
+                let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+                (data as u64, if hole < 0 { size } else { cmp::min(hole as u64, size) })
+            }
+            _ => match io::Error::last_os_error().raw_os_error() {
+                // 之后全是空洞
+                Some(libc::ENXIO) => break,
+                // 文件系统不支持 SEEK_DATA：剩余部分按数据处理
+                Some(libc::EINVAL) => (offset, size),
+                _ => return Err(io::Error::last_os_error()),
+            },
+        };
+        let mut pos = start;
+        while pos < end {
+            let want = cmp::min(buffer.len() as u64, end - pos) as usize;
+            let n = src.read_at(&mut buffer[..want], pos)?;
+            if n == 0 {
+                break;
+            }
+            dst.write_all_at(&buffer[..n], pos)?;
+            pos += n as u64;
+        }
+        offset = end;
     }
+    Ok(size)
+}
 
-    if same_filesystem(src, dst) {
-        if fs::hard_link(src, dst).is_ok() {
-            return Ok(size);
-        }
+fn fast_file_copy(src: &fs::File, dst: &mut fs::File, size: u64, show_progress: bool) -> io::Result<u64> {
+    if try_reflink_copy(src, dst) {
+        return Ok(size);
+    }
+
+    // 计划按 reflink 或 rename 计算时没有为这份数据预留空间（reflink 失败、共享设备号的绑定挂载之间 EXDEV 等），复制前再检查一次
+    let allocated = src.metadata()?.blocks() * 512;
+    check_space(available_space_fd(dst)?, allocated.min(size), true)?;
+
+    if src.metadata()?.blocks() * 512 < size {
+        return sparse_copy(src, dst, size);
     }
 
     if size > 10 * 1024 * 1024 {
         return mmap_copy(src, dst, size, show_progress);
     }
 
-    let mut reader = fs::File::open(src)?;
-    let mut writer = fs::File::create(dst)?;
+    let mut reader = src;
 
     if show_progress && size > PROGRESS_THRESHOLD_BYTES {
         let pb = ProgressBar::new(size);
@@ -255,155 +313,567 @@ fn fast_file_copy(src: &Path, dst: &Path, show_progress: bool) -> io::Result<u64
 
             let n = reader.read(&mut buffer)?;
             if n == 0 { break; }
-            writer.write_all(&buffer[..n])?;
+            dst.write_all(&buffer[..n])?;
             total += n as u64;
             pb.set_position(total);
         }
         pb.finish_with_message("Done");
         Ok(total)
     } else {
-        io::copy(&mut reader, &mut writer)
+        io::copy(&mut reader, dst)
     }
 }
 
-fn safe_move_with_progress(src: &Path, dst: &Path, show_progress: bool) -> io::Result<u64> {
-    let src_meta = fs::symlink_metadata(src)?;
-    let src_size = src_meta.len();
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum VerifyMode {
+    /// fsync + size check for copies above VERIFY_THRESHOLD_BYTES
+    Auto,
+    /// fsync + size check for every copied file
+    Size,
+    /// fsync + size check + BLAKE3 comparison of source and copy
+    Hash,
+    /// Trust the copy (previous behaviour)
+    Off,
+}
 
-    if fs::rename(src, dst).is_ok() {
-        return Ok(src_size);
+impl std::fmt::Display for VerifyMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            VerifyMode::Auto => write!(f, "auto"),
+            VerifyMode::Size => write!(f, "size"),
+            VerifyMode::Hash => write!(f, "hash"),
+            VerifyMode::Off => write!(f, "off"),
+        }
     }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ChecksumMode {
+    /// Checksum data copied to another filesystem while it is copied; renamed items are not read
+    Auto,
+    /// Also read items renamed in place to checksum them
+    Always,
+    /// Record no checksums
+    Off,
+}
 
-    if src_meta.file_type().is_symlink() {
-        let target = fs::read_link(src)?;
-        std::os::unix::fs::symlink(target, dst)?;
-        fs::remove_file(src)?;
-        return Ok(0);
+impl std::fmt::Display for ChecksumMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ChecksumMode::Auto => write!(f, "auto"),
+            ChecksumMode::Always => write!(f, "always"),
+            ChecksumMode::Off => write!(f, "off"),
+        }
     }
+}
 
-    if src_meta.is_dir() {
-        return move_directory_with_progress(src, dst, show_progress);
+fn hash_file(path: &Path) -> io::Result<blake3::Hash> {
+    hash_reader(fs::File::open(path)?)
+}
+
+fn hash_reader(mut file: fs::File) -> io::Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = vec![0u8; 256 * 1024];
+    loop {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "Operation interrupted"));
+        }
+        let n = file.read(&mut buffer)?;
+        if n == 0 { break; }
+        hasher.update(&buffer[..n]);
     }
+    Ok(hasher.finalize())
+}
 
-    let size = fast_file_copy(src, dst, show_progress)?;
-    fs::remove_file(src)?;
-    Ok(size)
+// 从头重新读取已打开的文件（与原 fd 共享偏移量）
+fn rewound(file: &fs::File) -> io::Result<fs::File> {
+    let mut file = file.try_clone()?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
 }
 
-fn is_dir_empty(path: &Path) -> io::Result<bool> {
-    let mut entries = fs::read_dir(path)?;
-    Ok(entries.next().is_none())
+// 源文件被删除前，确认副本已落盘且内容完整；按哈希校验时返回源文件的摘要
+fn verify_copy(src: &fs::File, dst: &fs::File, dst_dir: &at::Dir, name: &OsStr, copied: u64, mode: VerifyMode) -> io::Result<Option<blake3::Hash>> {
+    let enabled = match mode {
+        VerifyMode::Off => false,
+        VerifyMode::Auto => copied >= VERIFY_THRESHOLD_BYTES,
+        VerifyMode::Size | VerifyMode::Hash => true,
+    };
+    if !enabled {
+        return Ok(None);
+    }
+
+    dst.sync_all()?;
+    dst_dir.sync()?;
+
+    let src_len = src.metadata()?.len();
+    let dst_len = dst.metadata()?.len();
+    if src_len != dst_len || copied != src_len {
+        return Err(io::Error::other(format!(
+            "Verification failed for '{}': source is {} bytes, copy is {} bytes ({} written)",
+            name.to_string_lossy(), src_len, dst_len, copied)));
+    }
+
+    if mode == VerifyMode::Hash {
+        // 丢弃页缓存，让哈希读到真正写入磁盘的数据
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::io::AsRawFd;
+            unsafe { libc::posix_fadvise(dst.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED); }
+        }
+        let src_hash = hash_reader(rewound(src)?)?;
+        let dst_hash = hash_reader(rewound(dst)?)?;
+        if src_hash != dst_hash {
+            return Err(io::Error::other(format!(
+                "Verification failed for '{}': content hash mismatch ({} vs {})",
+                name.to_string_lossy(), src_hash.to_hex(), dst_hash.to_hex())));
+        }
+        return Ok(Some(src_hash));
+    }
+
+    Ok(None)
 }
 
-fn move_directory_with_progress(src: &Path, dst: &Path, show_progress: bool) -> io::Result<u64> {
-    fs::create_dir_all(dst)?;
+fn safe_move_with_progress(src: &Path, dst: &Path, show_progress: bool, verify: VerifyMode) -> io::Result<u64> {
+    safe_move_hashed(src, dst, show_progress, verify, ChecksumMode::Off).map(|(size, _)| size)
+}
 
-    let mut stack: Vec<(PathBuf, PathBuf, usize)> = vec![(src.to_path_buf(), dst.to_path_buf(), 0)];
-    let mut total_size = 0u64;
-    let mut processed_items = 0usize;
-    let mut pb: Option<ProgressBar> = None;
+fn safe_move_hashed(src: &Path, dst: &Path, show_progress: bool, verify: VerifyMode, checksum: ChecksumMode) -> io::Result<(u64, Hashed)> {
+    let (src_dir, src_name) = at::Dir::open_parent(src)?;
+    let (dst_dir, dst_name) = at::Dir::open_parent(dst)?;
+    move_hashed(&src_dir, &src_name, &dst_dir, &dst_name, show_progress, verify, checksum)
+}
+
+// 移动时得到的内容摘要：文件、软链接与节点为单个摘要，目录为逐项清单（与 compute_manifest 相同）
+enum Digest {
+    Item(String),
+    Tree(BTreeMap<String, String>),
+}
+
+// None 表示没有计算；计算失败不影响已完成的移动
+type Hashed = Option<io::Result<Digest>>;
+
+// 读取已移到目标处的一项计算摘要
+fn digest_at(dir: &at::Dir, name: &OsStr) -> io::Result<Digest> {
+    let stat = dir.stat(name)?;
+    Ok(if stat.is_dir() {
+        Digest::Tree(dir_manifest(&dir.open_dir(name)?)?)
+    } else if stat.is_symlink() {
+        Digest::Item(blake3::hash(dir.read_link(name)?.as_os_str().as_bytes()).to_hex().to_string())
+    } else if stat.is_node() {
+        Digest::Item(node_hash(FileType::from_mode(stat.mode()), stat.rdev()))
+    } else {
+        Digest::Item(hash_reader(dir.open_file(name)?)?.to_hex().to_string())
+    })
+}
+
+fn move_at(src_dir: &at::Dir, src_name: &OsStr, dst_dir: &at::Dir, dst_name: &OsStr, show_progress: bool, verify: VerifyMode) -> io::Result<u64> {
+    move_hashed(src_dir, src_name, dst_dir, dst_name, show_progress, verify, ChecksumMode::Off).map(|(size, _)| size)
+}
+
+// 在两个目录 fd 之间移动一项：同一文件系统直接 renameat，否则复制、校验后删除源
+// 复制时摘要按源数据计算（刚被复制读过，仍在页缓存中），副本损坏时恢复与 verify 能够发现；
+// rename 不经过复制，只在 Always 时读取移动后的数据
+fn move_hashed(src_dir: &at::Dir, src_name: &OsStr, dst_dir: &at::Dir, dst_name: &OsStr, show_progress: bool,
+               verify: VerifyMode, checksum: ChecksumMode) -> io::Result<(u64, Hashed)> {
+    let src_meta = src_dir.stat(src_name)?;
+    let src_size = src_meta.len();
+
+    if src_dir.rename(src_name, dst_dir, dst_name).is_ok() {
+        return Ok((src_size, (checksum == ChecksumMode::Always).then(|| digest_at(dst_dir, dst_name))));
+    }
+    let hashing = checksum != ChecksumMode::Off;
+
+    if src_meta.is_symlink() {
+        let target = src_dir.read_link(src_name)?;
+        dst_dir.symlink(&target, dst_name)?;
+        src_dir.remove_file(src_name)?;
+        return Ok((0, hashing.then(|| Ok(Digest::Item(blake3::hash(target.as_os_str().as_bytes()).to_hex().to_string())))));
+    }
 
-    if show_progress {
-        let (bytes, items) = calculate_dir_stats(src)?;
-        if bytes > PROGRESS_THRESHOLD_BYTES || items > PROGRESS_THRESHOLD_ITEMS {
-            let mp = MultiProgress::new();
-            let progress_bar = mp.add(ProgressBar::new(items as u64));
-            progress_bar.set_style(ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] {pos}/{len} items [{wide_bar:.cyan/blue}] {percent}% ({eta})")
-                .unwrap()
-                .progress_chars("█▓▒░ "));
-            progress_bar.set_position(0);
-            progress_bar.set_message(format!("Moving: {}", src.file_name().unwrap_or_default().to_string_lossy()));
-            pb = Some(progress_bar);
+    // FIFO、套接字与设备节点没有可复制的数据（打开 FIFO 会一直阻塞），在目标处按类型与设备号重建
+    if src_meta.is_node() {
+        dst_dir.mknod(dst_name, src_meta.mode(), src_meta.rdev())?;
+        if let Err(e) = dst_dir.set_mode(dst_name, src_meta.mode()).and_then(|_| src_dir.remove_file(src_name)) {
+            let _ = dst_dir.remove_file(dst_name);
+            return Err(e);
         }
+        let digest = node_hash(FileType::from_mode(src_meta.mode()), src_meta.rdev());
+        return Ok((0, hashing.then_some(Ok(Digest::Item(digest)))));
     }
 
-    while let Some((current_src, current_dst, depth)) = stack.pop() {
-        if depth > MAX_RECURSION_DEPTH {
-            return Err(io::Error::new(io::ErrorKind::Other, "Directory depth exceeds safety limit (1000)"));
+    if src_meta.is_dir() {
+        return move_directory_with_progress(src_dir, src_name, dst_dir, dst_name, show_progress, verify, checksum);
+    }
+
+    let src_file = src_dir.open_file(src_name)?;
+    let mut dst_file = dst_dir.create_file(dst_name, src_meta.mode() & 0o7777)?;
+    let result = fast_file_copy(&src_file, &mut dst_file, src_size, show_progress)
+        .and_then(|size| verify_copy(&src_file, &dst_file, dst_dir, src_name, size, verify).map(|hash| (size, hash)));
+    let (size, verified) = match result {
+        Ok(r) => r,
+        Err(e) => {
+            let _ = dst_dir.remove_file(dst_name);
+            return Err(e);
         }
+    };
+    // 按哈希校验时已经算过源文件的摘要
+    let digest = hashing.then(|| match verified {
+        Some(hash) => Ok(hash),
+        None => rewound(&src_file).and_then(hash_reader),
+    }.map(|hash| Digest::Item(hash.to_hex().to_string())));
+    src_dir.remove_file(src_name)?;
+    Ok((size, digest))
+}
 
-        if INTERRUPTED.load(Ordering::Relaxed) {
-            if let Some(p) = &pb {
-                p.abandon_with_message("Interrupted");
+// 以不跟随软链接的方式重新打开父目录，并确认待删项仍是检查时的那个文件，再移入回收站
+// 目录已在计划阶段扫描过时，rename 失败后直接按扫描结果逐项移动，不再重新遍历
+fn move_to_trash(path: &Path, checked: &fs::Metadata, tree: Option<&scan::Tree>, trash_path: &Path, show_progress: bool,
+                 verify: VerifyMode, checksum: ChecksumMode) -> io::Result<(u64, Hashed)> {
+    let (src_dir, src_name) = at::Dir::open_parent(path)?;
+    let stat = src_dir.stat(&src_name)?;
+    if stat.dev() != checked.dev() || stat.ino() != checked.ino() {
+        return Err(io::Error::other(format!("'{}' was replaced after it was checked", path.display())));
+    }
+    let (dst_dir, dst_name) = at::Dir::open_parent(trash_path)?;
+    match tree {
+        Some(tree) if stat.is_dir() => {
+            // 树中有其他文件系统时不能整体 rename，挂载会随目录一起移走
+            if tree.mounts.is_empty() && src_dir.rename(&src_name, &dst_dir, &dst_name).is_ok() {
+                return Ok((tree.usage.apparent, (checksum == ChecksumMode::Always).then(|| digest_at(&dst_dir, &dst_name))));
             }
-            return Err(io::Error::new(io::ErrorKind::Interrupted, "Operation interrupted"));
+            move_tree(&src_dir, &src_name, &dst_dir, &dst_name, tree, show_progress, verify, checksum)
         }
+        _ => move_hashed(&src_dir, &src_name, &dst_dir, &dst_name, show_progress, verify, checksum),
+    }
+}
 
-        if let Ok(entries) = fs::read_dir(&current_src) {
-            for entry in entries.flatten() {
-                let src_path = entry.path();
-                let dst_path = current_dst.join(entry.file_name());
+// 创建目录，已存在同名目录时直接使用
+fn ensure_dir(parent: &at::Dir, name: &OsStr, mode: u32) -> io::Result<at::Dir> {
+    match parent.create_dir(name, mode) {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        other => other?,
+    }
+    parent.open_dir(name)
+}
 
-                if let Ok(meta) = fs::symlink_metadata(&src_path) {
-                    if meta.is_dir() {
-                        fs::create_dir_all(&dst_path)?;
-                        stack.push((src_path, dst_path, depth + 1));
-                    } else {
-                        let size = safe_move_with_progress(&src_path, &dst_path, false)?;
-                        total_size += size;
-                        processed_items += 1;
+// 目录移动中途失败时，把已搬到目标处的内容放回原处，保证该项保持原样
+fn undo_partial_move(moved: &Path, original: &Path, verify: VerifyMode) -> io::Result<()> {
+    let (moved_parent, moved_name) = at::Dir::open_parent(moved)?;
+    let (orig_parent, orig_name) = at::Dir::open_parent(original)?;
+    let moved_root = moved_parent.open_dir(&moved_name)?;
+    let mode = moved_root.metadata()?.mode() & 0o7777;
+    let mut orig_stack = vec![ensure_dir(&orig_parent, &orig_name, mode)?];
+
+    at::walk(&moved_root, MAX_RECURSION_DEPTH, |event| match event {
+        at::Event::Entry { parent, name, stat, .. } => {
+            let orig = orig_stack.last().unwrap();
+            if stat.is_dir() {
+                let dir = ensure_dir(orig, name, stat.mode() & 0o7777)?;
+                orig_stack.push(dir);
+            } else if orig.stat(name).is_err() {
+                move_at(parent, name, orig, name, false, verify)?;
+            }
+            Ok(())
+        }
+        at::Event::Leave { parent, name, .. } => {
+            orig_stack.pop();
+            let _ = parent.remove_dir(name);
+            Ok(())
+        }
+        at::Event::Unreadable { error, .. } => Err(error),
+    })?;
+
+    drop(moved_root);
+    let _ = moved_parent.remove_dir(&moved_name);
+    Ok(())
+}
+
+fn remove_moved_dir(parent: &at::Dir, name: &OsStr, rel: &Path) -> io::Result<()> {
+    match parent.remove_dir(name) {
+        Err(e) if e.raw_os_error() == Some(libc::ENOTEMPTY) => {
+            Err(io::Error::other(format!("Failed to delete non-empty directory: {}", rel.display())))
+        }
+        other => other,
+    }
+}
 
-                        if let Some(p) = &pb {
-                            p.inc(1);
+fn move_directory_with_progress(src_dir: &at::Dir, src_name: &OsStr, dst_dir: &at::Dir, dst_name: &OsStr, show_progress: bool,
+                                verify: VerifyMode, checksum: ChecksumMode) -> io::Result<(u64, Hashed)> {
+    let tree = scan::scan(&src_dir.open_dir(src_name)?, 1, None)?;
+    move_tree(src_dir, src_name, dst_dir, dst_name, &tree, show_progress, verify, checksum)
+}
+
+// 按扫描记录的顺序把目录树搬到目标处：目录在进入前创建、在其内容搬完后（Leave）删除
+// 文件系统边界的上级目录与挂载点本身留在原处，不删除；需要摘要时逐项记入清单
+#[allow(clippy::too_many_arguments)]
+fn move_tree(src_dir: &at::Dir, src_name: &OsStr, dst_dir: &at::Dir, dst_name: &OsStr, tree: &scan::Tree, show_progress: bool,
+             verify: VerifyMode, checksum: ChecksumMode) -> io::Result<(u64, Hashed)> {
+    let mut kept: HashSet<&Path> = HashSet::new();
+    for b in &tree.mounts {
+        kept.extend(b.rel.ancestors().skip(usize::from(!b.included)));
+    }
+    let mut src_stack = vec![src_dir.open_dir(src_name)?];
+    let mut dst_stack = vec![ensure_dir(dst_dir, dst_name, 0o777)?];
+    let mut rel = PathBuf::new();
+    // 已复制的多链接文件 (dev, ino) -> (副本相对目标根目录的路径, 摘要)；后续链接在目标处重建为硬链接
+    let mut links: HashMap<(u64, u64), (PathBuf, Option<String>)> = HashMap::new();
+    // 清单不完整时没有意义，任何一项计算失败都记为整体失败；其中 rename 的条目也要读取
+    let mut manifest: Option<io::Result<BTreeMap<String, String>>> = (checksum != ChecksumMode::Off).then(|| Ok(BTreeMap::new()));
+    let item_checksum = if checksum == ChecksumMode::Off { ChecksumMode::Off } else { ChecksumMode::Always };
+    let mut total_size = 0u64;
+    let mut processed_items = 0usize;
+    let mut pb: Option<ProgressBar> = None;
+
+    if show_progress && (tree.usage.apparent > PROGRESS_THRESHOLD_BYTES || tree.usage.items > PROGRESS_THRESHOLD_ITEMS) {
+        let mp = MultiProgress::new();
+        let progress_bar = mp.add(ProgressBar::new(tree.usage.items as u64));
+        progress_bar.set_style(ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] {pos}/{len} items [{wide_bar:.cyan/blue}] {percent}% ({eta})")
+            .unwrap()
+            .progress_chars("█▓▒░ "));
+        progress_bar.set_position(0);
+        progress_bar.set_message(format!("Moving: {}", src_name.to_string_lossy()));
+        pb = Some(progress_bar);
+    }
+
+    // 扫描后整棵消失的子目录：跳过其记录直到对应的 Leave
+    let mut skipping = 0usize;
+    let mut replay = || -> io::Result<()> {
+        for step in &tree.steps {
+            if INTERRUPTED.load(Ordering::Relaxed) {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "Operation interrupted"));
+            }
+            if skipping > 0 {
+                match step {
+                    scan::Step::Dir(..) => skipping += 1,
+                    scan::Step::Leave => skipping -= 1,
+                    scan::Step::Item(..) | scan::Step::Mount => {}
+                }
+                continue;
+            }
+            match step {
+                scan::Step::Dir(name, _) => match src_stack.last().unwrap().open_dir(name) {
+                    Ok(src) => {
+                        dst_stack.push(ensure_dir(dst_stack.last().unwrap(), name, 0o777)?);
+                        src_stack.push(src);
+                        rel.push(name);
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => skipping = 1,
+                    Err(e) => {
+                        return Err(io::Error::new(e.kind(), format!("Failed to read {}: {}", rel.join(name).display(), e)));
+                    }
+                },
+                scan::Step::Item(name, stat) => {
+                    let (parent, dst) = (src_stack.last().unwrap(), dst_stack.last().unwrap());
+                    let key = (stat.dev(), stat.ino());
+                    // 前面的链接已被移走，此时 nlink 可能已降为 1，所以按 inode 查找
+                    let linked = links.get(&key).filter(|(first, _)| !stat.is_symlink() && dst_stack[0].link(first, dst, name).is_ok());
+                    let digest = if let Some((_, digest)) = linked {
+                        parent.remove_file(name)?;
+                        digest.clone().map(|d| Ok(Digest::Item(d)))
+                    } else {
+                        let digest = match move_hashed(parent, name, dst, name, false, verify, item_checksum) {
+                            Ok((size, digest)) => {
+                                total_size += size;
+                                digest
+                            }
+                            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                            Err(e) => return Err(e),
+                        };
+                        if stat.nlink() > 1 && !stat.is_symlink() {
+                            let recorded = match &digest {
+                                Some(Ok(Digest::Item(d))) => Some(d.clone()),
+                                _ => None,
+                            };
+                            links.insert(key, (rel.join(name), recorded));
+                        }
+                        digest
+                    };
+                    match (digest, manifest.as_mut()) {
+                        (Some(Ok(Digest::Item(d))), Some(Ok(m))) => {
+                            m.insert(manifest_key(&rel.join(name)), d);
                         }
+                        (Some(Err(e)), Some(Ok(_))) => manifest = Some(Err(e)),
+                        // 扫描后变成了目录
+                        (Some(Ok(Digest::Tree(_))), Some(Ok(_))) => {
+                            manifest = Some(Err(io::Error::other(format!("{} changed while moving", rel.join(name).display()))));
+                        }
+                        _ => {}
+                    }
+                    processed_items += 1;
+                    if let Some(p) = &pb {
+                        p.inc(1);
+                    }
+                }
+                scan::Step::Mount => {}
+                scan::Step::Leave => {
+                    src_stack.pop();
+                    dst_stack.pop();
+                    if !kept.contains(rel.as_path()) {
+                        let name = rel.file_name().map(OsStr::to_os_string).unwrap_or_default();
+                        remove_moved_dir(src_stack.last().unwrap(), &name, &rel)?;
                     }
+                    rel.pop();
                 }
             }
         }
-
-        if is_dir_empty(&current_src)? {
-            fs::remove_dir(&current_src)?;
-        } else {
-            return Err(io::Error::new(io::ErrorKind::Other, format!("Failed to delete non-empty directory: {}", current_src.display())));
+        Ok(())
+    };
+    if let Err(e) = replay() {
+        if let Some(p) = &pb {
+            p.abandon_with_message("Interrupted");
         }
+        return Err(e);
+    }
+
+    drop(src_stack);
+    if tree.mounts.is_empty() {
+        remove_moved_dir(src_dir, src_name, Path::new(src_name))?;
     }
 
     if let Some(p) = pb {
         p.finish_with_message(format!("Done ({} items, {})", processed_items, ByteSize(total_size)));
     }
 
-    Ok(total_size)
+    Ok((total_size, manifest.map(|m| m.map(Digest::Tree))))
 }
 
-fn calculate_dir_stats(path: &Path) -> io::Result<(u64, usize)> {
-    let mut stack = vec![path.to_path_buf()];
-    let mut total_size = 0u64;
-    let mut total_items = 0usize;
+// apparent 为文件长度之和（稀疏文件按长度计），allocated 为实际占用的块（含目录本身）；
+// 同一 inode 的多个硬链接只计一次
+#[derive(Debug, Clone, Copy, Default)]
+struct DiskUsage {
+    apparent: u64,
+    allocated: u64,
+    items: usize,
+}
+
+impl DiskUsage {
+    fn add(&mut self, stat: &at::Stat, seen: &mut HashSet<(u64, u64)>) {
+        self.items += 1;
+        if stat.is_dir() {
+            self.allocated += stat.allocated();
+        } else if stat.nlink() <= 1 || seen.insert((stat.dev(), stat.ino())) {
+            self.apparent += stat.len();
+            self.allocated += stat.allocated();
+        }
+    }
+}
 
-    while let Some(current) = stack.pop() {
+fn calculate_dir_stats(path: &Path) -> io::Result<DiskUsage> {
+    dir_stats(&at::Dir::open(path)?)
+}
+
+fn path_usage(path: &Path) -> io::Result<DiskUsage> {
+    let meta = fs::symlink_metadata(path)?;
+    if meta.is_dir() {
+        calculate_dir_stats(path)
+    } else {
+        Ok(DiskUsage { apparent: meta.len(), allocated: meta.blocks() * 512, items: 0 })
+    }
+}
+
+fn dir_stats(root: &at::Dir) -> io::Result<DiskUsage> {
+    let mut usage = DiskUsage { allocated: root.metadata()?.allocated(), ..Default::default() };
+    let mut seen = HashSet::new();
+
+    // 无法读取的子目录不计入，与移动前的估算用途相符
+    at::walk(root, MAX_RECURSION_DEPTH, |event| {
         if INTERRUPTED.load(Ordering::Relaxed) {
             return Err(io::Error::new(io::ErrorKind::Interrupted, "Operation interrupted"));
         }
-        if let Ok(entries) = fs::read_dir(&current) {
-            for entry in entries.flatten() {
-                total_items += 1;
-                if let Ok(meta) = fs::symlink_metadata(entry.path()) {
-                    if meta.is_dir() {
-                        stack.push(entry.path());
-                    } else {
-                        total_size += meta.len();
-                    }
-                }
-            }
+        if let at::Event::Entry { stat, .. } = event {
+            usage.add(stat, &mut seen);
         }
-    }
+        Ok(())
+    })?;
 
-    Ok((total_size, total_items))
+    Ok(usage)
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct FileMeta {
+    #[serde(default)]
+    schema_version: u32,
     original_path: String,
+    // 相对存储根目录（如 "trash/<回收ID>"），整个 .srm 可以被复制或移动；旧版本记录的是绝对路径
     trash_path: String,
-    delete_time: String,
+    // 路径不是合法 UTF-8 时，上面两项只是替换后的显示形式，原始字节以 base64 保存在这里
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    original_path_b64: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trash_path_b64: Option<String>,
+    delete_time: DateTime<Utc>,
+    expire_time: DateTime<Utc>,
     expire_days: i64,
     file_type: FileType,
+    // 设备节点的设备号，跨设备移动与恢复时据此重建
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rdev: Option<u64>,
     permissions: Option<u32>,
     uid: Option<u32>,
     gid: Option<u32>,
     short_id: String,
     size_bytes: u64,
+    // 实际占用的磁盘空间（st_blocks * 512，硬链接去重）；旧元数据没有此项
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    allocated_bytes: Option<u64>,
+    #[serde(default)]
+    checksum: Option<String>,
+    // 删除时目录是、包含或位于 git 工作区时的检查结果
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    git: Option<git::Summary>,
+    // 删除时留在原处的文件系统边界；非空时原目录仍然存在，恢复时合并回去
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    mounts: Vec<plan::Boundary>,
+    // 删除前清除的不可变与仅追加属性，恢复时重新设置
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    attrs: Vec<attrs::Locked>,
+}
+
+impl FileMeta {
+    fn original(&self) -> PathBuf {
+        decode_path(&self.original_path, self.original_path_b64.as_deref())
+    }
+
+    // trash_dir 为当前存储的 trash/，其父目录即存储根目录
+    fn trash(&self, trash_dir: &Path) -> PathBuf {
+        let path = decode_path(&self.trash_path, self.trash_path_b64.as_deref());
+        if path.is_relative() {
+            return trash_dir.parent().unwrap_or(trash_dir).join(path);
+        }
+        // 旧版绝对路径：存储被复制或移动过时，优先使用当前存储中同名的回收项
+        match path.file_name() {
+            Some(name) if trash_dir.join(name).symlink_metadata().is_ok() || path.symlink_metadata().is_err() => {
+                trash_dir.join(name)
+            }
+            _ => path,
+        }
+    }
+
+    // 旧元数据没有记录实际占用，按长度估计
+    fn allocated(&self) -> u64 {
+        self.allocated_bytes.unwrap_or(self.size_bytes)
+    }
+
+    fn set_trash(&mut self, rel: &Path) {
+        (self.trash_path, self.trash_path_b64) = encode_path(rel);
+    }
+}
+
+// 路径 -> (显示形式, 原始字节的 base64)；合法 UTF-8 路径不需要后者
+fn encode_path(path: &Path) -> (String, Option<String>) {
+    let bytes = path.as_os_str().as_bytes();
+    match std::str::from_utf8(bytes) {
+        Ok(s) => (s.to_string(), None),
+        Err(_) => (path.to_string_lossy().into_owned(), Some(BASE64.encode(bytes))),
+    }
+}
+
+fn decode_path(display: &str, b64: Option<&str>) -> PathBuf {
+    b64.and_then(|b| BASE64.decode(b).ok())
+        .map(|bytes| PathBuf::from(OsString::from_vec(bytes)))
+        .unwrap_or_else(|| PathBuf::from(display))
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
@@ -411,6 +881,35 @@ enum FileType {
     File,
     Dir,
     Symlink,
+    Fifo,
+    Socket,
+    CharDevice,
+    BlockDevice,
+}
+
+impl FileType {
+    // st_mode 中的文件类型位
+    fn from_mode(mode: u32) -> FileType {
+        match mode & libc::S_IFMT {
+            libc::S_IFDIR => FileType::Dir,
+            libc::S_IFLNK => FileType::Symlink,
+            libc::S_IFIFO => FileType::Fifo,
+            libc::S_IFSOCK => FileType::Socket,
+            libc::S_IFCHR => FileType::CharDevice,
+            libc::S_IFBLK => FileType::BlockDevice,
+            _ => FileType::File,
+        }
+    }
+
+    // 不跟随软链接取得的元数据
+    fn of(meta: &fs::Metadata) -> FileType {
+        FileType::from_mode(meta.mode())
+    }
+
+    // 设备节点的设备号，其余类型为 None
+    fn device(meta: &fs::Metadata) -> Option<u64> {
+        matches!(FileType::of(meta), FileType::CharDevice | FileType::BlockDevice).then(|| meta.rdev())
+    }
 }
 
 impl std::fmt::Display for FileType {
@@ -419,50 +918,371 @@ impl std::fmt::Display for FileType {
             FileType::File => write!(f, "file"),
             FileType::Dir => write!(f, "dir"),
             FileType::Symlink => write!(f, "symlink"),
+            FileType::Fifo => write!(f, "fifo"),
+            FileType::Socket => write!(f, "socket"),
+            FileType::CharDevice => write!(f, "chardev"),
+            FileType::BlockDevice => write!(f, "blockdev"),
         }
     }
 }
 
+// 先写 <名称>.<PID>-<序号>.tmp 再 renameat 替换。临时文件以 O_EXCL|O_NOFOLLOW、0600 新建，
+// 名称已被占用（其他写入者、崩溃残留或预先放置的软链接）时换下一个序号，从不删除已有文件
+fn atomic_write(dir: &Path, name: &str, content: &[u8]) -> io::Result<()> {
+    let dir = at::Dir::open_no_symlinks(dir)?;
+    let (tmp_name, mut tmp_file) = loop {
+        let tmp_name = OsString::from(format!("{}.{}-{}.tmp", name, std::process::id(), TMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+        match dir.create_file(&tmp_name, 0o600) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            other => break (tmp_name, other?),
+        }
+    };
+    let written = tmp_file.write_all(content).and_then(|_| tmp_file.sync_all());
+    drop(tmp_file);
+    if let Err(e) = written.and_then(|_| dir.rename(&tmp_name, &dir, OsStr::new(name))) {
+        let _ = dir.remove_file(&tmp_name);
+        return Err(e);
+    }
+    dir.sync()
+}
+
+// atomic_write 遗留的临时文件对应的最终名称；也识别旧版本使用的 <名称>.tmp
+fn tmp_target(name: &str) -> Option<&str> {
+    let stem = name.strip_suffix(".tmp")?;
+    let unique = stem.rsplit_once('.').filter(|(_, suffix)| {
+        suffix.split_once('-').is_some_and(|(pid, n)| {
+            [pid, n].iter().all(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()))
+        })
+    });
+    Some(unique.map_or(stem, |(target, _)| target))
+}
+
+// 存储分片：trash/ 与 meta/ 下按回收ID的 BLAKE3 前两位十六进制分成最多 256 个子目录，
+// 避免几十万个条目挤在同一目录中。旧的扁平条目原地可用，`srm store reshard` 把它们迁入分片
+fn shard_of(trash_id: &str) -> String {
+    blake3::hash(trash_id.as_bytes()).to_hex()[..2].to_string()
+}
+
+fn is_shard_name(name: &OsStr) -> bool {
+    name.len() == 2 && name.as_bytes().iter().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+// 新回收项相对存储根目录的路径
+fn trash_rel(trash_id: &str) -> PathBuf {
+    Path::new("trash").join(shard_of(trash_id)).join(trash_id)
+}
+
+// 新回收项在 trash/ 中的绝对路径，分片目录按需创建
+fn new_trash_path(trash_dir: &Path, trash_id: &str) -> io::Result<PathBuf> {
+    let shard = trash_dir.join(shard_of(trash_id));
+    if !shard.exists() {
+        secure_create_dir(&shard)?;
+    }
+    Ok(shard.join(trash_id))
+}
+
+// 回收ID的元数据与清单所在目录：扁平存储中已有的条目留在 meta/ 下，其余在分片中
+fn meta_home(meta_dir: &Path, trash_id: &str) -> PathBuf {
+    if meta_dir.join(format!("{}.meta", trash_id)).exists() {
+        meta_dir.to_path_buf()
+    } else {
+        meta_dir.join(shard_of(trash_id))
+    }
+}
+
+fn meta_file(meta_dir: &Path, trash_id: &str, ext: &str) -> PathBuf {
+    meta_home(meta_dir, trash_id).join(format!("{}.{}", trash_id, ext))
+}
+
+// 列出 trash/ 或 meta/ 中的条目，分片子目录展开一层
+fn store_entries(dir: &Path) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        if is_shard_name(&entry.file_name()) && entry.file_type().is_ok_and(|t| t.is_dir()) {
+            paths.extend(fs::read_dir(entry.path()).into_iter().flatten().flatten().map(|e| e.path()));
+        } else {
+            paths.push(entry.path());
+        }
+    }
+    paths
+}
+
+fn write_meta_file(meta_dir: &Path, trash_id: &str, ext: &str, content: &[u8]) -> io::Result<()> {
+    let dir = meta_home(meta_dir, trash_id);
+    if !dir.exists() {
+        secure_create_dir(&dir)?;
+    }
+    atomic_write(&dir, &format!("{}.{}", trash_id, ext), content)
+}
+
 fn atomic_save_meta(name: &str, meta: &FileMeta, meta_dir: &Path) -> io::Result<()> {
-    let tmp_path = meta_dir.join(format!("{}.meta.tmp", name));
-    let final_path = meta_dir.join(format!("{}.meta", name));
+    write_meta_file(meta_dir, name, "meta", serde_json::to_string_pretty(meta)?.as_bytes())
+}
 
-    let mut tmp_file = fs::File::create(&tmp_path)?;
-    write!(tmp_file, "{}", serde_json::to_string_pretty(meta)?)?;
-    tmp_file.sync_all()?;
-    drop(tmp_file);
+// 本地时间转UTC：DST回拨造成的重复时刻取较早者，跳过的时刻按跳变后的偏移解释
+fn local_to_utc(naive: &NaiveDateTime) -> DateTime<Utc> {
+    zoned_to_utc(&Local, naive)
+}
 
-    fs::rename(&tmp_path, &final_path)?;
+// 同上，时区由调用方给出（测试用固定规则的时区，不依赖进程的 TZ）
+fn zoned_to_utc<Tz: TimeZone>(tz: &Tz, naive: &NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(naive) {
+        LocalResult::Single(dt) => dt.with_timezone(&Utc),
+        // chrono 的 Local 不保证两个结果的先后，按实际时刻比较
+        LocalResult::Ambiguous(a, b) => a.min(b).with_timezone(&Utc),
+        LocalResult::None => tz
+            .from_local_datetime(&(*naive + Duration::hours(1)))
+            .earliest()
+            .map(|dt| dt.with_timezone(&Utc) - Duration::hours(1))
+            .unwrap_or_else(|| Utc.from_utc_datetime(naive)),
+    }
+}
+
+fn parse_legacy_time(s: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(s, LEGACY_TIME_FORMAT).ok().map(|naive| local_to_utc(&naive))
+}
+
+// v0：delete_time 为不带时区的本地时间字符串，没有 expire_time
+fn migrate_v0_to_v1(value: &mut serde_json::Value) -> Result<(), String> {
+    let obj = value.as_object_mut().ok_or("metadata is not a JSON object")?;
+    let expire_days = obj.get("expire_days").and_then(|v| v.as_i64()).unwrap_or(DEFAULT_EXPIRE_DAYS);
+
+    let delete_time = obj
+        .get("delete_time")
+        .and_then(|v| v.as_str())
+        .and_then(|s| parse_legacy_time(s).or_else(|| s.parse::<DateTime<Utc>>().ok()))
+        .or_else(|| {
+            // 时间无法解析时退回回收项的 ctime（移入回收站时更新）
+            let trash_path = obj.get("trash_path")?.as_str()?;
+            let meta = fs::symlink_metadata(trash_path).ok()?;
+            Utc.timestamp_opt(meta.ctime(), meta.ctime_nsec() as u32).single()
+        })
+        .ok_or("delete_time is unreadable and the trash entry is missing")?;
+
+    obj.insert("delete_time".into(), json!(delete_time));
+    obj.insert("expire_time".into(), json!(delete_time + Duration::days(expire_days)));
+    obj.insert("expire_days".into(), json!(expire_days));
+    obj.insert("schema_version".into(), json!(1));
     Ok(())
 }
 
+// 解析元数据并按需逐级升级到 META_SCHEMA_VERSION；返回值第二项表示是否发生了升级
+fn parse_meta(content: &str) -> Result<(FileMeta, bool), String> {
+    let mut value: serde_json::Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    let version = value.get("schema_version").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+    if version > META_SCHEMA_VERSION {
+        return Err(format!("schema version {} is newer than supported ({}); upgrade srm", version, META_SCHEMA_VERSION));
+    }
+
+    if version < 1 {
+        migrate_v0_to_v1(&mut value)?;
+    }
+
+    let meta = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    Ok((meta, version < META_SCHEMA_VERSION))
+}
+
+fn load_meta(path: &Path) -> io::Result<(FileMeta, bool)> {
+    let content = fs::read_to_string(path)?;
+    parse_meta(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
 fn remove_meta(name: &str, meta_dir: &Path) {
-    let _ = fs::remove_file(meta_dir.join(format!("{}.meta", name)));
+    for dir in [meta_dir.to_path_buf(), meta_dir.join(shard_of(name))] {
+        let _ = fs::remove_file(dir.join(format!("{}.meta", name)));
+        let _ = fs::remove_file(dir.join(format!("{}.manifest", name)));
+    }
+}
+
+// 清单键：合法 UTF-8 的相对路径原样使用，否则以 "b64:" 前缀记录原始字节，避免不同文件名替换后相撞
+fn manifest_key(rel: &Path) -> String {
+    match encode_path(rel) {
+        (display, None) => display,
+        (_, Some(b64)) => format!("b64:{}", b64),
+    }
+}
+
+// 目录的逐文件清单：相对路径 -> BLAKE3（软链接记录其指向的哈希）
+fn compute_manifest(root: &Path) -> io::Result<BTreeMap<String, String>> {
+    dir_manifest(&at::Dir::open(root)?)
 }
 
+fn dir_manifest(root: &at::Dir) -> io::Result<BTreeMap<String, String>> {
+    let mut manifest = BTreeMap::new();
+    at::walk(root, MAX_RECURSION_DEPTH, |event| {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "Operation interrupted"));
+        }
+        match event {
+            at::Event::Entry { stat, .. } if stat.is_dir() => {}
+            at::Event::Entry { parent, name, rel, stat } if stat.is_symlink() => {
+                let target = parent.read_link(name)?;
+                manifest.insert(manifest_key(rel), blake3::hash(target.as_os_str().as_bytes()).to_hex().to_string());
+            }
+            at::Event::Entry { rel, stat, .. } if stat.is_node() => {
+                manifest.insert(manifest_key(rel), node_hash(FileType::from_mode(stat.mode()), stat.rdev()));
+            }
+            at::Event::Entry { parent, name, rel, .. } => {
+                manifest.insert(manifest_key(rel), hash_reader(parent.open_file(name)?)?.to_hex().to_string());
+            }
+            at::Event::Leave { .. } => {}
+            at::Event::Unreadable { error, .. } => return Err(error),
+        }
+        Ok(())
+    })?;
+    Ok(manifest)
+}
+
+fn manifest_digest(manifest: &BTreeMap<String, String>) -> io::Result<(String, Vec<u8>)> {
+    let bytes = serde_json::to_vec(manifest)?;
+    Ok((blake3::hash(&bytes).to_hex().to_string(), bytes))
+}
+
+// 节点没有内容，按类型与设备号计算摘要
+fn node_hash(file_type: FileType, rdev: u64) -> String {
+    blake3::hash(format!("{}:{}", file_type, rdev).as_bytes()).to_hex().to_string()
+}
+
+// 计算回收项的内容摘要；目录同时返回需要落盘的清单
+fn compute_checksum(path: &Path, file_type: FileType) -> io::Result<(String, Option<Vec<u8>>)> {
+    match file_type {
+        FileType::File => Ok((hash_file(path)?.to_hex().to_string(), None)),
+        FileType::Symlink => {
+            let target = fs::read_link(path)?;
+            Ok((blake3::hash(target.as_os_str().as_encoded_bytes()).to_hex().to_string(), None))
+        }
+        FileType::Dir => {
+            let (digest, bytes) = manifest_digest(&compute_manifest(path)?)?;
+            Ok((digest, Some(bytes)))
+        }
+        FileType::Fifo | FileType::Socket | FileType::CharDevice | FileType::BlockDevice => {
+            Ok((node_hash(file_type, fs::symlink_metadata(path)?.rdev()), None))
+        }
+    }
+}
+
+fn save_manifest(name: &str, manifest: &[u8], meta_dir: &Path) -> io::Result<()> {
+    write_meta_file(meta_dir, name, "manifest", manifest)
+}
+
+// 移动时得到的摘要写入元数据的形式；目录的清单在此落盘，失败时告警并不记录摘要
+fn record_digest(digest: Hashed, trash_id: &str, meta_dir: &Path, shown: &Path) -> Option<String> {
+    match digest? {
+        Ok(Digest::Item(digest)) => Some(digest),
+        Ok(Digest::Tree(manifest)) => {
+            match manifest_digest(&manifest).and_then(|(digest, bytes)| save_manifest(trash_id, &bytes, meta_dir).map(|_| digest)) {
+                Ok(digest) => Some(digest),
+                Err(e) => {
+                    eprintln!("⚠️  Failed to save manifest for '{}': {}", shown.display(), e);
+                    None
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("⚠️  Failed to checksum '{}': {}", shown.display(), e);
+            None
+        }
+    }
+}
+
+fn short_hash(hash: &str) -> &str {
+    hash.get(..12).unwrap_or(hash)
+}
+
+// 校验回收项与删除时记录的摘要是否一致，返回发现的问题（为空表示完好）
+fn verify_item(trash_id: &str, meta: &FileMeta, trash_dir: &Path, meta_dir: &Path) -> io::Result<Vec<String>> {
+    let Some(expected) = &meta.checksum else {
+        return Ok(Vec::new());
+    };
+    let trash_path = meta.trash(trash_dir);
+
+    if meta.file_type != FileType::Dir {
+        let (actual, _) = compute_checksum(&trash_path, meta.file_type)?;
+        return Ok(if &actual == expected {
+            Vec::new()
+        } else {
+            vec![format!("content hash mismatch (expected {}, got {})", short_hash(expected), short_hash(&actual))]
+        });
+    }
+
+    let mut problems = Vec::new();
+    let recorded: BTreeMap<String, String> = match fs::read(meta_file(meta_dir, trash_id, "manifest")) {
+        Ok(bytes) => {
+            if blake3::hash(&bytes).to_hex().as_str() != expected {
+                problems.push("manifest does not match recorded checksum".to_string());
+            }
+            serde_json::from_slice(&bytes)?
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let (actual, _) = manifest_digest(&compute_manifest(&trash_path)?)?;
+            if &actual != expected {
+                problems.push("manifest missing and directory hash differs".to_string());
+            }
+            return Ok(problems);
+        }
+        Err(e) => return Err(e),
+    };
+
+    let actual = compute_manifest(&trash_path)?;
+    for (rel, hash) in &recorded {
+        match actual.get(rel) {
+            Some(h) if h == hash => {}
+            Some(_) => problems.push(format!("modified: {}", rel)),
+            None => problems.push(format!("missing: {}", rel)),
+        }
+    }
+    for rel in actual.keys().filter(|rel| !recorded.contains_key(*rel)) {
+        problems.push(format!("unexpected: {}", rel));
+    }
+    Ok(problems)
+}
+
+// 只读：旧格式的元数据在内存中迁移，不写回。持共享锁的命令（ls、verify、status、演练）只能用它
 fn list_all_meta(meta_dir: &Path, trash_dir: &Path) -> HashMap<String, FileMeta> {
+    load_all_meta(meta_dir, trash_dir, false)
+}
+
+// write_upgrades 只能在持有排他锁时为 true：迁移后的元数据写回文件，之后的读取不必再迁移
+fn load_all_meta(meta_dir: &Path, trash_dir: &Path, write_upgrades: bool) -> HashMap<String, FileMeta> {
     let mut map = HashMap::new();
-    if let Ok(entries) = fs::read_dir(meta_dir) {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            let Some(name) = path.file_name().and_then(|n| n.to_str()).and_then(|s| s.strip_suffix(".meta")) else {
-                continue;
-            };
-            if let Ok(content) = fs::read_to_string(&path) {
-                if let Ok(mut meta) = serde_json::from_str::<FileMeta>(&content) {
-                    let trash_file = PathBuf::from(&meta.trash_path);
-                    if !trash_file.exists() || !same_filesystem(&trash_file, trash_dir) {
-                        eprintln!("⚠️  清理无效元数据：{}", name);
-                        remove_meta(name, meta_dir);
+    for path in store_entries(meta_dir) {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()).and_then(|s| s.strip_suffix(".meta")) else {
+            continue;
+        };
+        let home = path.parent().unwrap_or(meta_dir);
+        if let Ok(content) = fs::read_to_string(&path) {
+            match parse_meta(&content) {
+                Ok((mut meta, mut upgraded)) => {
+                    let trash_file = meta.trash(trash_dir);
+                    // 回收项缺失时保留元数据（可能是存储尚未迁移完整），交给 fsck 处理
+                    if trash_file.symlink_metadata().is_err() {
+                        eprintln!("⚠️  跳过找不到回收项的元数据：{}（运行 `srm fsck` 检查）", name);
                         continue;
                     }
+                    if Path::new(&meta.trash_path).is_absolute() {
+                        if let Some(rel) = trash_dir.parent().and_then(|base| trash_file.strip_prefix(base).ok()) {
+                            meta.set_trash(rel);
+                            upgraded = true;
+                        }
+                    }
+                    if upgraded && write_upgrades {
+                        let content = serde_json::to_string_pretty(&meta).map_err(io::Error::from);
+                        if let Err(e) = content.and_then(|c| atomic_write(home, &format!("{}.meta", name), c.as_bytes())) {
+                            eprintln!("⚠️  元数据升级写回失败：{} ({})", name, e);
+                        }
+                    }
                     if meta.short_id.is_empty() {
                         meta.short_id = name.to_string();
                     }
                     map.insert(name.to_string(), meta);
-                } else {
-                    eprintln!("⚠️  清理损坏元数据：{}", name);
-                    remove_meta(name, meta_dir);
+                }
+                // JSON 完好但无法升级（如来自更新版本的 srm）：保留文件，交给 fsck 处理
+                Err(e) if serde_json::from_str::<serde_json::Value>(&content).is_ok() => {
+                    eprintln!("⚠️  跳过无法识别的元数据：{} ({})", name, e);
+                }
+                // 损坏的元数据同样保留，由 `srm fsck --repair` 隔离
+                Err(e) => {
+                    eprintln!("⚠️  跳过损坏的元数据：{} ({})（运行 `srm fsck` 检查）", name, e);
                 }
             }
         }
@@ -470,23 +1290,37 @@ fn list_all_meta(meta_dir: &Path, trash_dir: &Path) -> HashMap<String, FileMeta>
     map
 }
 
-fn generate_short_id(trash_id: &str, file_type: FileType, existing: &HashSet<String>) -> String {
+// 回收ID定长（"{20位纳秒时间戳}-{原路径哈希前8位}"），与文件名长度无关；原路径只记录在元数据中
+fn new_trash_id(original: &Path) -> String {
+    let nanos = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos();
+    let hash = blake3::hash(original.as_os_str().as_bytes());
+    format!("{:020}-{}", nanos, &hash.to_hex()[..8])
+}
+
+// 生成短ID并立即登记到 existing 中，同一批次内后续分配也不会重复
+fn generate_short_id(trash_id: &str, file_type: FileType, existing: &mut HashSet<String>) -> String {
     let hash = compute(trash_id.as_bytes());
     let hex = format!("{:x}", hash);
     let type_prefix = match file_type {
         FileType::File => "f",
         FileType::Dir => "d",
         FileType::Symlink => "l",
+        FileType::Fifo => "p",
+        FileType::Socket => "s",
+        FileType::CharDevice => "c",
+        FileType::BlockDevice => "b",
     };
 
-    let mut candidate = format!("{}{}", type_prefix, &hex[..SHORT_ID_LENGTH]);
+    let base = format!("{}{}", type_prefix, &hex[..SHORT_ID_LENGTH]);
+    let mut candidate = base.clone();
     let mut counter = 1;
 
     while existing.contains(&candidate) {
-        candidate = format!("{}_{}", candidate, counter);
+        candidate = format!("{}_{}", base, counter);
         counter += 1;
     }
 
+    existing.insert(candidate.clone());
     candidate
 }
 
@@ -497,9 +1331,22 @@ fn setup_interrupt_handler() {
 }
 
 fn check_disk_space(trash_dir: &Path, required_bytes: u64, is_single_file: bool) -> io::Result<()> {
-    let available = fs2::available_space(trash_dir)?;
+    check_space(fs2::available_space(trash_dir)?, required_bytes, is_single_file)
+}
+
+// 已打开文件所在文件系统中非特权用户可用的空间
+fn available_space_fd(file: &fs::File) -> io::Result<u64> {
+    use std::os::unix::io::AsRawFd;
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstatvfs(file.as_raw_fd(), &mut st) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(st.f_bavail as u64 * st.f_frsize as u64)
+}
+
+fn check_space(available: u64, required_bytes: u64, is_single_file: bool) -> io::Result<()> {
     if available == 0 {
-        return Err(io::Error::new(io::ErrorKind::Other, "No disk space available"));
+        return Err(io::Error::other("No disk space available"));
     }
 
     let base_required = if required_bytes < 100 * 1024 * 1024 {
@@ -508,14 +1355,13 @@ fn check_disk_space(trash_dir: &Path, required_bytes: u64, is_single_file: bool)
         required_bytes
             .checked_mul(120)
             .and_then(|v| v.checked_div(100))
-            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "File size too large, calculation overflow"))?
+            .ok_or_else(|| io::Error::other("File size too large, calculation overflow"))?
     };
 
     if is_single_file {
         let max_allowed = (available as f64 * MAX_FILE_SPACE_RATIO) as u64;
         if required_bytes > max_allowed {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
+            return Err(io::Error::other(
                 format!("Single file too large: {} exceeds {}% of available space ({})",
                         ByteSize(required_bytes),
                         (MAX_FILE_SPACE_RATIO * 100.0) as u8,
@@ -525,8 +1371,7 @@ fn check_disk_space(trash_dir: &Path, required_bytes: u64, is_single_file: bool)
     }
 
     if base_required > available {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
+        return Err(io::Error::other(
             format!("Insufficient disk space. Need {} but only {} available",
                     ByteSize(base_required),
                     ByteSize(available))
@@ -536,139 +1381,111 @@ fn check_disk_space(trash_dir: &Path, required_bytes: u64, is_single_file: bool)
     Ok(())
 }
 
-fn handle_delete_batch(paths: Vec<PathBuf>, expire_days: i64, force: bool, trash_dir: &Path, meta_dir: &Path) {
-    setup_interrupt_handler();
-
-    if !force {
-        for path in &paths {
-            let path_str = path.to_string_lossy();
-            if path_str.contains("..") || path_str.starts_with('-') {
-                eprintln!("❌  Path traversal detected ('{}'). Use -f to override safety checks.", path_str);
-                std::process::exit(1);
-            }
-        }
-    }
-
-    let mut items_to_delete = Vec::new();
-    let mut skipped = Vec::new();
-    let mut total_required_space = 0u64;
+// 删除结果的输出方式：默认带图标、逐项短ID和汇总；rm 兼容模式只按 GNU rm 的格式输出错误与 -v 信息
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DeleteOutput {
+    Normal,
+    Rm { verbose: bool },
+}
 
-    for path in &paths {
-        let abs_path = if path.is_absolute() {
-            path.clone()
+// 返回是否所有目标都已移入回收站（没有跳过、失败或中断）
+#[allow(clippy::too_many_arguments)]
+fn handle_delete_batch(plan: plan::Plan, expire_days: i64, force: bool, verify: VerifyMode, checksum: ChecksumMode, output: DeleteOutput,
+                       trash_dir: &Path, meta_dir: &Path) -> bool {
+    setup_interrupt_handler();
+    let rm_mode = output != DeleteOutput::Normal;
+    let report_failure = |abs_path: &Path, arg: &Path, error: &dyn std::fmt::Display| {
+        if rm_mode {
+            eprintln!("rm: cannot remove '{}': {}", arg.display(), error);
         } else {
-            match std::env::current_dir() {
-                Ok(cwd) => cwd.join(path),
-                Err(e) => {
-                    skipped.push((path.display().to_string(), format!("Failed to get current directory: {}", e)));
-                    continue;
-                }
-            }
-        };
-
-        if let Ok(meta) = fs::symlink_metadata(&abs_path) {
-            if meta.file_type().is_symlink() {
-                if let Ok(target) = fs::read_link(&abs_path) {
-                    let target_str = target.to_string_lossy();
-                    if !force && PROTECTED_PATHS.iter().any(|&p| target_str.starts_with(p)) {
-                        skipped.push((abs_path.display().to_string(), format!("Symlink targets protected path: {}", target_str)));
-                        continue;
-                    }
-                }
-            }
-        }
-
-        if !abs_path.exists() {
-            skipped.push((abs_path.display().to_string(), "Not found".into()));
-            continue;
-        }
-
-        let meta = match fs::symlink_metadata(&abs_path) {
-            Ok(m) => m,
-            Err(e) => {
-                skipped.push((abs_path.display().to_string(), format!("{}", e)));
-                continue;
-            }
-        };
-
-        let is_symlink = meta.file_type().is_symlink();
-        let file_type = if is_symlink { FileType::Symlink } else if meta.is_dir() { FileType::Dir } else { FileType::File };
-
-        if !force && !is_symlink {
-            let canon_path = match canonicalize_safe(&abs_path) {
-                Ok(p) => p,
-                Err(e) => {
-                    skipped.push((abs_path.display().to_string(), format!("Invalid path: {}", e)));
-                    continue;
-                }
-            };
-            let path_str = canon_path.to_string_lossy();
-            if PROTECTED_PATHS.iter().any(|&p| {
-                path_str.starts_with(p) &&
-                (path_str.len() == p.len() || path_str.as_bytes()[p.len()] == b'/')
-            }) {
-                skipped.push((abs_path.display().to_string(), "Protected system path (use -f to override)".into()));
-                continue;
-            }
+            eprintln!("❌ Failed '{}': {}", abs_path.display(), error);
         }
+    };
 
-        let (size_bytes, _) = if file_type == FileType::Dir {
-            match calculate_dir_stats(&abs_path) {
-                Ok(s) => s,
-                Err(e) => {
-                    skipped.push((abs_path.display().to_string(), format!("Failed to get dir stats: {}", e)));
-                    continue;
-                }
-            }
+    if let Err(e) = plan.check_space() {
+        if rm_mode {
+            eprintln!("rm: {}", e);
         } else {
-            (meta.len(), 0)
-        };
-
-        if file_type != FileType::Dir && size_bytes > 0 {
-            if let Err(e) = check_disk_space(trash_dir, size_bytes, true) {
-                skipped.push((abs_path.display().to_string(), format!("{}", e)));
-                continue;
-            }
+            eprintln!("❌  {}", e);
         }
-
-        total_required_space += size_bytes;
-        items_to_delete.push((abs_path, meta, file_type, size_bytes));
-    }
-
-    if let Err(e) = check_disk_space(trash_dir, total_required_space, false) {
-        eprintln!("❌  {}", e);
         std::process::exit(1);
     }
 
+    let total_required_space = plan.total_bytes();
+    let required_copy_space = plan.required_bytes();
+    let mut skipped = plan.skipped;
+    let items_to_delete = plan.targets;
+
     log_event("INFO", "Delete command started", Some(json!({
-        "paths_count": paths.len(),
+        "paths_count": items_to_delete.len() + skipped.len(),
         "expire_days": expire_days,
         "force": force,
+        "verify": verify.to_string(),
         "items_to_delete": items_to_delete.len(),
         "skipped": skipped.len(),
-        "total_size_bytes": total_required_space
+        "total_size_bytes": total_required_space,
+        "required_space_bytes": required_copy_space
     })));
 
     if items_to_delete.is_empty() && skipped.is_empty() {
-        println!("ℹ️  No items to delete");
+        if !rm_mode {
+            println!("ℹ️  No items to delete");
+        }
         log_event("INFO", "Delete command completed", Some(json!({"success": 0, "skipped": 0, "failed": 0})));
-        return;
+        return true;
+    }
+
+    let mut breaker = match breaker::Breaker::load(&get_srm_base()) {
+        Ok(b) => b,
+        Err(e) => {
+            if rm_mode {
+                eprintln!("rm: {}", e);
+            } else {
+                eprintln!("❌  {}", e);
+            }
+            std::process::exit(1);
+        }
+    };
+    // 熔断已断开时，只有终端前的人确认后才继续
+    if let Some(trip) = breaker.tripped().cloned() {
+        let question = format!("mass-deletion circuit breaker is open ({}); delete anyway", trip);
+        let answer = if io::stdin().is_terminal() { confirm_item(&question, rm_mode) } else { Err("stdin is not a terminal") };
+        if let Err(why) = answer {
+            if rm_mode {
+                eprintln!("rm: refusing to remove: mass-deletion circuit breaker is open ({})", trip);
+            } else {
+                eprintln!("🚨 Mass-deletion circuit breaker is open ({})", trip);
+                eprintln!("   Confirm in a terminal or run `srm status --reset` to resume deleting");
+            }
+            log_event("SECURITY", "Delete blocked by circuit breaker", Some(json!({
+                "since": trip.since,
+                "reason": trip.reason,
+                "items_to_delete": items_to_delete.len(),
+                "total_size_bytes": total_required_space,
+                "why": why
+            })));
+            std::process::exit(1);
+        }
+        breaker.reset("delete prompt");
     }
 
-    println!("🗑️  Deleting {} item(s) ({} total, expire in {} days)...",
-             items_to_delete.len(),
-             ByteSize(total_required_space),
-             expire_days);
+    if !rm_mode {
+        println!("🗑️  Deleting {} item(s) ({} total, expire in {} days)...",
+                 items_to_delete.len(),
+                 ByteSize(total_required_space),
+                 expire_days);
+    }
 
-    let existing_short_ids: HashSet<String> = list_all_meta(meta_dir, trash_dir)
+    let mut existing_short_ids: HashSet<String> = load_all_meta(meta_dir, trash_dir, true)
         .values()
         .map(|m| m.short_id.clone())
         .collect();
 
-    let mut moved: Vec<(String, String, String, String, u64)> = Vec::new();
+    // (回收ID, 短ID, 原路径, 回收站路径, 大小, 清除的属性)
+    let mut moved: Vec<(String, String, PathBuf, PathBuf, u64, Vec<attrs::Locked>)> = Vec::new();
     let mut failed = Vec::new();
     let total_items = items_to_delete.len();
-    let show_batch_progress = total_items > PROGRESS_THRESHOLD_ITEMS || total_required_space > PROGRESS_THRESHOLD_BYTES;
+    let show_batch_progress = !rm_mode && (total_items > PROGRESS_THRESHOLD_ITEMS || total_required_space > PROGRESS_THRESHOLD_BYTES);
     let mut mp_pb = None;
     if show_batch_progress {
         let mp = MultiProgress::new();
@@ -683,56 +1500,156 @@ fn handle_delete_batch(paths: Vec<PathBuf>, expire_days: i64, force: bool, trash
 
     let start_time = Instant::now();
     let mut processed = 0usize;
+    let mut paused = 0usize;
 
-    for (abs_path, meta, file_type, size_bytes) in items_to_delete {
+    for (index, plan::Target { path: abs_path, arg, meta, file_type, usage, tree, strategy, overridden, rule, confirm, git, keep_days, mounts, busy, locked }) in items_to_delete.into_iter().enumerate() {
         if INTERRUPTED.load(Ordering::Relaxed) {
             println!("\n⚠️  Operation interrupted. Stopping...");
             log_event("WARN", "User interrupted operation", None);
             break;
         }
 
-        let name = abs_path.file_name().and_then(|n| n.to_str()).unwrap_or("unknown");
-        let ts = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_nanos();
-        let trash_id = format!("{}_{}", name, ts);
-        let trash_path = trash_dir.join(&trash_id);
-        let short_id = generate_short_id(&trash_id, file_type, &existing_short_ids);
-        let item_count = if file_type == FileType::Dir {
-            fs::read_dir(&abs_path).map(|e| e.count()).unwrap_or(0)
-        } else {
-            0
+        if let Some(trip) = breaker.tripped() {
+            skipped.push(plan::Skip {
+                path: abs_path.display().to_string(),
+                arg,
+                reason: format!("Paused by mass-deletion circuit breaker: {}", trip.reason),
+                rules: Vec::new(),
+                hard: false,
+                matched: rule,
+                busy: Vec::new(),
+            });
+            paused += 1;
+            continue;
+        }
+
+        if !confirm.is_empty() {
+            let shown = if rm_mode { arg.display() } else { abs_path.display() };
+            let reasons = confirm.iter().map(|c| c.reason.as_str()).collect::<Vec<_>>().join("; ");
+            let question = format!("remove '{}' ({})", shown, reasons);
+            let answer = if !io::stdin().is_terminal() {
+                if confirm.iter().any(|c| c.required) { Err("stdin is not a terminal") } else { Ok(()) }
+            } else {
+                match &mp_pb {
+                    Some((mp, _)) => mp.suspend(|| confirm_item(&question, rm_mode)),
+                    None => confirm_item(&question, rm_mode),
+                }
+            };
+            if let Err(why) = answer {
+                skipped.push(plan::Skip {
+                    path: abs_path.display().to_string(),
+                    arg: arg.clone(),
+                    reason: format!("Requires confirmation: {} ({})", reasons, why),
+                    rules: confirm.iter().map(|c| c.rule).collect(),
+                    hard: false,
+                    matched: rule.clone(),
+                    busy: Vec::new(),
+                });
+                continue;
+            }
+        }
+        if !busy.is_empty() {
+            if !rm_mode {
+                eprintln!("⚠️  '{}' is in use by {}; it may keep writing to the copy in trash (use --busy deny to skip)",
+                          abs_path.display(), busy::describe(&busy));
+            }
+            log_event("WARN", "Deleting file in use", Some(json!({
+                "path": abs_path.display().to_string(),
+                "busy": busy
+            })));
+        }
+        let extended = keep_days.filter(|&d| d > expire_days);
+        let expire_days = extended.unwrap_or(expire_days);
+
+        let name = abs_path.file_name().map(|n| n.to_string_lossy()).unwrap_or("unknown".into());
+        let trash_id = new_trash_id(&abs_path);
+        let trash_path = match new_trash_path(trash_dir, &trash_id) {
+            Ok(p) => p,
+            Err(e) => {
+                report_failure(&abs_path, &arg, &e);
+                failed.push((abs_path.display().to_string(), format!("{}", e)));
+                continue;
+            }
         };
+        let short_id = generate_short_id(&trash_id, file_type, &mut existing_short_ids);
+        let item_count = tree.as_ref().map_or(0, |t| t.top_level);
 
         if let Some((_, pb)) = &mp_pb {
             pb.set_position(processed as u64);
             pb.set_message(format!("{} → {}", truncate_path(&abs_path.display().to_string(), 30), short_id));
         }
 
-        let show_progress = size_bytes > PROGRESS_THRESHOLD_BYTES || (file_type == FileType::Dir && item_count > 100);
-        match safe_move_with_progress(&abs_path, &trash_path, show_progress) {
-            Ok(_) => {
-                let original_str = abs_path.to_string_lossy().into_owned();
-                let trash_str = trash_path.to_string_lossy().into_owned();
-                let file_meta = FileMeta {
+        if let Err(e) = attrs::clear(&abs_path, &locked) {
+            report_failure(&abs_path, &arg, &e);
+            failed.push((abs_path.display().to_string(), format!("{}", e)));
+            continue;
+        }
+        // 移动失败、该项放回原处后按原样设置属性
+        let relock = || {
+            for problem in attrs::reapply(&abs_path, &locked) {
+                eprintln!("⚠️  Failed to reapply attributes in '{}': {}", abs_path.display(), problem);
+            }
+        };
+
+        let show_progress = usage.is_some_and(|u| u.apparent > PROGRESS_THRESHOLD_BYTES) || item_count > 100;
+        match move_to_trash(&abs_path, &meta, tree.as_ref(), &trash_path, show_progress, verify, checksum) {
+            Ok((_, digest)) => {
+                drop(tree);
+                let checksum = record_digest(digest, &trash_id, meta_dir, &abs_path);
+                // 未扫描的 rename 目录在此统计大小；复制到另一个文件系统后块分配会变化，按回收站中的副本重新计量
+                let usage = match usage {
+                    Some(u) if strategy == plan::Strategy::Rename => u,
+                    u => path_usage(&trash_path).unwrap_or(u.unwrap_or_default()),
+                };
+                let (size_bytes, allocated_bytes) = (usage.apparent, usage.allocated);
+
+                let (original_str, original_b64) = encode_path(&abs_path);
+                let (trash_str, trash_b64) = encode_path(&trash_path);
+                let delete_time = Utc::now();
+                let mut file_meta = FileMeta {
+                    schema_version: META_SCHEMA_VERSION,
                     original_path: original_str.clone(),
-                    trash_path: trash_str.clone(),
-                    delete_time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                    trash_path: String::new(),
+                    original_path_b64: original_b64.clone(),
+                    trash_path_b64: None,
+                    delete_time,
+                    expire_time: delete_time + Duration::days(expire_days),
                     expire_days,
                     file_type,
+                    rdev: FileType::device(&meta),
                     permissions: Some(meta.permissions().mode()),
                     uid: Some(meta.uid()),
                     gid: Some(meta.gid()),
                     short_id: short_id.clone(),
                     size_bytes,
+                    allocated_bytes: Some(allocated_bytes),
+                    checksum: checksum.clone(),
+                    git: git.clone(),
+                    mounts: mounts.clone(),
+                    attrs: locked.clone(),
                 };
+                file_meta.set_trash(&trash_rel(&trash_id));
 
                 if let Err(e) = atomic_save_meta(&trash_id, &file_meta, meta_dir) {
-                    eprintln!("⚠️  Failed to save metadata for '{}': {}", abs_path.display(), e);
-                    let _ = safe_move_with_progress(&trash_path, &abs_path, false);
+                    report_failure(&abs_path, &arg, &format!("failed to save metadata: {}", e));
+                    remove_meta(&trash_id, meta_dir);
+                    // 留有文件系统边界时原目录仍在，把内容合并回去
+                    let _ = if mounts.is_empty() {
+                        safe_move_with_progress(&trash_path, &abs_path, false, verify).map(|_| ())
+                    } else {
+                        undo_partial_move(&trash_path, &abs_path, verify)
+                    };
+                    relock();
                     failed.push((original_str, format!("Metadata save failed: {}", e)));
                     continue;
                 }
 
-                if !show_batch_progress {
+                if let DeleteOutput::Rm { verbose } = output {
+                    if verbose {
+                        let kind = if file_type == FileType::Dir { "removed directory" } else { "removed" };
+                        println!("{} '{}'", kind, arg.display());
+                    }
+                } else if !show_batch_progress {
                     let display_name = if file_type == FileType::Dir {
                         format!("{}/", name)
                     } else {
@@ -746,6 +1663,18 @@ fn handle_delete_batch(paths: Vec<PathBuf>, expire_days: i64, force: bool, trash
                     };
 
                     println!("✅ {} → 🆔 {}{} [{}]", display_name, short_id, item_suffix, ByteSize(size_bytes));
+                    if let Some(days) = extended {
+                        println!("   ⏳ Kept for {} days: unsaved git work ({})", days, git.as_ref().map(|g| g.describe()).unwrap_or_default());
+                    }
+                    if !locked.is_empty() {
+                        println!("   🔓 Cleared attributes (reapplied on restore): {}", attrs::summary(&abs_path, &locked));
+                    }
+                    for b in &mounts {
+                        let what = if b.included { "Contents moved, mount point left in place" } else { "Left in place" };
+                        println!("   🧱 {}: {}", what, b);
+                    }
+                } else if !mounts.is_empty() {
+                    println!("🧱 {}: {} filesystem boundary(ies) left in place", short_id, mounts.len());
                 }
 
                 log_event("INFO", "File deleted", Some(json!({
@@ -753,59 +1682,176 @@ fn handle_delete_batch(paths: Vec<PathBuf>, expire_days: i64, force: bool, trash
                     "short_id": short_id,
                     "trash_id": trash_id,
                     "original_path": original_str,
+                    "original_path_b64": original_b64,
                     "backup_path": trash_str,
+                    "backup_path_b64": trash_b64,
                     "file_type": format!("{}", file_type),
+                    "rdev": FileType::device(&meta),
                     "size_bytes": size_bytes,
+                    "allocated_bytes": allocated_bytes,
+                    "checksum": checksum,
                     "permissions": format!("{:o}", meta.permissions().mode() & 0o777),
+                    "uid": meta.uid(),
+                    "gid": meta.gid(),
+                    "delete_time": file_meta.delete_time,
+                    "expire_time": file_meta.expire_time,
                     "expire_days": expire_days,
                     "forced": force,
+                    "overridden": overridden,
+                    "rule": rule,
+                    "git": git,
+                    "mounts": mounts,
+                    "busy": busy,
+                    "cleared_attrs": locked,
                     "duration_ms": start_time.elapsed().as_millis()
                 })));
 
-                moved.push((trash_id, short_id, original_str, trash_str, size_bytes));
+                moved.push((trash_id, short_id, abs_path, trash_path, size_bytes, locked));
                 processed += 1;
+
+                if let Some(trip) = breaker.record(size_bytes) {
+                    let (window_items, window_bytes) = breaker.totals();
+                    let remaining = total_items - index - 1;
+                    log_event("SECURITY", "Mass-deletion circuit breaker tripped", Some(json!({
+                        "reason": trip.reason,
+                        "window_secs": breaker.config.window_secs,
+                        "items": window_items,
+                        "bytes": window_bytes,
+                        "max_items": breaker.config.max_items,
+                        "max_bytes": breaker.config.max_bytes,
+                        "remaining": remaining
+                    })));
+                    // 立即保存，让同时等锁的其他 srm 进程也看到断开
+                    if let Err(e) = breaker.save() {
+                        eprintln!("⚠️  Failed to save circuit breaker state: {}", e);
+                    }
+                    let question = format!("mass-deletion circuit breaker tripped ({}); continue with the remaining {} item(s)", trip.reason, remaining);
+                    let answer = if remaining == 0 || !io::stdin().is_terminal() {
+                        Err("stdin is not a terminal")
+                    } else {
+                        match &mp_pb {
+                            Some((mp, _)) => mp.suspend(|| confirm_item(&question, rm_mode)),
+                            None => confirm_item(&question, rm_mode),
+                        }
+                    };
+                    if answer.is_ok() {
+                        breaker.reset("delete prompt");
+                    } else if rm_mode {
+                        eprintln!("rm: mass-deletion circuit breaker tripped: {}; further deletes are paused (see `srm status`)", trip.reason);
+                    } else {
+                        eprintln!("🚨 Mass-deletion circuit breaker tripped: {}", trip.reason);
+                        eprintln!("   Further deletes are paused until confirmed in a terminal (see `srm status`)");
+                    }
+                }
             }
             Err(e) => {
+                if file_type == FileType::Dir && trash_path.exists() {
+                    if let Err(undo_err) = undo_partial_move(&trash_path, &abs_path, verify) {
+                        eprintln!("⚠️  Partial move of '{}' could not be undone ({}); remaining data is in {}",
+                                  abs_path.display(), undo_err, trash_path.display());
+                    }
+                }
+                relock();
                 failed.push((abs_path.display().to_string(), format!("{}", e)));
-                eprintln!("❌ Failed '{}': {}", abs_path.display(), e);
+                report_failure(&abs_path, &arg, &e);
+                log_event("ERROR", "Delete failed", Some(json!({
+                    "path": abs_path.display().to_string(),
+                    "error": e.to_string(),
+                    "verify": verify.to_string()
+                })));
             }
         }
     }
 
+    // 中断回滚的项目同样计入窗口：回滚前它们已经离开了原位置
+    if let Err(e) = breaker.save() {
+        eprintln!("⚠️  Failed to save circuit breaker state: {}", e);
+    }
+
     let skipped_count = skipped.len();
-    for (path, reason) in &skipped {
-        println!("⚠️  Skip '{}': {}", path, reason);
+    for s in &skipped {
+        let plan::Skip { path, arg, reason, rules, hard, matched, busy } = s;
+        match output {
+            DeleteOutput::Rm { .. } if *hard => eprintln!("rm: refusing to remove '{}': {}", arg.display(), reason),
+            DeleteOutput::Rm { .. } if !rules.is_empty() => {
+                eprintln!("rm: refusing to remove '{}': {} (use `srm del --allow {}` to override)", arg.display(), reason, s.allow_hint())
+            }
+            DeleteOutput::Rm { .. } => eprintln!("rm: cannot remove '{}': {}", arg.display(), reason),
+            DeleteOutput::Normal if *hard => println!("🛡️  Refused '{}': {}", path, reason),
+            DeleteOutput::Normal if !rules.is_empty() => {
+                println!("🛡️  Protected '{}': {} (use --allow {} to override)", path, reason, s.allow_hint())
+            }
+            DeleteOutput::Normal => println!("⚠️  Skip '{}': {}", path, reason),
+        }
         log_event("WARN", "Skipped deletion", Some(json!({
             "path": path,
             "reason": reason,
+            "rules": rules,
+            "matched_rule": matched,
+            "busy": busy,
             "forced": force
         })));
     }
 
     let failed_count = failed.len();
+    let interrupted = INTERRUPTED.load(Ordering::Relaxed);
 
-    if INTERRUPTED.load(Ordering::Relaxed) {
+    if interrupted {
         let rollback_count = moved.len();
         println!("\n🔄 Rolling back {} items...", rollback_count);
 
-        for (trash_id, short_id, orig_str, trash_str, _) in moved.into_iter().rev() {
-            let orig_path = PathBuf::from(&orig_str);
-            let trash_path = PathBuf::from(&trash_str);
-            if trash_path.exists() {
-                println!("↩️  Rolling back: {}", orig_path.display());
-                let _ = safe_move_with_progress(&trash_path, &orig_path, false);
-                remove_meta(&trash_id, meta_dir);
-                log_event("INFO", "Rollback performed", Some(json!({
+        // 复制与校验会检查中断标志，回滚前清除；回滚中再次中断时，其余项目连同元数据留在回收站，可以 restore
+        INTERRUPTED.store(false, Ordering::Relaxed);
+        let mut kept = 0usize;
+        for (trash_id, short_id, orig_path, trash_path, _, locked) in moved.into_iter().rev() {
+            if INTERRUPTED.load(Ordering::Relaxed) {
+                kept += 1;
+                continue;
+            }
+            if !trash_path.exists() {
+                continue;
+            }
+            println!("↩️  Rolling back: {}", orig_path.display());
+            if let Err(e) = safe_move_with_progress(&trash_path, &orig_path, false, verify) {
+                if trash_path.is_dir() && orig_path.exists() {
+                    if let Err(undo_err) = undo_partial_move(&orig_path, &trash_path, verify) {
+                        eprintln!("⚠️  Partial rollback of '{}' could not be undone ({}); remaining data is in {}",
+                                  orig_path.display(), undo_err, orig_path.display());
+                    }
+                }
+                eprintln!("❌  Failed to roll back '{}': {} (kept in trash as {})", orig_path.display(), e, short_id);
+                log_event("ERROR", "Rollback failed", Some(json!({
                     "short_id": short_id,
-                    "original_path": orig_str
+                    "trash_id": trash_id,
+                    "original_path": orig_path.to_string_lossy(),
+                    "original_path_b64": encode_path(&orig_path).1,
+                    "error": e.to_string()
                 })));
+                kept += 1;
+                continue;
+            }
+            for problem in attrs::reapply(&orig_path, &locked) {
+                eprintln!("⚠️  Failed to reapply attributes in '{}': {}", orig_path.display(), problem);
             }
+            remove_meta(&trash_id, meta_dir);
+            log_event("INFO", "Rollback performed", Some(json!({
+                "action": "rollback",
+                "short_id": short_id,
+                "trash_id": trash_id,
+                "original_path": orig_path.to_string_lossy(),
+                "original_path_b64": encode_path(&orig_path).1,
+                "reapplied_attrs": locked
+            })));
+        }
+        if kept > 0 {
+            println!("⚠️  Rollback incomplete: {} item(s) remain in trash (see `srm ls`)", kept);
+        } else {
+            println!("✅ Rollback finished.");
         }
-        println!("✅ Rollback finished.");
-        log_event("WARN", "Operation interrupted and rolled back", Some(json!({"rolled_back_count": rollback_count})));
+        log_event("WARN", "Operation interrupted and rolled back", Some(json!({"rolled_back_count": rollback_count - kept, "kept_in_trash": kept})));
     } else {
         let success_count = moved.len();
-        let total_size = moved.iter().map(|(_, _, _, _, s)| s).sum::<u64>();
+        let total_size = moved.iter().map(|(_, _, _, _, s, _)| s).sum::<u64>();
         let duration = start_time.elapsed();
         let throughput = if duration.as_secs() > 0 {
             total_size / duration.as_secs()
@@ -820,7 +1866,7 @@ fn handle_delete_batch(paths: Vec<PathBuf>, expire_days: i64, force: bool, trash
                     ByteSize(total_size),
                     throughput as f64 / 1024.0 / 1024.0));
             }
-        } else {
+        } else if !rm_mode {
             println!("\n✅ Deletion completed ({} succeeded, {} skipped, {} failed)",
                      success_count, skipped_count, failed_count);
             println!("   Total: {} in {:.1}s ({:.1} MB/s)",
@@ -838,6 +1884,31 @@ fn handle_delete_batch(paths: Vec<PathBuf>, expire_days: i64, force: bool, trash
             "throughput_bytes_per_sec": throughput
         })));
     }
+
+    // 被暂停的删除总是以非零状态退出，调用它的脚本才能察觉
+    if paused > 0 && !rm_mode {
+        println!("⏸️  {} item(s) paused by the mass-deletion circuit breaker; run `srm status` for details", paused);
+        std::process::exit(1);
+    }
+
+    skipped_count == 0 && failed_count == 0 && !interrupted
+}
+
+// 规则或 git 检查要求确认的项目逐项询问
+fn confirm_item(question: &str, rm_mode: bool) -> Result<(), &'static str> {
+    if rm_mode {
+        eprint!("rm: {}? ", question);
+    } else {
+        eprint!("❓ {}? [y/N]: ", question);
+    }
+    io::stderr().flush().ok();
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).ok();
+    if input.trim().eq_ignore_ascii_case("y") || (rm_mode && input.trim_start().starts_with(['y', 'Y'])) {
+        Ok(())
+    } else {
+        Err("declined")
+    }
 }
 
 fn confirm_overwrite(path: &Path) -> bool {
@@ -848,28 +1919,32 @@ fn confirm_overwrite(path: &Path) -> bool {
     input.trim().eq_ignore_ascii_case("y")
 }
 
-fn handle_restore(names: Vec<String>, force: bool, target: Option<PathBuf>, meta_dir: &Path, trash_dir: &Path) {
-    let all_meta = list_all_meta(meta_dir, trash_dir);
-    let short_id_map: HashMap<String, String> = all_meta
+// 短ID或完整回收ID -> 回收ID
+fn resolve_trash_id(name: &str, all_meta: &HashMap<String, FileMeta>) -> Option<String> {
+    if all_meta.contains_key(name) {
+        return Some(name.to_string());
+    }
+    all_meta
         .iter()
-        .map(|(trash_id, meta)| (meta.short_id.clone(), trash_id.clone()))
-        .collect();
+        .find(|(_, meta)| meta.short_id == name)
+        .map(|(trash_id, _)| trash_id.clone())
+}
+
+fn handle_restore(names: Vec<String>, force: bool, target: Option<PathBuf>, verify: VerifyMode, ignore_checksum: bool, meta_dir: &Path, trash_dir: &Path) {
+    let all_meta = load_all_meta(meta_dir, trash_dir, true);
 
     let mut restored = 0;
     let mut failed = 0;
 
     for name in names {
-        let Some(trash_id) = short_id_map.get(&name).cloned().or_else(|| {
-            if all_meta.contains_key(&name) { Some(name.clone()) } else { None }
-        }) else {
+        let Some(trash_id) = resolve_trash_id(&name, &all_meta) else {
             eprintln!("❌  '{}' not found in trash (check with `srm ls`)", name);
             failed += 1;
             continue;
         };
 
-        let meta_path = meta_dir.join(format!("{}.meta", trash_id));
-        let content = match fs::read_to_string(&meta_path) {
-            Ok(c) => c,
+        let meta = match load_meta(&meta_file(meta_dir, &trash_id, "meta")) {
+            Ok((m, _)) => m,
             Err(e) => {
                 eprintln!("❌  Failed to read metadata for '{}': {}", name, e);
                 failed += 1;
@@ -877,54 +1952,113 @@ fn handle_restore(names: Vec<String>, force: bool, target: Option<PathBuf>, meta
             }
         };
 
-        let meta: FileMeta = match serde_json::from_str(&content) {
-            Ok(m) => m,
-            Err(e) => {
-                eprintln!("❌  Failed to parse metadata for '{}': {}", name, e);
-                failed += 1;
-                continue;
-            }
-        };
-
-        let trash_path = PathBuf::from(&meta.trash_path);
-        if !trash_path.exists() {
+        let trash_path = meta.trash(trash_dir);
+        if trash_path.symlink_metadata().is_err() {
             eprintln!("❌  Trash file missing for '{}'", name);
             remove_meta(&trash_id, meta_dir);
             failed += 1;
             continue;
         }
 
+        let problems = match verify_item(&trash_id, &meta, trash_dir, meta_dir) {
+            Ok(p) => p,
+            Err(e) => vec![format!("checksum verification failed: {}", e)],
+        };
+        if !problems.is_empty() {
+            log_event(if ignore_checksum { "WARN" } else { "ERROR" }, "Checksum mismatch", Some(json!({
+                "action": "restore",
+                "short_id": meta.short_id,
+                "trash_id": trash_id,
+                "problems": problems,
+                "ignored": ignore_checksum
+            })));
+            for problem in problems.iter().take(10) {
+                eprintln!("   {}", problem);
+            }
+            if !ignore_checksum {
+                eprintln!("❌  '{}' failed integrity check ({} problem(s)); use --ignore-checksum to restore anyway", name, problems.len());
+                failed += 1;
+                continue;
+            }
+            eprintln!("⚠️  '{}' failed integrity check ({} problem(s)); restoring anyway", name, problems.len());
+        }
+
         let final_target = if let Some(t) = &target {
             let t_abs = if t.is_absolute() {
                 t.clone()
             } else {
                 std::env::current_dir().map(|c| c.join(t)).unwrap_or_else(|_| t.clone())
             };
-            if t_abs.is_dir() {
-                t_abs.join(Path::new(&meta.original_path).file_name().unwrap())
+            // -t 由用户显式给出，允许其中含软链接，先规范化
+            let resolved = if t_abs.is_dir() {
+                t_abs.canonicalize().map(|d| d.join(meta.original().file_name().unwrap()))
             } else {
-                t_abs
+                match (t_abs.parent(), t_abs.file_name()) {
+                    (Some(parent), Some(file_name)) => parent.canonicalize().map(|p| p.join(file_name)),
+                    _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid target")),
+                }
+            };
+            match resolved {
+                Ok(p) => p,
+                Err(e) => {
+                    eprintln!("❌  Invalid target '{}': {}", t.display(), e);
+                    failed += 1;
+                    continue;
+                }
             }
         } else {
-            PathBuf::from(&meta.original_path)
+            meta.original()
+        };
+
+        // 原路径的父目录中出现软链接时拒绝恢复，避免被引导写入其他位置
+        let (target_dir, target_name) = match at::Dir::open_parent(&final_target) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("❌  Failed to restore '{}': {} (use -t to restore elsewhere)", name, e);
+                failed += 1;
+                continue;
+            }
         };
 
-        if final_target.exists() {
+        // 删除时留下了文件系统边界的目录：原目录仍在，逐项合并回去，已存在的条目留在回收站
+        let merge = !meta.mounts.is_empty() && target_dir.stat(&target_name).is_ok_and(|s| s.is_dir());
+        if merge {
+            if let Err(e) = undo_partial_move(&trash_path, &final_target, verify) {
+                eprintln!("❌  Failed to restore '{}': {}", name, e);
+                failed += 1;
+                continue;
+            }
+            if trash_path.symlink_metadata().is_ok() {
+                eprintln!("❌  Partially restored '{}': entries that already exist under {} were left in {}",
+                          name, final_target.display(), trash_path.display());
+                failed += 1;
+                continue;
+            }
+        } else if target_dir.stat(&target_name).is_ok() {
             if !force && !confirm_overwrite(&final_target) {
                 println!("✅  Skipped restoring '{}'", name);
                 continue;
             }
-            let _ = fs::remove_dir_all(&final_target);
+            let _ = target_dir.remove_tree(&target_name, MAX_RECURSION_DEPTH);
         }
 
-        if safe_move_with_progress(&trash_path, &final_target, false).is_err() {
-            eprintln!("❌  Failed to restore '{}'", name);
+        let moved = if merge {
+            Ok(0)
+        } else {
+            at::Dir::open_parent(&trash_path)
+                .and_then(|(store_dir, trash_name)| move_at(&store_dir, &trash_name, &target_dir, &target_name, false, verify))
+        };
+        if let Err(e) = moved {
+            eprintln!("❌  Failed to restore '{}': {}", name, e);
             failed += 1;
             continue;
         }
 
         if let Some(mode) = meta.permissions {
-            let _ = fs::set_permissions(&final_target, fs::Permissions::from_mode(mode));
+            let _ = target_dir.set_mode(&target_name, mode);
+        }
+        for problem in attrs::reapply(&final_target, &meta.attrs) {
+            eprintln!("⚠️  Failed to reapply attributes in '{}': {}", final_target.display(), problem);
         }
 
         remove_meta(&trash_id, meta_dir);
@@ -935,7 +2069,10 @@ fn handle_restore(names: Vec<String>, force: bool, target: Option<PathBuf>, meta
             "short_id": meta.short_id,
             "trash_id": trash_id,
             "original_path": meta.original_path,
-            "restored_path": final_target.display().to_string(),
+            "original_path_b64": meta.original_path_b64,
+            "restored_path": final_target.to_string_lossy(),
+            "restored_path_b64": encode_path(&final_target).1,
+            "merged": merge,
             "forced": force
         })));
 
@@ -971,7 +2108,80 @@ fn truncate_path(path: &str, max_len: usize) -> String {
     }
 }
 
-fn handle_list(meta_dir: &Path, trash_dir: &Path, expired: bool, verbose: bool) {
+fn handle_verify(ids: Vec<String>, meta_dir: &Path, trash_dir: &Path) {
+    let all_meta = list_all_meta(meta_dir, trash_dir);
+    if all_meta.is_empty() {
+        println!("📭 Trash is empty");
+        return;
+    }
+
+    let mut targets = Vec::new();
+    let mut failed = 0;
+    if ids.is_empty() {
+        targets.extend(all_meta.keys().cloned());
+        targets.sort();
+    } else {
+        for name in &ids {
+            match resolve_trash_id(name, &all_meta) {
+                Some(trash_id) => targets.push(trash_id),
+                None => {
+                    eprintln!("❌  '{}' not found in trash (check with `srm ls`)", name);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    let mut ok = 0;
+    let mut unchecked = 0;
+    for trash_id in &targets {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            println!("\n⚠️  Operation interrupted. Stopping...");
+            break;
+        }
+        let meta = &all_meta[trash_id];
+        if meta.checksum.is_none() {
+            println!("➖ {} ({}): no checksum recorded (renamed in place without --checksum always, or checksums were off)", meta.short_id, truncate_path(&meta.original_path, 40));
+            unchecked += 1;
+            continue;
+        }
+
+        let problems = match verify_item(trash_id, meta, trash_dir, meta_dir) {
+            Ok(p) => p,
+            Err(e) => vec![format!("checksum verification failed: {}", e)],
+        };
+        if problems.is_empty() {
+            println!("✅ {} ({})", meta.short_id, truncate_path(&meta.original_path, 40));
+            ok += 1;
+        } else {
+            println!("❌ {} ({}): {} problem(s)", meta.short_id, truncate_path(&meta.original_path, 40), problems.len());
+            for problem in problems.iter().take(10) {
+                println!("   {}", problem);
+            }
+            log_event("ERROR", "Checksum mismatch", Some(json!({
+                "action": "verify",
+                "short_id": meta.short_id,
+                "trash_id": trash_id,
+                "problems": problems
+            })));
+            failed += 1;
+        }
+    }
+
+    log_event("INFO", "Verify completed", Some(json!({
+        "action": "verify",
+        "ok": ok,
+        "failed": failed,
+        "unchecked": unchecked
+    })));
+    println!("\n✅ Verify completed ({} ok, {} failed, {} without checksum)", ok, failed, unchecked);
+
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn handle_list(meta_dir: &Path, trash_dir: &Path, expired: bool, verbose: bool, allocated: bool) {
     let now = Local::now();
     let all_meta = list_all_meta(meta_dir, trash_dir);
     if all_meta.is_empty() {
@@ -981,22 +2191,11 @@ fn handle_list(meta_dir: &Path, trash_dir: &Path, expired: bool, verbose: bool)
 
     let mut active = Vec::new();
     let mut expired_items = Vec::new();
+    let shown_size = |meta: &FileMeta| if allocated { meta.allocated() } else { meta.size_bytes };
+    let size_header = if allocated { "ON DISK" } else { "SIZE" };
 
     for (trash_id, meta) in all_meta {
-        let Some(delete_naive) = NaiveDateTime::parse_from_str(&meta.delete_time, "%Y-%m-%d %H:%M:%S").ok() else {
-            eprintln!("⚠️  跳过无效元数据：{}", meta.short_id);
-            remove_meta(&trash_id, meta_dir);
-            continue;
-        };
-        let delete_time = match Local.from_local_datetime(&delete_naive) {
-            LocalResult::Single(dt) => dt,
-            _ => {
-                eprintln!("⚠️  跳过时间无效的元数据：{}", meta.short_id);
-                remove_meta(&trash_id, meta_dir);
-                continue;
-            }
-        };
-        let expire_time = delete_time + Duration::days(meta.expire_days);
+        let expire_time = meta.expire_time.with_timezone(&Local);
         let is_expired = now > expire_time;
 
         if expired && !is_expired { continue; }
@@ -1018,7 +2217,7 @@ fn handle_list(meta_dir: &Path, trash_dir: &Path, expired: bool, verbose: bool)
     if !active.is_empty() && !expired {
         println!("📦 Active items ({}):", active.len());
         if !verbose {
-            println!("{:<12} {:<45} {:<12} {}", "🆔 SHORT", "ORIGINAL PATH", "EXPIRES IN", "SIZE");
+            println!("{:<12} {:<45} {:<12} {}", "🆔 SHORT", "ORIGINAL PATH", "EXPIRES IN", size_header);
             println!("{:-<12} {:-<45} {:-<12} {:-<15}", "", "", "", "");
         }
 
@@ -1026,25 +2225,34 @@ fn handle_list(meta_dir: &Path, trash_dir: &Path, expired: bool, verbose: bool)
             if verbose {
                 println!("\n🆔 {} ({})", meta.short_id, meta.trash_path);
                 println!("   Original: {}", meta.original_path);
-                let Some(delete_naive) = NaiveDateTime::parse_from_str(&meta.delete_time, "%Y-%m-%d %H:%M:%S").ok() else {
-                    continue;
-                };
-                let delete_chrono = match Local.from_local_datetime(&delete_naive) {
-                    LocalResult::Single(dt) => dt,
-                    _ => continue,
-                };
-                println!("   Deleted:  {} ({} ago)", meta.delete_time, format_duration(now.signed_duration_since(delete_chrono)));
+                let delete_time = meta.delete_time.with_timezone(&Local);
+                println!("   Deleted:  {} ({} ago)", delete_time.format(LEGACY_TIME_FORMAT), format_duration(now.signed_duration_since(delete_time)));
                 println!("   Expires:  in {} (on {})", format_duration(*duration),
-                    (Local::now() + *duration).format("%Y-%m-%d %H:%M:%S"));
-                println!("   Type:     {} | Size: {} | Perm: {:o}",
+                    meta.expire_time.with_timezone(&Local).format(LEGACY_TIME_FORMAT));
+                println!("   Type:     {} | Size: {} ({} on disk) | Perm: {:o}",
                     meta.file_type,
                     ByteSize(meta.size_bytes),
+                    ByteSize(meta.allocated()),
                     meta.permissions.unwrap_or(0) & 0o777);
+                if let Some(rdev) = meta.rdev {
+                    println!("   Device:   {}:{}", libc::major(rdev as libc::dev_t), libc::minor(rdev as libc::dev_t));
+                }
+                for repo in meta.git.iter().flat_map(|g| &g.repos) {
+                    println!("   Git:      {}", repo.describe());
+                }
+                if !meta.attrs.is_empty() {
+                    println!("   Attrs:    {}", attrs::summary(&meta.original(), &meta.attrs));
+                }
+                for b in &meta.mounts {
+                    println!("   Mount:    {}{}", b, if b.included { ", contents included" } else { ", left in place" });
+                }
             } else {
                 let size_display = if meta.file_type == FileType::Dir {
-                    format!("{} (dir)", ByteSize(meta.size_bytes))
+                    format!("{} (dir)", ByteSize(shown_size(meta)))
+                } else if !matches!(meta.file_type, FileType::File | FileType::Symlink) {
+                    format!("- ({})", meta.file_type)
                 } else {
-                    ByteSize(meta.size_bytes).to_string()
+                    ByteSize(shown_size(meta)).to_string()
                 };
                 println!("{:<12} {:<45} {:<12} {}",
                     meta.short_id,
@@ -1057,9 +2265,9 @@ fn handle_list(meta_dir: &Path, trash_dir: &Path, expired: bool, verbose: bool)
 
     if !expired_items.is_empty() {
         if !verbose && !active.is_empty() && !expired { println!(); }
-        println!("🗑️  {}", if expired { format!("Expired items ({})", expired_items.len()) } else { format!("Expired items ({})", expired_items.len()) });
+        println!("🗑️  Expired items ({}):", expired_items.len());
         if !verbose {
-            println!("{:<12} {:<45} {:<12} {}", "🆔 SHORT", "ORIGINAL PATH", "EXPIRED", "SIZE");
+            println!("{:<12} {:<45} {:<12} {}", "🆔 SHORT", "ORIGINAL PATH", "EXPIRED", size_header);
             println!("{:-<12} {:-<45} {:-<12} {:-<15}", "", "", "", "");
         }
 
@@ -1067,25 +2275,34 @@ fn handle_list(meta_dir: &Path, trash_dir: &Path, expired: bool, verbose: bool)
             if verbose {
                 println!("\n🆔 {} (EXPIRED)", meta.short_id);
                 println!("   Original: {}", meta.original_path);
-                let Some(delete_naive) = NaiveDateTime::parse_from_str(&meta.delete_time, "%Y-%m-%d %H:%M:%S").ok() else {
-                    continue;
-                };
-                let delete_chrono = match Local.from_local_datetime(&delete_naive) {
-                    LocalResult::Single(dt) => dt,
-                    _ => continue,
-                };
-                println!("   Deleted:  {} ({} ago)", meta.delete_time, format_duration(now.signed_duration_since(delete_chrono)));
+                let delete_time = meta.delete_time.with_timezone(&Local);
+                println!("   Deleted:  {} ({} ago)", delete_time.format(LEGACY_TIME_FORMAT), format_duration(now.signed_duration_since(delete_time)));
                 println!("   Expired:  {} ago (on {})", format_duration(*duration),
-                    (Local::now() - *duration).format("%Y-%m-%d %H:%M:%S"));
-                println!("   Type:     {} | Size: {} | Perm: {:o}",
+                    meta.expire_time.with_timezone(&Local).format(LEGACY_TIME_FORMAT));
+                println!("   Type:     {} | Size: {} ({} on disk) | Perm: {:o}",
                     meta.file_type,
                     ByteSize(meta.size_bytes),
+                    ByteSize(meta.allocated()),
                     meta.permissions.unwrap_or(0) & 0o777);
+                if let Some(rdev) = meta.rdev {
+                    println!("   Device:   {}:{}", libc::major(rdev as libc::dev_t), libc::minor(rdev as libc::dev_t));
+                }
+                for repo in meta.git.iter().flat_map(|g| &g.repos) {
+                    println!("   Git:      {}", repo.describe());
+                }
+                if !meta.attrs.is_empty() {
+                    println!("   Attrs:    {}", attrs::summary(&meta.original(), &meta.attrs));
+                }
+                for b in &meta.mounts {
+                    println!("   Mount:    {}{}", b, if b.included { ", contents included" } else { ", left in place" });
+                }
             } else {
                 let size_display = if meta.file_type == FileType::Dir {
-                    format!("{} (dir)", ByteSize(meta.size_bytes))
+                    format!("{} (dir)", ByteSize(shown_size(meta)))
+                } else if !matches!(meta.file_type, FileType::File | FileType::Symlink) {
+                    format!("- ({})", meta.file_type)
                 } else {
-                    ByteSize(meta.size_bytes).to_string()
+                    ByteSize(shown_size(meta)).to_string()
                 };
                 println!("{:<12} {:<45} {:<12} {}",
                     meta.short_id,
@@ -1096,40 +2313,28 @@ fn handle_list(meta_dir: &Path, trash_dir: &Path, expired: bool, verbose: bool)
         }
     }
 
+    let listed = active.iter().chain(&expired_items).filter(|(_, _, is_expired, _)| !expired || *is_expired);
+    let (count, apparent, on_disk) = listed.fold((0, 0, 0), |(c, a, d), (_, meta, _, _)| (c + 1, a + meta.size_bytes, d + meta.allocated()));
+    println!("\n📊 Total: {} item(s), {} ({} on disk)", count, ByteSize(apparent), ByteSize(on_disk));
+
     if !verbose && !expired {
-        println!("\n💡 Use `srm ls -v` for detailed view, `srm ls --expired` for expired items");
+        println!("\n💡 Use `srm ls -v` for detailed view, `srm ls --expired` for expired items, `srm ls --allocated` for disk usage");
     }
 }
 
 fn clean_trash(meta_dir: &Path, trash_dir: &Path, all: bool) {
-    let now = Local::now();
+    let now = Utc::now();
     let mut cleaned = 0;
     let mut total_size = 0u64;
 
-    for (trash_id, meta) in list_all_meta(meta_dir, trash_dir) {
-        let should_clean = if all {
-            true
-        } else {
-            let Some(delete_naive) = NaiveDateTime::parse_from_str(&meta.delete_time, "%Y-%m-%d %H:%M:%S").ok() else {
-                eprintln!("⚠️  清理无效元数据：{}", meta.short_id);
-                remove_meta(&trash_id, meta_dir);
-                continue;
-            };
-            let delete_time = match Local.from_local_datetime(&delete_naive) {
-                LocalResult::Single(dt) => dt,
-                _ => {
-                    remove_meta(&trash_id, meta_dir);
-                    continue;
-                }
-            };
-            now > (delete_time + Duration::days(meta.expire_days))
-        };
+    for (trash_id, meta) in load_all_meta(meta_dir, trash_dir, true) {
+        let should_clean = all || now > meta.expire_time;
 
         if should_clean {
-            let trash_path = PathBuf::from(&meta.trash_path);
+            let trash_path = meta.trash(trash_dir);
             let file_size = meta.size_bytes;
             total_size += file_size;
-            let _ = fs::remove_dir_all(&trash_path);
+            let _ = at::remove_tree(&trash_path, MAX_RECURSION_DEPTH);
             remove_meta(&trash_id, meta_dir);
             cleaned += 1;
 
@@ -1138,6 +2343,7 @@ fn clean_trash(meta_dir: &Path, trash_dir: &Path, all: bool) {
                 "short_id": meta.short_id,
                 "trash_id": trash_id,
                 "original_path": meta.original_path,
+                "original_path_b64": meta.original_path_b64,
                 "size_bytes": file_size,
                 "cleaned_all": all
             })));
@@ -1153,6 +2359,57 @@ fn clean_trash(meta_dir: &Path, trash_dir: &Path, all: bool) {
     }
 }
 
+// 存储位置、回收站用量与熔断状态；--reset 在终端确认后闭合熔断
+fn handle_status(reset: bool, base: &Path, trash_dir: &Path, meta_dir: &Path) {
+    let mut breaker = match breaker::Breaker::load(base) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("❌  {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if reset {
+        let Some(trip) = breaker.tripped().cloned() else {
+            println!("ℹ️  Circuit breaker is not open");
+            return;
+        };
+        if !io::stdin().is_terminal() {
+            eprintln!("❌  Closing the circuit breaker must be confirmed in a terminal");
+            std::process::exit(1);
+        }
+        println!("🚨 Circuit breaker is open ({})", trip);
+        if confirm_item("close the circuit breaker and resume deleting", false).is_err() {
+            println!("✅ Canceled");
+            return;
+        }
+        breaker.reset("srm status --reset");
+        println!("✅ Circuit breaker closed");
+        return;
+    }
+
+    let now = Utc::now();
+    let all_meta = list_all_meta(meta_dir, trash_dir);
+    let total_size = all_meta.values().map(|m| m.size_bytes).sum::<u64>();
+    let expired = all_meta.values().filter(|m| m.expire_time < now).count();
+    println!("📦 Store: {}", base.display());
+    println!("🗑️  Trash: {} item(s), {} ({} expired)", all_meta.len(), ByteSize(total_size), expired);
+
+    let config = &breaker.config;
+    let (items, bytes) = breaker.totals();
+    let window = format!("last {}: {}/{} items, {}/{}", config.window(), items, config.max_items, ByteSize(bytes), ByteSize(config.max_bytes));
+    if !config.enabled {
+        println!("⏸️  Circuit breaker: disabled in {}", base.join(breaker::CONFIG_FILE).display());
+    } else if let Some(trip) = breaker.tripped() {
+        println!("🚨 Circuit breaker: OPEN {}", trip);
+        println!("   Window: {}", window);
+        println!("   Deletes without a terminal are paused; run `srm status --reset` in a terminal to resume");
+    } else {
+        println!("✅ Circuit breaker: closed");
+        println!("   Window: {}", window);
+    }
+}
+
 fn handle_empty(yes: bool, trash_dir: &Path, meta_dir: &Path) {
     if !yes {
         print!("⚠️  Empty trash permanently? This cannot be undone! [y/N]: ");
@@ -1175,8 +2432,8 @@ fn handle_empty(yes: bool, trash_dir: &Path, meta_dir: &Path) {
         "total_size_bytes": total_size
     })));
 
-    let _ = fs::remove_dir_all(trash_dir);
-    let _ = fs::remove_dir_all(meta_dir);
+    let _ = at::remove_tree(trash_dir, MAX_RECURSION_DEPTH);
+    let _ = at::remove_tree(meta_dir, MAX_RECURSION_DEPTH);
     secure_create_dir(trash_dir).ok();
     secure_create_dir(meta_dir).ok();
 
@@ -1210,6 +2467,21 @@ fn handle_empty(yes: bool, trash_dir: &Path, meta_dir: &Path) {
 struct Cli {
     #[command(subcommand)]
     cmd: Commands,
+    #[arg(long, global = true, default_value_t = LOCK_TIMEOUT_SECS, help = "Seconds to wait for another srm process to release the store")]
+    lock_timeout: u64,
+}
+
+impl Commands {
+    // 只读命令共享存储锁，其余命令独占
+    fn lock_mode(&self) -> lock::LockMode {
+        match self {
+            Commands::List { .. } | Commands::Verify { .. } => lock::LockMode::Shared,
+            Commands::Status { reset: false } => lock::LockMode::Shared,
+            Commands::Fsck { repair: false } | Commands::RebuildMeta { apply: false, .. } => lock::LockMode::Shared,
+            Commands::Delete { dry_run: true, .. } | Commands::Import { dry_run: true, .. } => lock::LockMode::Shared,
+            _ => lock::LockMode::Exclusive,
+        }
+    }
 }
 
 #[derive(Subcommand, Debug)]
@@ -1220,8 +2492,29 @@ pub enum Commands {
         paths: Vec<PathBuf>,
         #[arg(short = 'd', long, default_value_t = DEFAULT_EXPIRE_DAYS, help = "Expiration days before auto-cleanup")]
         expire_days: i64,
-        #[arg(short = 'f', long, help = "Force delete protected paths and disable safety checks")]
+        #[arg(short = 'f', long, help = "Allow deleting the current directory or its parents and skip the unsaved git work prompt (other guard rules need --allow)")]
         force: bool,
+        #[arg(long, value_enum, value_delimiter = ',', value_name = "RULE", help = "Override one guard rule (repeatable or comma-separated)")]
+        allow: Vec<guard::Rule>,
+        #[arg(long, value_enum, default_value_t = VerifyMode::Auto, help = "Verify cross-device copies before removing the source")]
+        verify: VerifyMode,
+        #[arg(long, value_enum, default_value_t = ChecksumMode::Auto, help = "Record content checksums for restore and `srm verify`; auto skips items renamed within the trash filesystem (use always to hash them too)")]
+        checksum: ChecksumMode,
+        #[arg(short = 'n', long, help = "Show the plan (strategy, space needed in trash, protections) without deleting")]
+        dry_run: bool,
+        #[arg(long, value_enum, default_value_t = plan::PlanFormat::Table, requires = "dry_run", help = "Plan output format")]
+        format: plan::PlanFormat,
+        #[arg(short = 'j', long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..=256),
+              help = "Threads used to stat directories that must be copied")]
+        jobs: u16,
+        #[arg(long, value_enum, default_value_t = plan::MountPolicy::Refuse, value_name = "POLICY",
+              help = "Other filesystems mounted inside a directory: refuse the item, skip them, or include their contents")]
+        mounts: plan::MountPolicy,
+        #[arg(long, value_enum, default_value_t = plan::BusyPolicy::Warn, value_name = "POLICY",
+              help = "Targets other processes have open or mapped: warn and delete, or deny (skip them)")]
+        busy: plan::BusyPolicy,
+        #[arg(long, help = "Clear immutable/append-only attributes before moving (root only; reapplied on restore)")]
+        clear_attrs: bool,
     },
     #[command(alias = "res", about = "Restore files from trash using short ID")]
     Restore {
@@ -1231,6 +2524,27 @@ pub enum Commands {
         force: bool,
         #[arg(short = 't', long = "target", help = "Custom restore path")]
         target: Option<PathBuf>,
+        #[arg(long, value_enum, default_value_t = VerifyMode::Auto, help = "Verify cross-device copies before removing the trash copy")]
+        verify: VerifyMode,
+        #[arg(long, help = "Restore even if the item fails its integrity check")]
+        ignore_checksum: bool,
+    },
+    #[command(about = "Check trash items against the checksums recorded at delete time")]
+    Verify {
+        #[arg(help = "Short IDs or full trash IDs (default: whole trash)")]
+        ids: Vec<String>,
+    },
+    #[command(about = "Check the trash store for inconsistencies and optionally repair them")]
+    Fsck {
+        #[arg(long, help = "Repair problems (adopt orphans, drop stale files, reassign duplicate IDs)")]
+        repair: bool,
+    },
+    #[command(about = "Rebuild lost or corrupted metadata by replaying the audit log")]
+    RebuildMeta {
+        #[arg(long, required = true, help = "Replay delete/restore/clean events from srm.log")]
+        from_log: bool,
+        #[arg(long, help = "Write the rebuilt metadata (default: show the diff only)")]
+        apply: bool,
     },
     #[command(alias = "ls", about = "List trash contents with sizes")]
     List {
@@ -1238,17 +2552,62 @@ pub enum Commands {
         expired: bool,
         #[arg(short = 'v', long, help = "Verbose mode with full metadata")]
         verbose: bool,
+        #[arg(long, help = "Show allocated disk usage (st_blocks, hard links counted once) instead of apparent size")]
+        allocated: bool,
+    },
+    #[command(about = "Show the store location, trash usage and mass-deletion circuit breaker state")]
+    Status {
+        #[arg(long, help = "Close an open circuit breaker (must be confirmed in a terminal)")]
+        reset: bool,
     },
     #[command(alias = "cln", about = "Clean expired items")]
     Clean {
         #[arg(short = 'a', long, help = "Clean all items (not just expired)")]
         all: bool,
     },
-    #[command(alias = "empty", about = "Permanently empty entire trash")]
+    #[command(about = "Permanently empty entire trash")]
     Empty {
         #[arg(short = 'y', long, help = "Skip confirmation prompt")]
         yes: bool,
     },
+    #[command(about = "Import items from FreeDesktop/trash-cli trash directories or another srm store")]
+    Import {
+        #[arg(required = true, help = "Trash directories, volume top directories or .srm stores")]
+        sources: Vec<PathBuf>,
+        #[arg(long = "from", value_enum, default_value_t = import::ImportFormat::Auto, help = "Source layout")]
+        format: import::ImportFormat,
+        #[arg(short = 'd', long, default_value_t = DEFAULT_EXPIRE_DAYS, help = "Expiration days for items without srm metadata, counted from the import")]
+        expire_days: i64,
+        #[arg(short = 'n', long, help = "Only list what would be imported")]
+        dry_run: bool,
+        #[arg(long, value_enum, default_value_t = VerifyMode::Auto, help = "Verify cross-device copies before removing the source")]
+        verify: VerifyMode,
+        #[arg(long, help = "Skip recording content checksums for FreeDesktop items")]
+        no_checksum: bool,
+    },
+    #[command(about = "Manage the trash store itself")]
+    Store {
+        #[command(subcommand)]
+        action: StoreAction,
+    },
+    #[command(about = "GNU rm compatible front end (also used when srm is invoked as `rm`)", disable_help_flag = true)]
+    Rm {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, help = "rm options and files (see `srm rm --help`)")]
+        args: Vec<OsString>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StoreAction {
+    #[command(about = "Move the whole store to another directory, keeping every short ID")]
+    Move {
+        #[arg(help = "New store directory (must not exist or be empty)")]
+        dest: PathBuf,
+        #[arg(long, value_enum, default_value_t = VerifyMode::Auto, help = "Verify cross-device copies before removing the originals")]
+        verify: VerifyMode,
+    },
+    #[command(about = "Move items of a flat (pre-sharding) store into hashed subdirectories")]
+    Reshard,
 }
 
 fn main() {
@@ -1259,17 +2618,65 @@ fn main() {
     secure_create_dir(&trash_dir).expect("Failed to create trash dir");
     secure_create_dir(&meta_dir).expect("Failed to create meta dir");
 
-    rotate_logs(&base);
+    let cli = if rm::invoked_as_rm() {
+        Cli { cmd: Commands::Rm { args: std::env::args_os().skip(1).collect() }, lock_timeout: LOCK_TIMEOUT_SECS }
+    } else {
+        Cli::parse()
+    };
+    at::raise_fd_limit();
+    let mode = cli.cmd.lock_mode();
+    let _store_lock = match lock::acquire(&base, mode, std::time::Duration::from_secs(cli.lock_timeout)) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("❌  {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // 等锁期间存储可能已被 `srm store move` 迁走
+    if get_srm_base() != base {
+        eprintln!("❌  The store was moved while waiting for the lock; please re-run the command");
+        std::process::exit(1);
+    }
+
+    // rebuild-meta 要读取完整的日志，不在此时轮转
+    if mode == lock::LockMode::Exclusive && !matches!(cli.cmd, Commands::RebuildMeta { .. }) {
+        rotate_logs(&base);
+    }
 
-    match Cli::parse().cmd {
-        Commands::Delete { paths, expire_days, force } => {
-            handle_delete_batch(paths, expire_days, force, &trash_dir, &meta_dir);
+    match cli.cmd {
+        Commands::Delete { paths, expire_days, force, allow, verify, checksum, dry_run, format, jobs, mounts, busy, clear_attrs } => {
+            let plan = match plan::build(&paths, &guard::Overrides { force, allow }, mounts, busy, clear_attrs, jobs as usize, &trash_dir) {
+                Ok(p) => p,
+                Err(e) => {
+                    eprintln!("❌  {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if dry_run {
+                plan.print(format);
+            } else {
+                handle_delete_batch(plan, expire_days, force, verify, checksum, DeleteOutput::Normal, &trash_dir, &meta_dir);
+            }
+        }
+        Commands::Restore { names, force, target, verify, ignore_checksum } => {
+            handle_restore(names, force, target, verify, ignore_checksum, &meta_dir, &trash_dir);
+        }
+        Commands::Fsck { repair } => {
+            fsck::handle_fsck(repair, &base, &trash_dir, &meta_dir);
         }
-        Commands::Restore { names, force, target } => {
-            handle_restore(names, force, target, &meta_dir, &trash_dir);
+        Commands::RebuildMeta { from_log: _, apply } => {
+            rebuild::handle_rebuild_meta(apply, &base, &trash_dir, &meta_dir);
         }
-        Commands::List { expired, verbose } => {
-            handle_list(&meta_dir, &trash_dir, expired, verbose);
+        Commands::Verify { ids } => {
+            setup_interrupt_handler();
+            handle_verify(ids, &meta_dir, &trash_dir);
+        }
+        Commands::List { expired, verbose, allocated } => {
+            handle_list(&meta_dir, &trash_dir, expired, verbose, allocated);
+        }
+        Commands::Status { reset } => {
+            handle_status(reset, &base, &trash_dir, &meta_dir);
         }
         Commands::Clean { all } => {
             clean_trash(&meta_dir, &trash_dir, all);
@@ -1277,5 +2684,387 @@ fn main() {
         Commands::Empty { yes } => {
             handle_empty(yes, &trash_dir, &meta_dir);
         }
+        Commands::Import { sources, format, expire_days, dry_run, verify, no_checksum } => {
+            setup_interrupt_handler();
+            import::handle_import(sources, format, expire_days, dry_run, verify, !no_checksum,
+                                  std::time::Duration::from_secs(cli.lock_timeout), &base, &trash_dir, &meta_dir);
+        }
+        Commands::Store { action: StoreAction::Move { dest, verify } } => {
+            setup_interrupt_handler();
+            store::handle_store_move(&dest, verify, &base, &trash_dir, &meta_dir);
+        }
+        Commands::Store { action: StoreAction::Reshard } => {
+            setup_interrupt_handler();
+            store::handle_store_reshard(&base, &trash_dir, &meta_dir);
+        }
+        Commands::Rm { args } => {
+            let args = if rm::invoked_as_rm() { args } else { rm::raw_args() };
+            rm::handle_rm(args, &trash_dir, &meta_dir);
+        }
+    }
+}
+
+// 单元测试用的最小元数据：普通文件，trash_rel 为回收项相对存储根目录的位置（也可以是旧版的绝对路径）
+#[cfg(test)]
+fn test_meta(short_id: &str, trash_rel: &Path) -> FileMeta {
+    let delete_time = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+    let mut meta = FileMeta {
+        schema_version: META_SCHEMA_VERSION,
+        original_path: format!("/data/{}", short_id),
+        trash_path: String::new(),
+        original_path_b64: None,
+        trash_path_b64: None,
+        delete_time,
+        expire_time: delete_time + Duration::days(DEFAULT_EXPIRE_DAYS),
+        expire_days: DEFAULT_EXPIRE_DAYS,
+        file_type: FileType::File,
+        rdev: None,
+        permissions: Some(0o100644),
+        uid: Some(0),
+        gid: Some(0),
+        short_id: short_id.to_string(),
+        size_bytes: 0,
+        allocated_bytes: None,
+        checksum: None,
+        git: None,
+        mounts: Vec::new(),
+        attrs: Vec::new(),
+    };
+    meta.set_trash(trash_rel);
+    meta
+}
+
+// 单元测试用的临时目录，每次调用都重新创建为空目录
+#[cfg(test)]
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("srm-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, NaiveDate, NaiveTime};
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn migrated(delete_time: &str, expire_days: Option<i64>) -> Result<serde_json::Value, String> {
+        let mut value = json!({ "delete_time": delete_time, "trash_path": "/nonexistent/srm-test" });
+        if let Some(days) = expire_days {
+            value["expire_days"] = json!(days);
+        }
+        migrate_v0_to_v1(&mut value).map(|_| value)
+    }
+
+    // 2024 年的中欧时间：3 月 31 日 01:00Z 起为 +2，10 月 27 日 01:00Z 起回到 +1
+    #[derive(Clone)]
+    struct Cet2024;
+
+    impl TimeZone for Cet2024 {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            Cet2024
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+        }
+
+        // 和 Local 一样，重复的时刻把较晚的一个放在前面
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            let valid: Vec<FixedOffset> = [1, 2]
+                .into_iter()
+                .map(|h| FixedOffset::east_opt(h * 3600).unwrap())
+                .filter(|o| self.offset_from_utc_datetime(&(*local - Duration::seconds(o.local_minus_utc() as i64))) == *o)
+                .collect();
+            match valid[..] {
+                [] => LocalResult::None,
+                [o] => LocalResult::Single(o),
+                [winter, summer] => LocalResult::Ambiguous(winter, summer),
+                _ => unreachable!(),
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_utc_datetime(&self, at: &NaiveDateTime) -> FixedOffset {
+            let summer = utc("2024-03-31T01:00:00Z").naive_utc() <= *at && *at < utc("2024-10-27T01:00:00Z").naive_utc();
+            FixedOffset::east_opt(if summer { 7200 } else { 3600 }).unwrap()
+        }
+    }
+
+    fn naive(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, LEGACY_TIME_FORMAT).unwrap()
+    }
+
+    #[test]
+    fn converts_local_times_across_dst() {
+        assert_eq!(zoned_to_utc(&Cet2024, &naive("2024-01-15 12:00:00")), utc("2024-01-15T11:00:00Z"));
+        assert_eq!(zoned_to_utc(&Cet2024, &naive("2024-07-01 12:00:00")), utc("2024-07-01T10:00:00Z"));
+        // 回拨：02:30 出现两次，取较早的一次（夏令时）
+        assert_eq!(zoned_to_utc(&Cet2024, &naive("2024-10-27 02:30:00")), utc("2024-10-27T00:30:00Z"));
+        // 跳变：02:30 不存在，按跳变后的偏移解释
+        assert_eq!(zoned_to_utc(&Cet2024, &naive("2024-03-31 02:30:00")), utc("2024-03-31T00:30:00Z"));
+    }
+
+    // 进程时区由环境决定，这里只检查迁移沿用 local_to_utc 的结果并据此计算过期时间
+    #[test]
+    fn migrates_v0_local_times() {
+        let deleted = local_to_utc(&naive("2024-01-15 12:00:00"));
+        let v = migrated("2024-01-15 12:00:00", Some(3)).unwrap();
+        assert_eq!(v["schema_version"], 1);
+        assert_eq!(v["delete_time"], json!(deleted));
+        assert_eq!(v["expire_time"], json!(deleted + Duration::days(3)));
+
+        let deleted = local_to_utc(&naive("2024-07-01 12:00:00"));
+        let v = migrated("2024-07-01 12:00:00", None).unwrap();
+        assert_eq!(v["delete_time"], json!(deleted));
+        assert_eq!(v["expire_time"], json!(deleted + Duration::days(DEFAULT_EXPIRE_DAYS)));
+        assert_eq!(v["expire_days"], DEFAULT_EXPIRE_DAYS);
+    }
+
+    fn log_line(timestamp: &str, message: &str, trash_id: &str) -> String {
+        json!({ "timestamp": timestamp, "level": "INFO", "message": message, "details": { "trash_id": trash_id } }).to_string()
+    }
+
+    #[test]
+    fn rotation_keeps_delete_records_of_items_still_in_trash() {
+        let base = std::env::temp_dir().join(format!("srm-test-{}-rotate", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        fs::create_dir_all(base.join("trash").join("kept_1")).unwrap();
+        let now = Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+        let old = "2000-01-01 12:00:00.000";
+        let lines = [
+            log_line(old, "File deleted", "kept_1"),
+            log_line(old, "File deleted", "gone_2"),
+            log_line(old, "File restored", "kept_1"),
+            log_line(old, "File imported", "kept_1"),
+            log_line(&now, "File restored", "gone_2"),
+        ];
+        fs::write(base.join("srm.log"), lines.join("\n") + "\n").unwrap();
+
+        rotate_logs(&base);
+        let rotated = fs::read_to_string(base.join("srm.log")).unwrap();
+        let _ = fs::remove_dir_all(&base);
+        assert_eq!(rotated.lines().collect::<Vec<_>>(), [&lines[0], &lines[3], &lines[4]]);
+    }
+
+    #[test]
+    fn migrates_rfc3339_delete_time() {
+        let v = migrated("2024-05-01T08:00:00Z", Some(1)).unwrap();
+        assert_eq!(v["delete_time"], json!(utc("2024-05-01T08:00:00Z")));
+        assert_eq!(v["expire_time"], json!(utc("2024-05-02T08:00:00Z")));
+    }
+
+    #[test]
+    fn unreadable_delete_time_without_trash_entry_fails() {
+        assert!(migrated("yesterday", Some(1)).is_err());
+        assert!(migrate_v0_to_v1(&mut json!("not an object")).is_err());
+    }
+
+    #[test]
+    fn non_utf8_paths_round_trip_through_metadata() {
+        let original = PathBuf::from(OsString::from_vec(b"/home/u/caf\xe9/\xff.txt".to_vec()));
+        let (display, b64) = encode_path(&original);
+        assert_eq!(display, "/home/u/caf\u{fffd}/\u{fffd}.txt");
+        assert_eq!(decode_path(&display, b64.as_deref()), original);
+        assert_eq!(encode_path(Path::new("/home/u/café.txt")), ("/home/u/café.txt".to_string(), None));
+        // 损坏的 base64 退回显示形式
+        assert_eq!(decode_path("/a/b", Some("not base64!")), PathBuf::from("/a/b"));
+
+        let v0 = json!({
+            "original_path": "/placeholder", "trash_path": "/placeholder", "delete_time": "2024-05-01T08:00:00Z",
+            "file_type": "File", "permissions": 0o644, "uid": 0, "gid": 0, "short_id": "abc1234", "size_bytes": 0
+        });
+        let (mut meta, _) = parse_meta(&v0.to_string()).unwrap();
+        (meta.original_path, meta.original_path_b64) = (display, b64);
+        let trash_rel = PathBuf::from(OsString::from_vec(b"trash/ab/\xff.txt_1".to_vec()));
+        meta.set_trash(&trash_rel);
+
+        let (reloaded, upgraded) = parse_meta(&serde_json::to_string(&meta).unwrap()).unwrap();
+        assert!(!upgraded);
+        assert_eq!(reloaded.original(), original);
+        assert_eq!(reloaded.trash(Path::new("/store/trash")), Path::new("/store").join(trash_rel));
+    }
+
+    #[test]
+    fn trash_ids_have_fixed_length() {
+        let long = PathBuf::from("/data").join("n".repeat(255));
+        let (a, b) = (new_trash_id(&long), new_trash_id(Path::new("/x")));
+        assert_eq!((a.len(), b.len()), (29, 29));
+        let (nanos, hash) = a.split_once('-').unwrap();
+        assert!(nanos.bytes().all(|c| c.is_ascii_digit()) && hash.bytes().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(a[21..], b[21..]);
+    }
+
+    #[test]
+    fn atomic_write_replaces_with_private_file_and_skips_taken_names() {
+        let dir = scratch_dir("atomic-write");
+        fs::write(dir.join("a.meta"), "old").unwrap();
+        fs::write(dir.join("victim"), "keep").unwrap();
+        // 预先在接下来可能用到的临时名称上放置软链接
+        let next = TMP_COUNTER.load(Ordering::Relaxed);
+        for n in next..next + 64 {
+            std::os::unix::fs::symlink(dir.join("victim"), dir.join(format!("a.meta.{}-{}.tmp", std::process::id(), n))).unwrap();
+        }
+
+        atomic_write(&dir, "a.meta", b"new").unwrap();
+        assert_eq!(fs::read_to_string(dir.join("a.meta")).unwrap(), "new");
+        assert_eq!(fs::symlink_metadata(dir.join("a.meta")).unwrap().permissions().mode() & 0o7777, 0o600);
+        assert_eq!(fs::read_to_string(dir.join("victim")).unwrap(), "keep");
+        let links = fs::read_dir(&dir).unwrap().flatten().filter(|e| e.file_type().unwrap().is_symlink()).count();
+        assert_eq!(links, 64);
+
+        // 目录路径经过软链接时拒绝写入
+        std::os::unix::fs::symlink(&dir, dir.with_extension("link")).unwrap();
+        let err = atomic_write(&dir.with_extension("link"), "b.meta", b"x").unwrap_err();
+        let _ = fs::remove_file(dir.with_extension("link"));
+        let _ = fs::remove_dir_all(&dir);
+        assert!(err.to_string().contains("symbolic link"));
+    }
+
+    #[test]
+    fn recognises_temp_file_targets() {
+        assert_eq!(tmp_target("abc.meta.1234-7.tmp"), Some("abc.meta"));
+        assert_eq!(tmp_target("abc.manifest.1-0.tmp"), Some("abc.manifest"));
+        // 旧版本的 <名称>.tmp
+        assert_eq!(tmp_target("abc.meta.tmp"), Some("abc.meta"));
+        assert_eq!(tmp_target("abc.meta.12x-3.tmp"), Some("abc.meta.12x-3"));
+        assert_eq!(tmp_target("abc.meta"), None);
+    }
+
+    #[test]
+    fn resolves_legacy_absolute_trash_paths_in_a_moved_store() {
+        let store = scratch_dir("legacy-path");
+        let trash_dir = store.join("trash");
+        fs::create_dir_all(&trash_dir).unwrap();
+        fs::write(trash_dir.join("a.txt_1700000000000000000"), "x").unwrap();
+        // 记录的是复制前的绝对路径，当前存储中有同名回收项
+        let legacy = test_meta("fa", Path::new("/old/.srm/trash/a.txt_1700000000000000000"));
+        assert_eq!(legacy.trash(&trash_dir), trash_dir.join("a.txt_1700000000000000000"));
+        // 两处都没有时按当前存储报告（由调用方发现缺失）
+        let missing = test_meta("fb", Path::new("/old/.srm/trash/b.txt_1700000000000000000"));
+        assert_eq!(missing.trash(&trash_dir), trash_dir.join("b.txt_1700000000000000000"));
+        // 只有旧位置存在时沿用旧位置
+        fs::write(store.join("elsewhere"), "x").unwrap();
+        let elsewhere = test_meta("fd", &store.join("elsewhere"));
+        assert_eq!(elsewhere.trash(&trash_dir), store.join("elsewhere"));
+        let relative = test_meta("fc", Path::new("trash/ab/c"));
+        assert_eq!(relative.trash(&trash_dir), store.join("trash/ab/c"));
+        let _ = fs::remove_dir_all(&store);
+    }
+
+    #[test]
+    fn shards_by_trash_id_hash_and_keeps_flat_entries_usable() {
+        let id = "01700000000000000001-0a1b2c3d";
+        let shard = shard_of(id);
+        assert_eq!(shard, blake3::hash(id.as_bytes()).to_hex()[..2]);
+        assert!(is_shard_name(OsStr::new(&shard)));
+        assert!(!is_shard_name(OsStr::new("0g")) && !is_shard_name(OsStr::new("abc")) && !is_shard_name(OsStr::new("AB")));
+        assert_eq!(trash_rel(id), Path::new("trash").join(&shard).join(id));
+
+        let meta_dir = scratch_dir("shard-meta");
+        assert_eq!(meta_home(&meta_dir, id), meta_dir.join(&shard));
+        assert_eq!(meta_file(&meta_dir, id, "manifest"), meta_dir.join(&shard).join(format!("{}.manifest", id)));
+        // 扁平存储中已有的元数据原地使用
+        fs::write(meta_dir.join(format!("{}.meta", id)), "{}").unwrap();
+        assert_eq!(meta_home(&meta_dir, id), meta_dir);
+        save_manifest(id, b"m", &meta_dir).unwrap();
+        assert!(meta_dir.join(format!("{}.manifest", id)).exists());
+
+        // store_entries 展开分片子目录，其他子目录原样列出
+        fs::create_dir_all(meta_dir.join("ff")).unwrap();
+        fs::write(meta_dir.join("ff/x.meta"), "").unwrap();
+        fs::create_dir_all(meta_dir.join("notashard")).unwrap();
+        let mut entries: Vec<String> = store_entries(&meta_dir).iter()
+            .map(|p| p.strip_prefix(&meta_dir).unwrap().display().to_string()).collect();
+        entries.sort();
+        let _ = fs::remove_dir_all(&meta_dir);
+        assert_eq!(entries, [format!("{}.manifest", id), format!("{}.meta", id), "ff/x.meta".into(), "notashard".into()]);
+    }
+
+    #[test]
+    fn parse_meta_upgrades_v0_records() {
+        let v0 = json!({
+            "original_path": "/home/u/a.txt",
+            "trash_path": "/home/u/.srm/trash/a",
+            "delete_time": "2024-05-01T08:00:00Z",
+            "expire_days": 2,
+            "file_type": "File",
+            "permissions": 0o644,
+            "uid": 1000,
+            "gid": 1000,
+            "short_id": "abc1234",
+            "size_bytes": 3
+        });
+        let (meta, upgraded) = parse_meta(&v0.to_string()).unwrap();
+        assert!(upgraded);
+        assert_eq!(meta.schema_version, 1);
+        assert_eq!(meta.expire_time, utc("2024-05-03T08:00:00Z"));
+
+        let (_, upgraded) = parse_meta(&serde_json::to_string(&meta).unwrap()).unwrap();
+        assert!(!upgraded);
+        assert!(parse_meta(&json!({ "schema_version": META_SCHEMA_VERSION + 1 }).to_string()).is_err());
+    }
+
+    #[test]
+    fn recreates_nodes_instead_of_copying_them() {
+        let src = scratch_dir("nodes-src");
+        // 跨设备才会走重建路径；/dev/shm 与临时目录在同一文件系统时只检查类型
+        let dst = Path::new("/dev/shm").join(format!("srm-test-{}-nodes", std::process::id()));
+        let _ = fs::remove_dir_all(&dst);
+        let cross_device = fs::create_dir_all(&dst).is_ok()
+            && fs::metadata(&dst).unwrap().dev() != fs::metadata(&src).unwrap().dev();
+        let root = unsafe { libc::geteuid() } == 0;
+
+        let mkfifo = |path: &Path| {
+            let c = std::ffi::CString::new(path.as_os_str().as_bytes()).unwrap();
+            assert_eq!(unsafe { libc::mkfifo(c.as_ptr(), 0o640) }, 0);
+        };
+        mkfifo(&src.join("fifo"));
+        fs::create_dir_all(src.join("tree/sub")).unwrap();
+        mkfifo(&src.join("tree/sub/fifo"));
+        let _socket = std::os::unix::net::UnixListener::bind(src.join("tree/sock")).unwrap();
+        fs::write(src.join("tree/file"), "data").unwrap();
+        let null = libc::makedev(1, 3);
+        if root {
+            at::Dir::open(&src.join("tree")).unwrap().mknod(OsStr::new("null"), libc::S_IFCHR | 0o600, null).unwrap();
+        }
+        let kinds = |dir: &Path| ["fifo", "tree/sub/fifo", "tree/sock", "tree/file", "tree/null"].map(|rel| {
+            fs::symlink_metadata(dir.join(rel)).ok().map(|m| (FileType::of(&m), FileType::device(&m), m.mode() & 0o7777))
+        });
+        let before = kinds(&src);
+        assert_eq!(before[0].map(|k| k.0), Some(FileType::Fifo));
+        assert_eq!(before[2].map(|k| k.0), Some(FileType::Socket));
+
+        if cross_device {
+            // 打开 FIFO 会一直阻塞，能返回说明没有复制数据
+            let (size, digest) = safe_move_hashed(&src.join("fifo"), &dst.join("fifo"), false, VerifyMode::Hash, ChecksumMode::Always).unwrap();
+            assert_eq!(size, 0);
+            assert!(matches!(digest, Some(Ok(Digest::Item(d))) if d == node_hash(FileType::Fifo, 0)));
+            safe_move_with_progress(&src.join("tree"), &dst.join("tree"), false, VerifyMode::Hash).unwrap();
+            let after = kinds(&dst);
+            assert!(fs::symlink_metadata(src.join("fifo")).is_err() && !src.join("tree").exists());
+            assert_eq!(after, before);
+            assert_eq!(fs::read_to_string(dst.join("tree/file")).unwrap(), "data");
+            if root {
+                assert_eq!(after[4].and_then(|k| k.1), Some(null));
+            }
+        }
+        let _ = fs::remove_dir_all(&src);
+        let _ = fs::remove_dir_all(&dst);
+
+        let mut ids = HashSet::new();
+        let prefixes: Vec<char> = [FileType::Fifo, FileType::Socket, FileType::CharDevice, FileType::BlockDevice]
+            .map(|t| generate_short_id("x_1", t, &mut ids).chars().next().unwrap()).to_vec();
+        assert_eq!(prefixes, ['p', 's', 'c', 'b']);
+        assert_eq!(FileType::from_mode(libc::S_IFBLK | 0o660), FileType::BlockDevice);
     }
 }
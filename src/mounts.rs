@@ -0,0 +1,102 @@
+// /proc/self/mountinfo 解析：按设备号查找挂载点、文件系统类型与来源设备
+
+use crate::at;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct Mount {
+    pub dev: u64,
+    pub mount_point: PathBuf,
+    pub fstype: String,
+    pub source: String,
+}
+
+// 支持跨挂载点（如 btrfs 子卷之间）reflink 的文件系统
+const REFLINK_FS: [&str; 4] = ["btrfs", "xfs", "bcachefs", "ocfs2"];
+
+// mountinfo 中的空格、制表符、换行与反斜杠以 \ooo 八进制转义
+fn unescape(field: &str) -> OsString {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|d| std::str::from_utf8(d).ok()).and_then(|d| u8::from_str_radix(d, 8).ok());
+        if let (b'\\', Some(byte)) = (bytes[i], octal) {
+            out.push(byte);
+            i += 4;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    OsString::from_vec(out)
+}
+
+fn parse_line(line: &str) -> Option<Mount> {
+    let (left, right) = line.split_once(" - ")?;
+    let fields: Vec<&str> = left.split(' ').collect();
+    let (major, minor) = fields.get(2)?.split_once(':')?;
+    let mut right = right.split(' ');
+    Some(Mount {
+        dev: libc::makedev(major.parse().ok()?, minor.parse().ok()?),
+        mount_point: PathBuf::from(unescape(fields.get(4)?)),
+        fstype: right.next()?.to_string(),
+        source: right.next().unwrap_or_default().to_string(),
+    })
+}
+
+pub fn read_mountinfo() -> Vec<Mount> {
+    fs::read_to_string("/proc/self/mountinfo")
+        .map(|content| content.lines().filter_map(parse_line).collect())
+        .unwrap_or_default()
+}
+
+// 同一设备可能挂载多次（bind mount），取最后一条，即当前可见的那次挂载
+pub fn by_dev(mounts: &[Mount], dev: u64) -> Option<&Mount> {
+    mounts.iter().rev().find(|m| m.dev == dev)
+}
+
+// 两个设备号属于同一个支持 reflink 的文件系统（来源设备与类型都相同）
+pub fn same_reflink_fs(mounts: &[Mount], a: u64, b: u64) -> bool {
+    match (by_dev(mounts, a), by_dev(mounts, b)) {
+        (Some(x), Some(y)) => {
+            x.fstype == y.fstype && x.source == y.source && x.source != "none" && REFLINK_FS.contains(&x.fstype.as_str())
+        }
+        _ => false,
+    }
+}
+
+// 挂载在 dir 之下（不含 dir 本身）的挂载点；dir 应为规范化路径
+pub fn nested<'a>(mounts: &'a [Mount], dir: &'a Path) -> impl Iterator<Item = &'a Mount> {
+    mounts.iter().filter(move |m| m.mount_point != dir && m.mount_point.starts_with(dir))
+}
+
+// 目录树中的文件系统边界：挂载表中位于树内的挂载点（同一文件系统的 bind mount 设备号不变），
+// 以及设备号与上级目录不同的条目（挂载表之外的挂载、btrfs 子卷等）
+pub struct Fence {
+    // 相对树根的挂载点
+    points: HashSet<PathBuf>,
+    // 进入边界另一侧并移动其中的内容；为 false 时边界另一侧原样保留
+    pub include: bool,
+}
+
+impl Fence {
+    // root 应为规范化路径
+    pub fn new(mounts: &[Mount], root: &Path, include: bool) -> Fence {
+        let points = nested(mounts, root).filter_map(|m| m.mount_point.strip_prefix(root).ok()).map(Path::to_path_buf).collect();
+        Fence { points, include }
+    }
+
+    // 挂载表中树内有挂载点；设备号变化只有遍历时才能发现
+    pub fn has_mounts(&self) -> bool {
+        !self.points.is_empty()
+    }
+
+    pub fn crosses(&self, rel: &Path, stat: &at::Stat, parent_dev: u64) -> bool {
+        stat.dev() != parent_dev || self.points.contains(rel)
+    }
+}
@@ -0,0 +1,660 @@
+// 删除计划：在移动任何东西之前，确定每个目标的移动方式、需要的空间以及会触发的保护
+//
+// 同一文件系统的 rename 不需要额外空间；跨设备但属于同一支持 reflink 的文件系统时只复制
+// 元数据；其余情况需要在目标文件系统上完整复制一份。空间按目标文件系统分组计算和检查。
+// `srm del --dry-run` 只输出计划，不做任何修改。
+//
+// 需要复制的目录在这里扫描一次，扫描结果随计划交给移动过程复用（进度总数、逐项移动）；
+// 同一文件系统内 rename 的目录不扫描，大小在移入回收站后随摘要一起统计。
+//
+// 目录树中的其他文件系统（挂载点、设备号变化处）默认拒绝删除该项；`--mounts skip` 把边界另一侧
+// 连同其上级目录留在原处，`--mounts include` 移走其中的内容、只留下挂载点本身。两种情况都逐项移动，
+// 不对整棵树 rename，否则挂载会随目录一起进入回收站。
+//
+// 被其他进程打开或映射的目标默认照常删除并给出警告，`--busy deny` 时跳过。
+// 带不可变或仅追加属性的条目默认跳过该项，root 可以用 `--clear-attrs` 清除后删除。
+
+use crate::{at, attrs, busy, calculate_dir_stats, check_disk_space, git, guard, mounts, rules, scan, DiskUsage, FileType, GIT_EXPIRE_DAYS, MAX_RECURSION_DEPTH};
+use bytesize::ByteSize;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    Rename,
+    Reflink,
+    Copy,
+}
+
+impl std::fmt::Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Strategy::Rename => write!(f, "rename"),
+            Strategy::Reflink => write!(f, "reflink"),
+            Strategy::Copy => write!(f, "copy"),
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum PlanFormat {
+    Table,
+    Json,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum MountPolicy {
+    // 目录树中有其他文件系统时不删除该项
+    Refuse,
+    // 其他文件系统原样留在原处，其余内容移入回收站
+    Skip,
+    // 连同其他文件系统中的内容一起移入回收站，挂载点本身留在原处
+    Include,
+}
+
+// 目标目录树中的文件系统边界，写入计划、审计日志与元数据
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Boundary {
+    pub path: String,
+    // 文件系统类型与来源；挂载表中查不到（如 btrfs 子卷）时为空
+    #[serde(default)]
+    pub filesystem: String,
+    // 其中的内容随该项移入了回收站
+    #[serde(default)]
+    pub included: bool,
+}
+
+impl std::fmt::Display for Boundary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.filesystem.is_empty() {
+            write!(f, "{} (different device)", self.path)
+        } else {
+            write!(f, "{} ({})", self.path, self.filesystem)
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum BusyPolicy {
+    // 照常删除，列出使用中的进程
+    Warn,
+    // 不删除被其他进程使用的目标
+    Deny,
+}
+
+pub struct Target {
+    pub path: PathBuf,
+    // 命令行中给出的形式，用于 rm 兼容模式的输出
+    pub arg: PathBuf,
+    pub meta: fs::Metadata,
+    pub file_type: FileType,
+    // 未扫描的 rename 目录为 None
+    pub usage: Option<DiskUsage>,
+    pub tree: Option<scan::Tree>,
+    pub strategy: Strategy,
+    // 被 -f 或 --allow 放行的防护
+    pub overridden: Vec<String>,
+    // 命中的用户规则（规则文件或 .srm-protect 标记）
+    pub rule: Option<rules::Verdict>,
+    // 删除前需要逐项确认的原因
+    pub confirm: Vec<Confirmation>,
+    // 目标是、包含或位于 git 工作区时的检查结果
+    pub git: Option<git::Summary>,
+    // 保留期下限（天）
+    pub keep_days: Option<i64>,
+    // 目录树中的文件系统边界（--mounts skip/include）
+    pub mounts: Vec<Boundary>,
+    // 打开或映射了目标中文件的其他进程
+    pub busy: Vec<busy::Holder>,
+    // 移动前要清除的不可变与仅追加属性（--clear-attrs）
+    pub locked: Vec<attrs::Locked>,
+}
+
+pub struct Confirmation {
+    pub rule: guard::Rule,
+    pub reason: String,
+    // 无法询问（标准输入不是终端）时跳过该项；否则照常删除
+    pub required: bool,
+}
+
+impl Target {
+    // 在目标文件系统上需要的空间：复制保留空洞与硬链接，按实际占用计算；
+    // reflink 按共享数据块计算，失败后退回复制时由 fast_file_copy 逐个文件再检查空间
+    pub fn required_bytes(&self) -> u64 {
+        match self.strategy {
+            Strategy::Copy => self.usage.map_or(0, |u| u.allocated),
+            Strategy::Rename | Strategy::Reflink => 0,
+        }
+    }
+}
+
+pub struct Skip {
+    pub path: String,
+    pub arg: PathBuf,
+    pub reason: String,
+    // 拦下该项的防护规则；不存在等普通原因为空
+    pub rules: Vec<guard::Rule>,
+    // 其中有无法放行的规则
+    pub hard: bool,
+    // 拒绝删除的用户规则
+    pub matched: Option<rules::Verdict>,
+    // 因 --busy deny 跳过时使用中的进程
+    pub busy: Vec<busy::Holder>,
+}
+
+impl Skip {
+    // 放行所需的 --allow 参数
+    pub fn allow_hint(&self) -> String {
+        self.rules.iter().map(|r| r.to_string()).collect::<Vec<_>>().join(",")
+    }
+}
+
+// 所有目标都移入同一个 trash/，只需检查它所在的文件系统
+pub struct SpaceCheck {
+    pub dest: PathBuf,
+    pub filesystem: String,
+    pub required: u64,
+    pub largest_copy: u64,
+    pub items: usize,
+}
+
+pub struct Plan {
+    pub targets: Vec<Target>,
+    pub skipped: Vec<Skip>,
+    // 没有可删除的目标时为 None
+    pub space: Option<SpaceCheck>,
+}
+
+// 词法规范化：去掉 `.` 与重复的分隔符，`..` 抵消前一个组件，不访问文件系统
+pub fn lexical(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            // 根目录的 `..` 仍是根目录；相对路径开头的 `..` 无可抵消，保留
+            Component::ParentDir => match out.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    out.pop();
+                }
+                Some(Component::RootDir) => {}
+                _ => out.push(".."),
+            },
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+// 解析要删除的实际位置：父目录按内核的方式规范化（软链接之后的 `..` 回到链接目标的上级，
+// 与 rename/unlink 实际作用的位置一致），最后一个组件本身不跟随，删除软链接时删除的是链接。
+// 以 `..` 结尾的路径指向一个目录，整体规范化。保护检查、记录的原路径与移动时重新打开的
+// 父目录都使用这条不含软链接的路径。
+pub fn resolve(path: &Path) -> io::Result<PathBuf> {
+    let abs_path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+    if matches!(abs_path.components().next_back(), Some(Component::ParentDir)) {
+        return abs_path.canonicalize();
+    }
+    match (abs_path.parent(), abs_path.file_name()) {
+        (Some(parent), Some(name)) => Ok(parent.canonicalize()?.join(name)),
+        // 根目录由防护规则拒绝
+        _ => Ok(PathBuf::from("/")),
+    }
+}
+
+pub fn build(paths: &[PathBuf], overrides: &guard::Overrides, mount_policy: MountPolicy, busy_policy: BusyPolicy,
+             clear_attrs: bool, jobs: usize, trash_dir: &Path) -> Result<Plan, String> {
+    let mut plan = Plan { targets: Vec::new(), skipped: Vec::new(), space: None };
+    let skip = |plan: &mut Plan, arg: &Path, path: String, reason: String| {
+        plan.skipped.push(Skip { path, arg: arg.to_path_buf(), reason, rules: Vec::new(), hard: false, matched: None, busy: Vec::new() });
+    };
+
+    let dest_dev = fs::metadata(trash_dir).map(|m| m.dev()).unwrap_or(0);
+    let mount_table = mounts::read_mountinfo();
+    let base = trash_dir.parent().unwrap_or(trash_dir);
+    let guard = guard::Guard::new(base, mount_table.clone());
+    let user_rules = rules::Rules::load(base)?;
+    let open_files = busy::snapshot();
+    let mut seen = HashSet::new();
+
+    for path in paths {
+        let abs_path = match resolve(path) {
+            Ok(p) => p,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                skip(&mut plan, path, lexical(path).display().to_string(), "Not found".into());
+                continue;
+            }
+            Err(e) => {
+                skip(&mut plan, path, lexical(path).display().to_string(), format!("Invalid path: {}", e));
+                continue;
+            }
+        };
+        // 不同写法可能解析到同一个目标
+        if !seen.insert(abs_path.clone()) {
+            skip(&mut plan, path, abs_path.display().to_string(), "Same target as an earlier argument".into());
+            continue;
+        }
+
+        let meta = match fs::symlink_metadata(&abs_path) {
+            Ok(m) => m,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                skip(&mut plan, path, abs_path.display().to_string(), "Not found".into());
+                continue;
+            }
+            Err(e) => {
+                skip(&mut plan, path, abs_path.display().to_string(), format!("{}", e));
+                continue;
+            }
+        };
+        let is_symlink = meta.file_type().is_symlink();
+        let file_type = FileType::of(&meta);
+        let mut overridden = Vec::new();
+
+        let (allowed, blocked): (Vec<_>, Vec<_>) = guard.check(&abs_path, &meta).into_iter().partition(|h| overrides.allows(h));
+        if !blocked.is_empty() {
+            let reason = blocked.iter().map(|h| h.message.as_str()).collect::<Vec<_>>().join("; ");
+            let rules = blocked.iter().map(|h| h.rule).collect();
+            let hard = blocked.iter().any(|h| h.hard);
+            plan.skipped.push(Skip { path: abs_path.display().to_string(), arg: path.clone(), reason, rules, hard, matched: None, busy: Vec::new() });
+            continue;
+        }
+        overridden.extend(allowed.iter().map(|h| format!("{} ({})", h.message, h.rule)));
+
+        let mut strategy = if meta.dev() == dest_dev {
+            Strategy::Rename
+        } else if matches!(file_type, FileType::File | FileType::Dir) && mounts::same_reflink_fs(&mount_table, meta.dev(), dest_dev) {
+            Strategy::Reflink
+        } else {
+            Strategy::Copy
+        };
+
+        // 挂载表中树内有挂载点时 rename 的目录也需要扫描，以便逐项移动
+        let fence = mounts::Fence::new(&mount_table, &abs_path, mount_policy == MountPolicy::Include);
+        let (mut usage, tree) = if file_type != FileType::Dir {
+            (Some(DiskUsage { apparent: meta.len(), allocated: meta.blocks() * 512, items: 0 }), None)
+        } else if strategy == Strategy::Rename && !fence.has_mounts() {
+            (None, None)
+        } else {
+            match at::Dir::open(&abs_path).and_then(|dir| scan::scan(&dir, jobs, Some(&fence))) {
+                Ok(tree) => (Some(tree.usage), Some(tree)),
+                Err(e) => {
+                    skip(&mut plan, path, abs_path.display().to_string(), format!("Failed to scan directory: {}", e));
+                    continue;
+                }
+            }
+        };
+        let boundaries: Vec<Boundary> = tree.iter().flat_map(|t| &t.mounts).map(|b| {
+            let point = abs_path.join(&b.rel);
+            let mount = mount_table.iter().rev().find(|m| m.mount_point == point).or_else(|| mounts::by_dev(&mount_table, b.dev));
+            Boundary {
+                path: point.display().to_string(),
+                filesystem: mount.map(|m| format!("{} from {}", m.fstype, m.source)).unwrap_or_default(),
+                included: b.included,
+            }
+        }).collect();
+        if !boundaries.is_empty() && mount_policy == MountPolicy::Refuse {
+            let list = boundaries.iter().map(|b| b.to_string()).collect::<Vec<_>>().join(", ");
+            skip(&mut plan, path, abs_path.display().to_string(),
+                 format!("Contains other filesystems: {} (use --mounts skip or --mounts include)", list));
+            continue;
+        }
+        // 软链接本身不会被打开，指向的文件不在删除范围内
+        let holders = if is_symlink { Vec::new() } else { open_files.holders(&abs_path) };
+        if !holders.is_empty() && busy_policy == BusyPolicy::Deny {
+            plan.skipped.push(Skip {
+                path: abs_path.display().to_string(),
+                arg: path.clone(),
+                reason: format!("In use by {}", busy::describe(&holders)),
+                rules: Vec::new(),
+                hard: false,
+                matched: None,
+                busy: holders,
+            });
+            continue;
+        }
+
+        if let Some(flags) = (!is_symlink).then(|| attrs::parent_flags(&abs_path)).flatten() {
+            let parent = abs_path.parent().unwrap_or(Path::new("/"));
+            skip(&mut plan, path, abs_path.display().to_string(),
+                 format!("Parent directory {} is {} (clear it with chattr first)", parent.display(), attrs::describe(flags)));
+            continue;
+        }
+        let locked = if is_symlink { Ok(Vec::new()) } else { attrs::find(&abs_path, tree.as_ref()) };
+        let locked = match locked {
+            Ok(l) => l,
+            Err(e) => {
+                skip(&mut plan, path, abs_path.display().to_string(), format!("Failed to read file attributes: {}", e));
+                continue;
+            }
+        };
+        if !locked.is_empty() && !clear_attrs {
+            skip(&mut plan, path, abs_path.display().to_string(),
+                 format!("Immutable or append-only: {} (root can clear them with --clear-attrs)", attrs::summary(&abs_path, &locked)));
+            continue;
+        }
+        if !locked.is_empty() && unsafe { libc::geteuid() } != 0 {
+            skip(&mut plan, path, abs_path.display().to_string(),
+                 format!("Immutable or append-only: {} (only root can clear them)", attrs::summary(&abs_path, &locked)));
+            continue;
+        }
+
+        // 包含的其他文件系统中的内容需要复制；按整项复制估算空间，宁可多算
+        if tree.as_ref().is_some_and(|t| t.mounts.iter().any(|b| b.included && b.dev != dest_dev)) {
+            strategy = Strategy::Copy;
+        }
+
+        // 目录之下的标记同样生效，否则在上一级删除就能绕过；嵌套的 git 仓库在同一次查找中找出
+        let found = if file_type == FileType::Dir {
+            let names = [rules::MARKER_FILE, git::NAMES[0], git::NAMES[1]];
+            match scan::find_names(&abs_path, tree.as_ref(), &names, MAX_RECURSION_DEPTH) {
+                Ok(found) => found,
+                Err(e) => {
+                    skip(&mut plan, path, abs_path.display().to_string(), format!("Failed to scan directory: {}", e));
+                    continue;
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        let (markers, mut repos): (Vec<PathBuf>, Vec<PathBuf>) = found.into_iter().partition(|p| p.ends_with(rules::MARKER_FILE));
+        if tree.is_none() {
+            repos.retain(|p| p.components().count() <= git::UNSCANNED_DEPTH);
+        }
+        // 未扫描的 rename 目录只在有规则需要大小或项目数时才统计
+        let rule = user_rules.evaluate(&abs_path, &meta, &markers, &mut || {
+            if usage.is_none() {
+                usage = calculate_dir_stats(&abs_path).ok();
+            }
+            usage
+        });
+        let mut confirm = Vec::new();
+        if let Some(verdict) = rule.as_ref().filter(|v| v.action != rules::Action::Allow) {
+            let hit = guard::Hit {
+                rule: guard::Rule::Rules,
+                message: format!("Rule '{}' ({}) from {}", verdict.name, verdict.action, verdict.source),
+                hard: false,
+            };
+            if overrides.allows(&hit) {
+                overridden.push(format!("{} ({})", hit.message, hit.rule));
+            } else if verdict.action == rules::Action::Deny {
+                plan.skipped.push(Skip {
+                    path: abs_path.display().to_string(),
+                    arg: path.clone(),
+                    reason: hit.message,
+                    rules: vec![hit.rule],
+                    hard: false,
+                    matched: rule.clone(),
+                    busy: Vec::new(),
+                });
+                continue;
+            } else {
+                confirm.push(Confirmation {
+                    rule: hit.rule,
+                    reason: format!("protected by rule '{}' from {}", verdict.name, verdict.source),
+                    required: true,
+                });
+            }
+        }
+
+        // 有未保存的工作时延长保留期并逐项确认；无法询问时跳过该项，-f 或 --allow git 放行
+        let git = if file_type == FileType::Dir { git::inspect(&abs_path, Some(&repos)) } else { None };
+        let mut keep_days = None;
+        if let Some(summary) = git.as_ref().filter(|s| s.unsaved()) {
+            keep_days = Some(GIT_EXPIRE_DAYS);
+            let hit = guard::Hit { rule: guard::Rule::Git, message: format!("Unsaved git work: {}", summary.describe()), hard: false };
+            if overrides.allows(&hit) {
+                overridden.push(format!("{} ({})", hit.message, hit.rule));
+            } else {
+                confirm.push(Confirmation { rule: hit.rule, reason: format!("unsaved git work: {}", summary.describe()), required: true });
+            }
+        }
+        plan.targets.push(Target {
+            path: abs_path, arg: path.clone(), meta, file_type, usage, tree, strategy, overridden, rule, confirm, git, keep_days,
+            mounts: boundaries, busy: holders, locked,
+        });
+    }
+
+    if !plan.targets.is_empty() {
+        plan.space = Some(SpaceCheck {
+            dest: trash_dir.to_path_buf(),
+            filesystem: mounts::by_dev(&mount_table, dest_dev).map(|m| format!("{} on {}", m.fstype, m.mount_point.display())).unwrap_or_default(),
+            required: plan.targets.iter().map(Target::required_bytes).sum(),
+            largest_copy: plan.targets.iter().map(Target::required_bytes).max().unwrap_or(0),
+            items: plan.targets.len(),
+        });
+    }
+    Ok(plan)
+}
+
+impl Plan {
+    pub fn total_bytes(&self) -> u64 {
+        self.targets.iter().filter_map(|t| t.usage).map(|u| u.apparent).sum()
+    }
+
+    pub fn allocated_bytes(&self) -> u64 {
+        self.targets.iter().filter_map(|t| t.usage).map(|u| u.allocated).sum()
+    }
+
+    pub fn required_bytes(&self) -> u64 {
+        self.space.as_ref().map_or(0, |s| s.required)
+    }
+
+    // 检查总量，以及最大的单个复制项是否超出可用空间的比例上限
+    pub fn check_space(&self) -> io::Result<()> {
+        let Some(space) = &self.space else { return Ok(()) };
+        if space.largest_copy > 0 {
+            check_disk_space(&space.dest, space.largest_copy, true)?;
+        }
+        if space.required > 0 {
+            check_disk_space(&space.dest, space.required, false)?;
+        }
+        Ok(())
+    }
+
+    fn space_status(space: &SpaceCheck) -> (Option<u64>, Result<(), String>) {
+        let available = fs2::available_space(&space.dest).ok();
+        let mut status = Ok(());
+        if space.largest_copy > 0 {
+            status = check_disk_space(&space.dest, space.largest_copy, true).map_err(|e| e.to_string());
+        }
+        if status.is_ok() && space.required > 0 {
+            status = check_disk_space(&space.dest, space.required, false).map_err(|e| e.to_string());
+        }
+        (available, status)
+    }
+
+    pub fn print(&self, format: PlanFormat) {
+        match format {
+            PlanFormat::Table => self.print_table(),
+            PlanFormat::Json => println!("{}", serde_json::to_string_pretty(&self.to_json()).unwrap_or_default()),
+        }
+    }
+
+    fn print_table(&self) {
+        println!("📋 Delete plan: {} item(s), {} total ({} on disk), {} to copy (dry run, nothing changed)",
+                 self.targets.len(), ByteSize(self.total_bytes()), ByteSize(self.allocated_bytes()), ByteSize(self.required_bytes()));
+        if !self.targets.is_empty() {
+            println!("{:<9} {:<8} {:>10} {:>10}  PATH", "STRATEGY", "TYPE", "SIZE", "ON DISK");
+            for t in &self.targets {
+                let (size, allocated) = match t.usage {
+                    Some(u) => (ByteSize(u.apparent).to_string(), ByteSize(u.allocated).to_string()),
+                    None => ("?".into(), "?".into()),
+                };
+                println!("{:<9} {:<8} {:>10} {:>10}  {}", t.strategy.to_string(), t.file_type.to_string(), size, allocated, t.path.display());
+                for c in &t.confirm {
+                    println!("{:<9} {:<8} {:>10} {:>10}    ❓ asks for confirmation: {}", "", "", "", "", c.reason);
+                }
+                if let Some(days) = t.keep_days {
+                    println!("{:<9} {:<8} {:>10} {:>10}    ⏳ kept for at least {} days (unsaved git work)", "", "", "", "", days);
+                }
+                if !t.locked.is_empty() {
+                    println!("{:<9} {:<8} {:>10} {:>10}    🔓 clears attributes: {}", "", "", "", "", attrs::summary(&t.path, &t.locked));
+                }
+                if !t.busy.is_empty() {
+                    println!("{:<9} {:<8} {:>10} {:>10}    ⚙️  in use by {}", "", "", "", "", busy::describe(&t.busy));
+                }
+                for b in &t.mounts {
+                    let what = if b.included { "contents included, mount point left in place" } else { "left in place" };
+                    println!("{:<9} {:<8} {:>10} {:>10}    🧱 {}: {}", "", "", "", "", what, b);
+                }
+                for reason in &t.overridden {
+                    println!("{:<9} {:<8} {:>10} {:>10}    ⚠️  overridden: {}", "", "", "", "", reason);
+                }
+            }
+            if self.targets.iter().any(|t| t.usage.is_none()) {
+                println!("ℹ️  ? = directory renamed in place without scanning; not included in the totals");
+            }
+        }
+        for s in &self.skipped {
+            if s.rules.is_empty() {
+                println!("⚠️  Skip '{}': {}", s.path, s.reason);
+            } else if s.hard {
+                println!("🛡️  Protected '{}': {} (cannot be overridden)", s.path, s.reason);
+            } else {
+                println!("🛡️  Protected '{}': {} (allow with --allow {})", s.path, s.reason, s.allow_hint());
+            }
+        }
+        if let Some(space) = &self.space {
+            println!("\n💾 Space on the trash filesystem:");
+            let (available, status) = Self::space_status(space);
+            let available = available.map(|a| ByteSize(a).to_string()).unwrap_or_else(|| "?".into());
+            let filesystem = if space.filesystem.is_empty() { String::new() } else { format!(" [{}]", space.filesystem) };
+            match status {
+                Ok(()) => println!("   ✅ {}{}: {} needed for {} item(s), {} available",
+                                   space.dest.display(), filesystem, ByteSize(space.required), space.items, available),
+                Err(e) => println!("   ❌ {}{}: {}", space.dest.display(), filesystem, e),
+            }
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "dry_run": true,
+            "total_size_bytes": self.total_bytes(),
+            "total_allocated_bytes": self.allocated_bytes(),
+            "required_space_bytes": self.required_bytes(),
+            "items": self.targets.iter().map(|t| json!({
+                "path": t.path.display().to_string(),
+                "file_type": t.file_type.to_string(),
+                "size_bytes": t.usage.map(|u| u.apparent),
+                "allocated_bytes": t.usage.map(|u| u.allocated),
+                "items": t.tree.as_ref().map(|tree| tree.usage.items),
+                "strategy": t.strategy,
+                "required_bytes": t.required_bytes(),
+                "overridden": t.overridden,
+                "rule": t.rule,
+                "confirm": t.confirm.iter().map(|c| json!({"rule": c.rule, "reason": c.reason})).collect::<Vec<_>>(),
+                "git": t.git,
+                "keep_days": t.keep_days,
+                "mounts": t.mounts,
+                "busy": t.busy,
+                "locked": t.locked
+            })).collect::<Vec<_>>(),
+            "skipped": self.skipped.iter().map(|s| json!({
+                "path": s.path,
+                "reason": s.reason,
+                "protection": !s.rules.is_empty(),
+                "rules": s.rules,
+                "matched_rule": s.matched,
+                "busy": s.busy
+            })).collect::<Vec<_>>(),
+            "space": self.space.as_ref().map(|s| {
+                let (available, status) = Self::space_status(s);
+                json!({
+                    "destination": s.dest.display().to_string(),
+                    "filesystem": s.filesystem,
+                    "items": s.items,
+                    "required_bytes": s.required,
+                    "available_bytes": available,
+                    "ok": status.is_ok(),
+                    "error": status.err()
+                })
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch_dir;
+
+    #[test]
+    fn normalises_paths_lexically() {
+        assert_eq!(lexical(Path::new("/a/./b//c/../d")), Path::new("/a/b/d"));
+        assert_eq!(lexical(Path::new("/../a/..")), Path::new("/"));
+        assert_eq!(lexical(Path::new("../a/../../b")), Path::new("../../b"));
+        assert_eq!(lexical(Path::new("a/..")), Path::new(""));
+    }
+
+    #[test]
+    fn resolves_parents_without_following_the_last_component() {
+        let dir = scratch_dir("plan-resolve").canonicalize().unwrap();
+        fs::create_dir_all(dir.join("real/sub")).unwrap();
+        std::os::unix::fs::symlink(dir.join("real/sub"), dir.join("link")).unwrap();
+        std::os::unix::fs::symlink("real", dir.join("rel")).unwrap();
+
+        // 最后一个组件是软链接时删除的是链接本身
+        let link = resolve(&dir.join("link"));
+        // 软链接之后的 `..` 回到链接目标的上级，与内核一致
+        let through = resolve(&dir.join("link/../x"));
+        let relative = resolve(&dir.join("rel/sub/./f"));
+        let dotdot = resolve(&dir.join("link/.."));
+        let missing = resolve(&dir.join("nowhere/x"));
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(link.unwrap(), dir.join("link"));
+        assert_eq!(through.unwrap(), dir.join("real/x"));
+        assert_eq!(relative.unwrap(), dir.join("real/sub/f"));
+        assert_eq!(dotdot.unwrap(), dir.join("real"));
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(resolve(Path::new("/")).unwrap(), Path::new("/"));
+    }
+
+    #[test]
+    fn plans_renames_and_reports_json() {
+        // 存储所在目录受保护，目标放在存储之外
+        let base = scratch_dir("plan-json");
+        let trash_dir = base.join(".srm/trash");
+        fs::create_dir_all(&trash_dir).unwrap();
+        fs::write(base.join("file"), "12345").unwrap();
+        fs::create_dir_all(base.join("dir/sub")).unwrap();
+        let paths = [base.join("file"), base.join("dir"), base.join("./file"), base.join("missing")];
+        let mut plan = build(&paths, &guard::Overrides::default(), MountPolicy::Refuse, BusyPolicy::Warn, false, 1, &trash_dir).unwrap();
+
+        let json = plan.to_json();
+        let items = json["items"].as_array().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!((items[0]["strategy"].as_str(), items[0]["size_bytes"].as_u64()), (Some("rename"), Some(5)));
+        // 同一文件系统内 rename 的目录不扫描，大小未知
+        assert_eq!((items[1]["file_type"].as_str(), items[1]["size_bytes"].is_null()), (Some("dir"), true));
+        let reasons: Vec<&str> = json["skipped"].as_array().unwrap().iter().map(|s| s["reason"].as_str().unwrap()).collect();
+        assert_eq!(reasons, ["Same target as an earlier argument", "Not found"]);
+        assert_eq!((json["dry_run"].as_bool(), json["required_space_bytes"].as_u64()), (Some(true), Some(0)));
+        assert_eq!(json["space"]["destination"].as_str(), Some(trash_dir.to_str().unwrap()));
+        assert_eq!(json["space"]["items"].as_u64(), Some(2));
+
+        // 复制按实际占用计算所需空间，rename 与 reflink 不需要
+        let copy = &mut plan.targets[0];
+        copy.strategy = Strategy::Copy;
+        copy.usage = Some(DiskUsage { apparent: 5, allocated: 4096, items: 0 });
+        assert_eq!(copy.required_bytes(), 4096);
+        copy.strategy = Strategy::Reflink;
+        assert_eq!(copy.required_bytes(), 0);
+
+        let empty = build(&[base.join("missing")], &guard::Overrides::default(), MountPolicy::Refuse, BusyPolicy::Warn, false, 1, &trash_dir).unwrap();
+        let _ = fs::remove_dir_all(&base);
+        assert!(empty.to_json()["space"].is_null());
+        assert_eq!(empty.to_json()["items"].as_array().map(Vec::len), Some(0));
+    }
+}
@@ -0,0 +1,358 @@
+// srm rebuild-meta --from-log：从审计日志重建 meta/ 索引
+//
+// 按时间重放 delete / import / restore / clean / rollback / empty 事件，为仍存在于 trash/ 中的
+// 回收项重建 FileMeta。默认只打印差异，--apply 才会写入。
+
+use crate::{
+    atomic_save_meta, encode_path, generate_short_id, local_to_utc, log_event, parse_legacy_time,
+    parse_meta, store_entries, FileMeta, FileType, LogEntry, META_SCHEMA_VERSION,
+};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+struct Record {
+    timestamp: DateTime<Utc>,
+    line: usize,
+    message: String,
+    details: Value,
+}
+
+// 一次删除事件中可用于重建的字段
+struct Deleted {
+    timestamp: DateTime<Utc>,
+    details: Value,
+}
+
+// 重放结果：deletes 保留每个回收ID最后一次删除记录，live 为按日志仍在回收站中的项
+struct Replay {
+    deletes: HashMap<String, Deleted>,
+    live: HashSet<String>,
+    removed_at: HashMap<String, (DateTime<Utc>, String)>,
+    conflicts: Vec<String>,
+}
+
+enum Change {
+    Add,
+    ReplaceCorrupt,
+}
+
+fn read_records(log_path: &Path) -> std::io::Result<(Vec<Record>, usize)> {
+    let reader = BufReader::new(fs::File::open(log_path)?);
+    let mut records = Vec::new();
+    let mut unreadable = 0;
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Ok(entry) = serde_json::from_str::<LogEntry>(&line) else {
+            unreadable += 1;
+            continue;
+        };
+        let Ok(timestamp) = NaiveDateTime::parse_from_str(&entry.timestamp, "%Y-%m-%d %H:%M:%S%.3f") else {
+            unreadable += 1;
+            continue;
+        };
+        records.push(Record {
+            timestamp: local_to_utc(&timestamp),
+            line: idx + 1,
+            message: entry.message,
+            details: entry.details.unwrap_or(Value::Null),
+        });
+    }
+
+    // 并发写入或时钟回拨会导致日志行乱序，按时间戳重排，同一时间戳保持原始行序
+    records.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then(a.line.cmp(&b.line)));
+    Ok((records, unreadable))
+}
+
+fn str_field<'a>(details: &'a Value, key: &str) -> Option<&'a str> {
+    details.get(key).and_then(Value::as_str)
+}
+
+fn parse_file_type(s: &str) -> Option<FileType> {
+    match s {
+        "file" => Some(FileType::File),
+        "dir" => Some(FileType::Dir),
+        "symlink" => Some(FileType::Symlink),
+        "fifo" => Some(FileType::Fifo),
+        "socket" => Some(FileType::Socket),
+        "chardev" => Some(FileType::CharDevice),
+        "blockdev" => Some(FileType::BlockDevice),
+        _ => None,
+    }
+}
+
+// path 为回收项在 trash/（或其分片子目录）中的实际位置
+fn build_meta(path: &Path, deleted: &Deleted, base: &Path) -> Option<FileMeta> {
+    let d = &deleted.details;
+    let disk = fs::symlink_metadata(path).ok()?;
+
+    let file_type = str_field(d, "file_type").and_then(parse_file_type).unwrap_or(FileType::of(&disk));
+    // 日志只记录了权限位，文件类型位从回收项本身取
+    let permissions = str_field(d, "permissions")
+        .and_then(|p| u32::from_str_radix(p, 8).ok())
+        .map(|p| (disk.permissions().mode() & !0o7777) | p)
+        .unwrap_or(disk.permissions().mode());
+
+    // 旧版日志的 delete_time 为本地时间字符串，新版为 RFC 3339 UTC；都没有时用日志时间戳
+    let delete_time = str_field(d, "delete_time")
+        .and_then(|s| s.parse::<DateTime<Utc>>().ok().or_else(|| parse_legacy_time(s)))
+        .unwrap_or(deleted.timestamp);
+    let expire_days = d.get("expire_days").and_then(Value::as_i64).unwrap_or(crate::DEFAULT_EXPIRE_DAYS);
+    let expire_time = str_field(d, "expire_time")
+        .and_then(|s| s.parse::<DateTime<Utc>>().ok())
+        .unwrap_or(delete_time + Duration::days(expire_days));
+
+    let (trash_path, trash_path_b64) = encode_path(path.strip_prefix(base).unwrap_or(path));
+    Some(FileMeta {
+        schema_version: META_SCHEMA_VERSION,
+        original_path: str_field(d, "original_path")?.to_string(),
+        trash_path,
+        original_path_b64: str_field(d, "original_path_b64").map(str::to_string),
+        trash_path_b64,
+        delete_time,
+        expire_time,
+        expire_days,
+        file_type,
+        rdev: d.get("rdev").and_then(Value::as_u64).or(FileType::device(&disk)),
+        permissions: Some(permissions),
+        uid: Some(d.get("uid").and_then(Value::as_u64).map(|v| v as u32).unwrap_or(disk.uid())),
+        gid: Some(d.get("gid").and_then(Value::as_u64).map(|v| v as u32).unwrap_or(disk.gid())),
+        short_id: str_field(d, "short_id").unwrap_or_default().to_string(),
+        size_bytes: d.get("size_bytes").and_then(Value::as_u64).unwrap_or(0),
+        allocated_bytes: d.get("allocated_bytes").and_then(Value::as_u64),
+        checksum: str_field(d, "checksum").map(str::to_string),
+        git: d.get("git").and_then(|g| serde_json::from_value(g.clone()).ok()),
+        mounts: d.get("mounts").and_then(|m| serde_json::from_value(m.clone()).ok()).unwrap_or_default(),
+        attrs: d.get("cleared_attrs").and_then(|a| serde_json::from_value(a.clone()).ok()).unwrap_or_default(),
+    })
+}
+
+// records 须已按时间排序（见 read_records）
+fn replay(records: Vec<Record>) -> Replay {
+    let mut deletes: HashMap<String, Deleted> = HashMap::new();
+    let mut live: HashSet<String> = HashSet::new();
+    let mut removed_at: HashMap<String, (DateTime<Utc>, String)> = HashMap::new();
+    let mut conflicts = Vec::new();
+
+    for record in records {
+        let trash_id = str_field(&record.details, "trash_id").map(str::to_string);
+        match (record.message.as_str(), trash_id) {
+            ("File deleted" | "File imported", Some(trash_id)) => {
+                if live.contains(&trash_id) {
+                    conflicts.push(format!("{}: deleted again at line {} without an intervening removal, keeping the later record",
+                        trash_id, record.line));
+                }
+                live.insert(trash_id.clone());
+                removed_at.remove(&trash_id);
+                deletes.insert(trash_id, Deleted { timestamp: record.timestamp, details: record.details });
+            }
+            ("File restored" | "Item cleaned from trash" | "Rollback performed", Some(trash_id)) => {
+                if !deletes.contains_key(&trash_id) {
+                    conflicts.push(format!("{}: '{}' at line {} has no earlier delete record", trash_id, record.message, record.line));
+                }
+                live.remove(&trash_id);
+                removed_at.insert(trash_id, (record.timestamp, record.message));
+            }
+            ("Trash emptied permanently", _) => {
+                for trash_id in live.drain() {
+                    removed_at.insert(trash_id, (record.timestamp, record.message.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+    Replay { deletes, live, removed_at, conflicts }
+}
+
+pub fn handle_rebuild_meta(apply: bool, base: &Path, trash_dir: &Path, meta_dir: &Path) {
+    let log_path = base.join("srm.log");
+    let (records, unreadable) = match read_records(&log_path) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("❌  Failed to read {}: {}", log_path.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let Replay { deletes, live, removed_at, mut conflicts } = replay(records);
+
+    // 读取当前 meta/（不经过 list_all_meta，避免其顺带删除元数据）
+    let mut current: HashMap<String, Option<FileMeta>> = HashMap::new();
+    for path in store_entries(meta_dir) {
+        let Some(trash_id) = path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_suffix(".meta")) else {
+            continue;
+        };
+        let parsed = fs::read_to_string(&path).ok().and_then(|c| parse_meta(&c).ok()).map(|(meta, _)| meta);
+        current.insert(trash_id.to_string(), parsed);
+    }
+
+    let mut on_disk: Vec<(String, PathBuf)> = store_entries(trash_dir)
+        .into_iter()
+        .filter_map(|p| Some((p.file_name()?.to_str()?.to_string(), p)))
+        .collect();
+    on_disk.sort();
+
+    let mut plan: Vec<(String, FileMeta, Change)> = Vec::new();
+    let mut kept = 0;
+    let mut differs = Vec::new();
+    let mut unknown = 0;
+
+    for (trash_id, path) in &on_disk {
+        let Some(deleted) = deletes.get(trash_id) else {
+            if !matches!(current.get(trash_id), Some(Some(_))) {
+                unknown += 1;
+            }
+            continue;
+        };
+        // 日志显示已移除但回收项仍在磁盘上：以最后一次删除记录为准
+        if let Some((ts, message)) = removed_at.get(trash_id).filter(|_| !live.contains(trash_id)) {
+            conflicts.push(format!("{}: log says '{}' at {}, but the entry is still in trash; using its last delete record",
+                trash_id, message, ts));
+        }
+        let Some(rebuilt) = build_meta(path, deleted, base) else {
+            conflicts.push(format!("{}: delete record lacks original_path, skipped", trash_id));
+            continue;
+        };
+
+        match current.get(trash_id) {
+            Some(Some(existing)) => {
+                kept += 1;
+                if existing.original() != rebuilt.original() || existing.short_id != rebuilt.short_id {
+                    differs.push(format!("{}: current {} → {}, log {} → {} (keeping current)",
+                        trash_id, existing.short_id, existing.original_path, rebuilt.short_id, rebuilt.original_path));
+                }
+            }
+            Some(None) => plan.push((trash_id.clone(), rebuilt, Change::ReplaceCorrupt)),
+            None => plan.push((trash_id.clone(), rebuilt, Change::Add)),
+        }
+    }
+
+    // 重建项与现有项之间的短ID冲突：保留现有项，重建项重新分配
+    let mut taken: HashSet<String> = current
+        .iter()
+        .filter(|(id, _)| !plan.iter().any(|(p, _, _)| p == *id))
+        .filter_map(|(_, m)| m.as_ref().map(|m| m.short_id.clone()))
+        .collect();
+    for (trash_id, meta, _) in plan.iter_mut() {
+        if meta.short_id.is_empty() || taken.contains(&meta.short_id) {
+            let new_id = generate_short_id(trash_id, meta.file_type, &mut taken);
+            conflicts.push(format!("{}: short ID '{}' already taken, reassigned {}", trash_id, meta.short_id, new_id));
+            meta.short_id = new_id;
+        }
+        taken.insert(meta.short_id.clone());
+    }
+
+    println!("📜 Replayed {} (trash entries on disk: {})", log_path.display(), on_disk.len());
+    if unreadable > 0 {
+        println!("⚠️  {} unreadable log line(s) ignored", unreadable);
+    }
+    for conflict in &conflicts {
+        println!("⚠️  {}", conflict);
+    }
+    for line in &differs {
+        println!("≠ {}", line);
+    }
+    for (trash_id, meta, change) in &plan {
+        let sign = match change {
+            Change::Add => "+",
+            Change::ReplaceCorrupt => "~",
+        };
+        println!("{} {:<12} {:<45} {} [{}]", sign, meta.short_id, crate::truncate_path(&meta.original_path, 43),
+            meta.delete_time.with_timezone(&chrono::Local).format(crate::LEGACY_TIME_FORMAT), trash_id);
+    }
+    if unknown > 0 {
+        println!("ℹ️  {} trash entr{} with no delete record in the log (see `srm fsck --repair`)",
+            unknown, if unknown == 1 { "y" } else { "ies" });
+    }
+
+    let adds = plan.iter().filter(|(_, _, c)| matches!(c, Change::Add)).count();
+    let replaces = plan.len() - adds;
+    if plan.is_empty() {
+        println!("\n✅ Nothing to rebuild ({} item(s) already indexed)", kept);
+        return;
+    }
+    if !apply {
+        println!("\nℹ️  Dry run: {} to add, {} to replace, {} unchanged. Re-run with --apply to write.", adds, replaces, kept);
+        return;
+    }
+
+    let mut written = 0;
+    for (trash_id, meta, _) in &plan {
+        match atomic_save_meta(trash_id, meta, meta_dir) {
+            Ok(()) => {
+                written += 1;
+                log_event("INFO", "Metadata rebuilt", Some(json!({
+                    "action": "rebuild-meta",
+                    "short_id": meta.short_id,
+                    "trash_id": trash_id,
+                    "original_path": meta.original_path
+                })));
+            }
+            Err(e) => eprintln!("❌  Failed to write metadata for {}: {}", trash_id, e),
+        }
+    }
+    log_event("INFO", "Metadata rebuild completed", Some(json!({
+        "action": "rebuild-meta",
+        "written": written,
+        "conflicts": conflicts.len()
+    })));
+    println!("\n✅ Rebuild completed ({} written, {} unchanged)", written, kept);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(time: &str, message: &str, trash_id: &str, short_id: &str) -> String {
+        json!({
+            "timestamp": format!("2024-05-01 {}.000", time),
+            "level": "INFO",
+            "message": message,
+            "details": { "trash_id": trash_id, "short_id": short_id, "original_path": format!("/data/{}", trash_id) }
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn replays_out_of_order_and_conflicting_records() {
+        let lines = [
+            // 写入顺序与时间戳不一致：恢复记录排在删除记录之前
+            line("10:00:02", "File restored", "a", "a1"),
+            line("10:00:01", "File deleted", "a", "a1"),
+            // 没有中间的移除就再次删除：以后一条为准
+            line("10:00:09", "File deleted", "b", "b1"),
+            line("10:00:10", "File deleted", "b", "b2"),
+            // 没有删除记录的清理
+            line("10:00:05", "Item cleaned from trash", "c", "c1"),
+            "not json".to_string(),
+            line("10:00:06", "File deleted", "d", "d1"),
+            json!({ "timestamp": "2024-05-01 10:00:07.000", "level": "INFO", "message": "Trash emptied permanently" }).to_string(),
+            line("10:00:08", "File imported", "e", "e1"),
+        ];
+        let log = std::env::temp_dir().join(format!("srm-test-{}-replay.log", std::process::id()));
+        fs::write(&log, lines.join("\n")).unwrap();
+        let read = read_records(&log);
+        let _ = fs::remove_file(&log);
+        let (records, unreadable) = read.unwrap();
+        assert_eq!(unreadable, 1);
+
+        let replay = replay(records);
+        let mut live: Vec<&str> = replay.live.iter().map(String::as_str).collect();
+        live.sort();
+        assert_eq!(live, ["b", "e"]);
+        assert_eq!(str_field(&replay.deletes["b"].details, "short_id"), Some("b2"));
+        assert_eq!(replay.removed_at["a"].1, "File restored");
+        assert_eq!(replay.removed_at["d"].1, "Trash emptied permanently");
+
+        assert_eq!(replay.conflicts.len(), 2);
+        assert!(replay.conflicts[0].starts_with("c: 'Item cleaned from trash' at line 5 has no earlier delete record"));
+        assert!(replay.conflicts[1].starts_with("b: deleted again at line 4"));
+    }
+}
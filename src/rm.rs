@@ -0,0 +1,381 @@
+// GNU rm 兼容前端：以 rm 为名调用（argv[0]）或 `srm rm ...` 时启用
+//
+// 按 GNU rm 的选项与语义筛选操作数（-r/-R、-d、-f、-i/-I/--interactive、-v、--preserve-root、
+// --one-file-system、--），通过检查的交给与 `srm del` 相同的删除计划移入回收站。输出与退出码
+// 对齐 GNU rm：错误以 "rm: " 开头，任何一项未删除时退出码为 1。
+//
+// 与 GNU rm 的差异：目录整体移入回收站，-i 对目录只询问一次；--one-file-system 遇到嵌套挂载点时
+// 整个操作数不删除（回收站移动不能只搬走一部分）；srm 的路径保护在此模式下不能用 -f 绕过。
+
+use crate::{guard, handle_delete_batch, mounts, plan, ChecksumMode, DeleteOutput, VerifyMode, DEFAULT_EXPIRE_DAYS};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use std::ffi::{CString, OsString};
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Component, Path, PathBuf};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum When {
+    #[value(alias = "no", alias = "none")]
+    Never,
+    Once,
+    #[value(alias = "yes")]
+    Always,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum PreserveRoot {
+    /// Also reject arguments on a different device than their parent
+    All,
+}
+
+#[derive(Args, Debug)]
+pub struct RmArgs {
+    #[arg(short = 'f', long, help = "ignore nonexistent files and arguments, never prompt")]
+    force: bool,
+    #[arg(short = 'i', help = "prompt before every removal")]
+    prompt_always: bool,
+    #[arg(short = 'I', help = "prompt once before removing more than three files, or when removing recursively")]
+    prompt_once: bool,
+    #[arg(long, value_enum, value_name = "WHEN", num_args = 0..=1, require_equals = true, default_missing_value = "always",
+          help = "prompt according to WHEN: never, once (-I), or always (-i)")]
+    interactive: Option<When>,
+    #[arg(long, help = "when removing recursively, refuse arguments that contain other file systems")]
+    one_file_system: bool,
+    #[arg(long, overrides_with = "preserve_root", help = "do not treat '/' specially")]
+    no_preserve_root: bool,
+    #[arg(long, value_enum, value_name = "all", num_args = 0..=1, require_equals = true, overrides_with = "no_preserve_root",
+          help = "do not remove '/' (default); with 'all', reject arguments on a separate device from their parent")]
+    preserve_root: Option<Option<PreserveRoot>>,
+    #[arg(short = 'r', short_alias = 'R', long, help = "remove directories and their contents recursively")]
+    recursive: bool,
+    #[arg(short = 'd', long = "dir", help = "remove empty directories")]
+    dir: bool,
+    #[arg(short = 'v', long, help = "explain what is being done")]
+    verbose: bool,
+    #[arg(value_name = "FILE")]
+    files: Vec<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "rm", version, about = "Remove (move to srm trash) the FILE(s), GNU rm compatible",
+          after_help = "Removed files can be listed with `srm ls` and restored with `srm res <ID>`.")]
+struct RmCommand {
+    #[command(flatten)]
+    args: RmArgs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Prompt {
+    Never,
+    Once,
+    Always,
+    // 未指定：只对写保护文件询问（标准输入为终端时）
+    Default,
+}
+
+// 以程序名 rm 调用（如 ln -s srm rm）
+pub fn invoked_as_rm() -> bool {
+    std::env::args_os()
+        .next()
+        .map(PathBuf::from)
+        .and_then(|p| p.file_name().map(|n| n == "rm"))
+        .unwrap_or(false)
+}
+
+// `srm rm ...` 中 rm 之后的原始参数：外层解析会吞掉其中第一个 "--"，这里原样取回
+pub fn raw_args() -> Vec<OsString> {
+    std::env::args_os().skip(1).skip_while(|a| a != "rm").skip(1).collect()
+}
+
+// GNU rm 以最后出现的 -f/-i/-I/--interactive 为准；-f 忽略不存在的文件这一效果不会被后面的 -i 取消
+fn prompt_mode(matches: &ArgMatches, args: &RmArgs) -> Prompt {
+    // 未出现的开关也有默认值及其索引，只看命令行中给出的
+    let last = |id: &str| {
+        (matches.value_source(id) == Some(ValueSource::CommandLine)).then(|| matches.indices_of(id).and_then(|i| i.max())).flatten()
+    };
+    let candidates = [
+        (last("force"), Prompt::Never),
+        (last("prompt_always"), Prompt::Always),
+        (last("prompt_once"), Prompt::Once),
+        (last("interactive"), match args.interactive {
+            Some(When::Never) => Prompt::Never,
+            Some(When::Once) => Prompt::Once,
+            _ => Prompt::Always,
+        }),
+    ];
+    candidates
+        .into_iter()
+        .filter_map(|(index, mode)| index.map(|i| (i, mode)))
+        .max_by_key(|(i, _)| *i)
+        .map_or(Prompt::Default, |(_, mode)| mode)
+}
+
+fn parse(args: Vec<OsString>) -> (RmArgs, Prompt) {
+    let matches = match RmCommand::command().try_get_matches_from(std::iter::once(OsString::from("rm")).chain(args)) {
+        Ok(m) => m,
+        // 帮助与版本信息正常退出，用法错误与 GNU rm 一样退出码为 1
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            let _ = e.print();
+            std::process::exit(1);
+        }
+    };
+    let args = match RmCommand::from_arg_matches(&matches) {
+        Ok(c) => c.args,
+        Err(e) => {
+            let _ = e.print();
+            std::process::exit(1);
+        }
+    };
+    let prompt = prompt_mode(&matches, &args);
+    (args, prompt)
+}
+
+fn ask(question: &str) -> bool {
+    eprint!("rm: {}? ", question);
+    io::stderr().flush().ok();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok() && answer.trim_start().starts_with(['y', 'Y'])
+}
+
+// GNU rm 提示中使用的文件类型描述
+fn describe(meta: &fs::Metadata) -> &'static str {
+    let ft = meta.file_type();
+    if ft.is_dir() {
+        "directory"
+    } else if ft.is_symlink() {
+        "symbolic link"
+    } else if ft.is_fifo() {
+        "fifo"
+    } else if ft.is_socket() {
+        "socket"
+    } else if ft.is_char_device() {
+        "character special file"
+    } else if ft.is_block_device() {
+        "block special file"
+    } else if meta.len() == 0 {
+        "regular empty file"
+    } else {
+        "regular file"
+    }
+}
+
+// 与 GNU rm 相同的错误描述（不带 Rust 附加的 "(os error N)"）
+fn strerror(e: &io::Error) -> String {
+    match e.raw_os_error() {
+        Some(code) => unsafe { std::ffi::CStr::from_ptr(libc::strerror(code)) }.to_string_lossy().into_owned(),
+        None => e.to_string(),
+    }
+}
+
+fn writable(path: &Path) -> bool {
+    CString::new(path.as_os_str().as_bytes())
+        .map(|c| unsafe { libc::access(c.as_ptr(), libc::W_OK) } == 0)
+        .unwrap_or(true)
+}
+
+fn is_dot_or_dotdot(path: &Path) -> bool {
+    matches!(path.components().next_back(), Some(Component::CurDir | Component::ParentDir))
+        || path.as_os_str().as_bytes().ends_with(b"/.")
+}
+
+// 逐个检查操作数，返回应移入回收站的路径；无法删除的已打印错误并令 ok 为 false
+fn select(args: &RmArgs, prompt: Prompt, ok: &mut bool) -> Vec<PathBuf> {
+    let preserve_root = !args.no_preserve_root;
+    let preserve_all = matches!(args.preserve_root, Some(Some(PreserveRoot::All)));
+    let interactive = prompt == Prompt::Default && io::stdin().is_terminal();
+    let mount_table = if args.one_file_system { mounts::read_mountinfo() } else { Vec::new() };
+    let mut selected = Vec::new();
+
+    for file in &args.files {
+        let name = file.display();
+        if is_dot_or_dotdot(file) {
+            eprintln!("rm: refusing to remove '.' or '..' directory: skipping '{}'", name);
+            *ok = false;
+            continue;
+        }
+        let meta = match fs::symlink_metadata(file) {
+            Ok(m) => m,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && args.force => continue,
+            Err(e) => {
+                eprintln!("rm: cannot remove '{}': {}", name, strerror(&e));
+                *ok = false;
+                continue;
+            }
+        };
+
+        if meta.is_dir() {
+            if !args.recursive {
+                let empty = fs::read_dir(file).map(|mut d| d.next().is_none()).unwrap_or(false);
+                if !args.dir {
+                    eprintln!("rm: cannot remove '{}': Is a directory", name);
+                    *ok = false;
+                    continue;
+                }
+                if !empty {
+                    eprintln!("rm: cannot remove '{}': Directory not empty", name);
+                    *ok = false;
+                    continue;
+                }
+            } else {
+                let canonical = fs::canonicalize(file).unwrap_or_else(|_| file.clone());
+                if preserve_root && canonical == Path::new("/") {
+                    eprintln!("rm: it is dangerous to operate recursively on '{}'", name);
+                    eprintln!("rm: use --no-preserve-root to override this failsafe");
+                    *ok = false;
+                    continue;
+                }
+                let parent_dev = canonical.parent().and_then(|p| fs::metadata(p).ok()).map(|m| m.dev());
+                if preserve_all && parent_dev.is_some_and(|d| d != meta.dev()) {
+                    eprintln!("rm: skipping '{}', since it's on a different device", name);
+                    eprintln!("rm: and --preserve-root=all is in effect");
+                    *ok = false;
+                    continue;
+                }
+                let nested: Vec<_> = mounts::nested(&mount_table, &canonical).collect();
+                if !nested.is_empty() {
+                    for m in nested {
+                        let rel = m.mount_point.strip_prefix(&canonical).unwrap_or(&m.mount_point);
+                        eprintln!("rm: skipping '{}', since it's on a different device", file.join(rel).display());
+                    }
+                    eprintln!("rm: cannot remove '{}': Directory not empty", name);
+                    *ok = false;
+                    continue;
+                }
+            }
+        }
+
+        let confirmed = match prompt {
+            Prompt::Always => ask(&format!("remove {} '{}'", describe(&meta), name)),
+            _ if interactive && !meta.file_type().is_symlink() && !writable(file) => {
+                ask(&format!("remove write-protected {} '{}'", describe(&meta), name))
+            }
+            _ => true,
+        };
+        if confirmed {
+            selected.push(file.clone());
+        }
+    }
+    selected
+}
+
+pub fn handle_rm(raw: Vec<OsString>, trash_dir: &Path, meta_dir: &Path) {
+    let (args, prompt) = parse(raw);
+    if args.files.is_empty() {
+        if args.force {
+            return;
+        }
+        eprintln!("rm: missing operand");
+        eprintln!("Try 'rm --help' for more information.");
+        std::process::exit(1);
+    }
+
+    if prompt == Prompt::Once && (args.files.len() > 3 || args.recursive) {
+        let count = args.files.len();
+        let question = format!("remove {} argument{}{}", count, if count == 1 { "" } else { "s" },
+                               if args.recursive { " recursively" } else { "" });
+        if !ask(&question) {
+            return;
+        }
+    }
+
+    let mut ok = true;
+    let selected = select(&args, prompt, &mut ok);
+    if !selected.is_empty() {
+        let plan = match plan::build(&selected, &guard::Overrides::default(), plan::MountPolicy::Refuse, plan::BusyPolicy::Warn, false, 1, trash_dir) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("rm: {}", e);
+                std::process::exit(1);
+            }
+        };
+        ok &= handle_delete_batch(plan, DEFAULT_EXPIRE_DAYS, false, VerifyMode::Auto, ChecksumMode::Auto,
+                                  DeleteOutput::Rm { verbose: args.verbose }, trash_dir, meta_dir);
+    }
+    if !ok {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch_dir;
+
+    fn parsed(args: &[&str]) -> (RmArgs, Prompt) {
+        parse(args.iter().map(OsString::from).collect())
+    }
+
+    #[test]
+    fn parses_gnu_rm_options() {
+        let (args, prompt) = parsed(&["-rf", "a", "b"]);
+        assert!(args.recursive && args.force);
+        assert_eq!((prompt, args.files), (Prompt::Never, vec![PathBuf::from("a"), PathBuf::from("b")]));
+        let (args, _) = parsed(&["-R", "-d", "-v", "x"]);
+        assert!(args.recursive && args.dir && args.verbose);
+
+        // -- 之后的一切都是操作数
+        let (args, prompt) = parsed(&["-r", "--", "-f", "--help"]);
+        assert!(args.recursive && !args.force);
+        assert_eq!((prompt, args.files), (Prompt::Default, vec![PathBuf::from("-f"), PathBuf::from("--help")]));
+    }
+
+    #[test]
+    fn last_prompt_option_wins() {
+        assert_eq!(parsed(&["-f", "-i", "x"]).1, Prompt::Always);
+        assert_eq!(parsed(&["-i", "-f", "x"]).1, Prompt::Never);
+        assert_eq!(parsed(&["-fI", "x"]).1, Prompt::Once);
+        assert_eq!(parsed(&["-I", "--interactive", "x"]).1, Prompt::Always);
+        assert_eq!(parsed(&["-i", "--interactive=never", "x"]).1, Prompt::Never);
+        assert_eq!(parsed(&["--interactive=once", "x"]).1, Prompt::Once);
+        // -i 之后 -f 忽略不存在文件的效果仍然保留
+        let (args, prompt) = parsed(&["-f", "-i", "x"]);
+        assert!(args.force && prompt == Prompt::Always);
+    }
+
+    #[test]
+    fn parses_preserve_root() {
+        let (args, _) = parsed(&["-r", "x"]);
+        assert!(!args.no_preserve_root && args.preserve_root.is_none());
+        let (args, _) = parsed(&["--preserve-root=all", "-r", "x"]);
+        assert_eq!(args.preserve_root, Some(Some(PreserveRoot::All)));
+        let (args, _) = parsed(&["--preserve-root", "--no-preserve-root", "-r", "x"]);
+        assert!(args.no_preserve_root && args.preserve_root.is_none());
+        let (args, _) = parsed(&["--no-preserve-root", "--preserve-root", "-r", "x"]);
+        assert!(!args.no_preserve_root && args.preserve_root == Some(None));
+    }
+
+    #[test]
+    fn selects_operands_like_gnu_rm() {
+        assert!(is_dot_or_dotdot(Path::new(".")) && is_dot_or_dotdot(Path::new("a/..")) && is_dot_or_dotdot(Path::new("a/.")));
+        assert!(!is_dot_or_dotdot(Path::new(".hidden")) && !is_dot_or_dotdot(Path::new("a/..b")));
+
+        let dir = scratch_dir("rm-select");
+        fs::create_dir_all(dir.join("empty")).unwrap();
+        fs::create_dir_all(dir.join("full")).unwrap();
+        fs::write(dir.join("full/f"), "x").unwrap();
+        fs::write(dir.join("file"), "").unwrap();
+        assert_eq!(describe(&fs::symlink_metadata(dir.join("file")).unwrap()), "regular empty file");
+        assert_eq!(describe(&fs::symlink_metadata(dir.join("full/f")).unwrap()), "regular file");
+        let operands = |names: &[&str]| names.iter().map(|n| dir.join(n).to_str().unwrap().to_string()).collect::<Vec<_>>();
+        let run = |flags: &[&str], names: &[&str]| {
+            let mut argv: Vec<String> = flags.iter().map(|f| f.to_string()).collect();
+            argv.extend(operands(names));
+            let (args, _) = parse(argv.into_iter().map(OsString::from).collect());
+            let mut ok = true;
+            let selected = select(&args, Prompt::Never, &mut ok);
+            (selected.iter().map(|p| p.strip_prefix(&dir).unwrap().display().to_string()).collect::<Vec<_>>(), ok)
+        };
+
+        assert_eq!(run(&[], &["file", "empty", "missing"]), (vec!["file".to_string()], false));
+        assert_eq!(run(&["-d"], &["empty", "full"]), (vec!["empty".to_string()], false));
+        assert_eq!(run(&["-r"], &["empty", "full", "."]), (vec!["empty".to_string(), "full".to_string()], false));
+        // -f 忽略不存在的文件，不影响退出码
+        assert_eq!(run(&["-f"], &["missing", "file"]), (vec!["file".to_string()], true));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
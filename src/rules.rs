@@ -0,0 +1,331 @@
+// 用户声明的防护规则：存储目录下的 rules.json 与目标上级目录中的 .srm-protect 标记
+//
+// rules.json 中的规则按顺序检查，第一条所有条件都满足的规则生效；标记取离目标最近的一个
+// （目标本身是目录时也检查其中的标记），目标之下的标记取最严格的一个。都命中时取更严格的动作（deny > confirm > allow）。
+// 规则文件无法解析时拒绝执行删除，避免声明的保护静默失效。
+
+use crate::DiskUsage;
+use bytesize::ByteSize;
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+
+pub const RULES_FILE: &str = "rules.json";
+pub const MARKER_FILE: &str = ".srm-protect";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Allow,
+    Confirm,
+    Deny,
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Action::Allow => write!(f, "allow"),
+            Action::Confirm => write!(f, "confirm"),
+            Action::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+// 大小既可以写字节数，也可以写 "10GB"、"512 MiB" 这样的字符串
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum Size {
+    Bytes(u64),
+    Text(String),
+}
+
+impl Size {
+    pub fn bytes(&self) -> Result<u64, String> {
+        match self {
+            Size::Bytes(b) => Ok(*b),
+            Size::Text(t) => t.parse::<ByteSize>().map(|b| b.as_u64()),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Owner {
+    Uid(u32),
+    Name(String),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    name: String,
+    action: Action,
+    glob: Option<String>,
+    prefix: Option<PathBuf>,
+    min_size: Option<Size>,
+    min_items: Option<usize>,
+    owner: Option<Owner>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RawRules {
+    rules: Vec<RawRule>,
+}
+
+struct Rule {
+    name: String,
+    action: Action,
+    glob: Option<Vec<String>>,
+    prefix: Option<PathBuf>,
+    min_size: Option<u64>,
+    min_items: Option<usize>,
+    owner: Option<u32>,
+}
+
+// 命中的规则，写入审计日志
+#[derive(Serialize, Debug, Clone)]
+pub struct Verdict {
+    pub name: String,
+    pub action: Action,
+    // 规则文件或标记文件的路径
+    pub source: String,
+}
+
+pub struct Rules {
+    path: PathBuf,
+    rules: Vec<Rule>,
+}
+
+fn expand_home(text: &str) -> PathBuf {
+    match (text.strip_prefix('~'), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(format!("{}{}", home.to_string_lossy(), rest))
+        }
+        _ => PathBuf::from(text),
+    }
+}
+
+fn lookup_uid(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let pw = unsafe { libc::getpwnam(name.as_ptr()) };
+    if pw.is_null() {
+        None
+    } else {
+        Some(unsafe { (*pw).pw_uid })
+    }
+}
+
+fn components(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(n) => Some(n.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+// `[...]` 字符类，返回是否匹配以及类之后的剩余模式；`!` 或 `^` 开头表示取反
+fn match_class(pattern: &[char], c: char) -> Option<(bool, &[char])> {
+    let negated = matches!(pattern.first(), Some('!' | '^'));
+    let mut i = usize::from(negated);
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() && (first || pattern[i] != ']') {
+        first = false;
+        if i + 2 < pattern.len() && pattern[i + 1] == '-' && pattern[i + 2] != ']' {
+            matched |= (pattern[i]..=pattern[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= pattern[i] == c;
+            i += 1;
+        }
+    }
+    (i < pattern.len()).then(|| (matched != negated, &pattern[i + 1..]))
+}
+
+// 单个路径组件内的通配：`*` 匹配任意字符串，`?` 匹配单个字符，`[...]` 匹配字符类
+pub fn match_component(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => match_component(&pattern[1..], name) || (!name.is_empty() && match_component(pattern, &name[1..])),
+        (Some('?'), Some(_)) => match_component(&pattern[1..], &name[1..]),
+        (Some('['), Some(&c)) => match match_class(&pattern[1..], c) {
+            Some((matched, rest)) => matched && match_component(rest, &name[1..]),
+            // 没有闭合的 `[` 按普通字符处理
+            None => c == '[' && match_component(&pattern[1..], &name[1..]),
+        },
+        (Some('\\'), Some(n)) if pattern.get(1) == Some(n) => match_component(&pattern[2..], &name[1..]),
+        (Some(p), Some(n)) if p == n => match_component(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+// `**` 匹配任意多层（包括零层）目录，其余组件逐个匹配
+pub fn match_glob(pattern: &[String], path: &[String]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some(p) if p == "**" => (0..=path.len()).any(|skip| match_glob(&pattern[1..], &path[skip..])),
+        Some(p) => match path.first() {
+            Some(name) => {
+                let (p, n): (Vec<char>, Vec<char>) = (p.chars().collect(), name.chars().collect());
+                match_component(&p, &n) && match_glob(&pattern[1..], &path[1..])
+            }
+            None => false,
+        },
+    }
+}
+
+// 通配模式中第一个通配组件之前的固定部分
+fn literal_prefix(pattern: &[String]) -> PathBuf {
+    let mut prefix = PathBuf::from("/");
+    for c in pattern.iter().take_while(|c| !c.contains(['*', '?', '['])) {
+        prefix.push(c);
+    }
+    prefix
+}
+
+fn read_marker(path: &Path) -> Option<Action> {
+    let content = fs::read_to_string(path).ok()?;
+    let word = content.lines().map(str::trim).find(|l| !l.is_empty() && !l.starts_with('#')).unwrap_or("");
+    Some(match word.to_ascii_lowercase().as_str() {
+        "deny" => Action::Deny,
+        "allow" => Action::Allow,
+        // 空文件或无法识别的内容都按需要确认处理
+        _ => Action::Confirm,
+    })
+}
+
+impl Rules {
+    // 规则文件不存在时没有任何规则
+    pub fn load(base: &Path) -> Result<Rules, String> {
+        let path = base.join(RULES_FILE);
+        let content = match fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Rules { path, rules: Vec::new() }),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        let raw: RawRules = serde_json::from_str(&content).map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+        let mut rules = Vec::new();
+        for r in raw.rules {
+            let invalid = |what: String| format!("Invalid {}: rule '{}': {}", path.display(), r.name, what);
+            let glob = r.glob.as_deref().map(expand_home);
+            if let Some(g) = glob.as_ref().filter(|g| !g.is_absolute()) {
+                return Err(invalid(format!("glob must be an absolute path: {}", g.display())));
+            }
+            let prefix = r.prefix.as_deref().map(|p| expand_home(&p.to_string_lossy()));
+            if let Some(p) = prefix.as_ref().filter(|p| !p.is_absolute()) {
+                return Err(invalid(format!("prefix must be an absolute path: {}", p.display())));
+            }
+            let min_size = r.min_size.as_ref().map(Size::bytes).transpose().map_err(|e| invalid(format!("min_size: {}", e)))?;
+            let owner = match &r.owner {
+                None => None,
+                Some(Owner::Uid(uid)) => Some(*uid),
+                Some(Owner::Name(n)) => Some(lookup_uid(n).ok_or_else(|| invalid(format!("unknown user '{}'", n)))?),
+            };
+            rules.push(Rule {
+                name: r.name.clone(),
+                action: r.action,
+                glob: glob.map(|g| components(&g)),
+                // 被检查的目标路径不含软链接，前缀也解析到实际位置
+                prefix: prefix.map(|p| p.canonicalize().unwrap_or_else(|_| crate::plan::lexical(&p))),
+                min_size,
+                min_items: r.min_items,
+                owner,
+            });
+        }
+        Ok(Rules { path, rules })
+    }
+
+    // usage 只在有规则需要大小或项目数、且其他条件都已满足时才调用；nested 为目标之下找到的标记（相对目标的路径）
+    pub fn evaluate(&self, path: &Path, meta: &fs::Metadata, nested: &[PathBuf],
+                    usage: &mut dyn FnMut() -> Option<DiskUsage>) -> Option<Verdict> {
+        let mut measured: Option<Option<DiskUsage>> = None;
+        let file_rule = self.rules.iter().find(|r| {
+            // 删除上级目录同样会删除受保护的内容
+            let location = r.glob.as_ref().is_none_or(|g| match_glob(g, &components(path)) || literal_prefix(g).starts_with(path))
+                && r.prefix.as_ref().is_none_or(|p| path.starts_with(p) || p.starts_with(path));
+            let mut thresholds = || {
+                let u = *measured.get_or_insert_with(&mut *usage);
+                u.is_some_and(|u| r.min_size.is_none_or(|min| u.apparent >= min) && r.min_items.is_none_or(|min| u.items >= min))
+            };
+            location
+                && r.owner.is_none_or(|uid| meta.uid() == uid)
+                && ((r.min_size.is_none() && r.min_items.is_none()) || thresholds())
+        });
+        let file_verdict = file_rule.map(|r| Verdict { name: r.name.clone(), action: r.action, source: self.path.display().to_string() });
+
+        // 目标本身是目录时其中的标记同样生效；不跟随软链接
+        let start = if meta.is_dir() { Some(path) } else { path.parent() };
+        let marker_verdict = |marker: PathBuf| {
+            read_marker(&marker).map(|action| Verdict { name: MARKER_FILE.to_string(), action, source: marker.display().to_string() })
+        };
+        let nearest = start.into_iter().flat_map(Path::ancestors).find_map(|dir| marker_verdict(dir.join(MARKER_FILE)));
+        // 下层的标记保护所在的子树，删除目标会一并删除，取其中最严格的一个
+        let below = nested.iter().filter_map(|rel| marker_verdict(path.join(rel))).max_by_key(|v| v.action);
+
+        [file_verdict, nearest, below].into_iter().flatten().reduce(|a, b| if b.action > a.action { b } else { a })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str, path: &str) -> bool {
+        match_glob(&components(Path::new(pattern)), &components(Path::new(path)))
+    }
+
+    #[test]
+    fn double_star_matches_any_depth() {
+        assert!(glob("/home/*/.ssh/**", "/home/alice/.ssh"));
+        assert!(glob("/home/*/.ssh/**", "/home/alice/.ssh/id_ed25519"));
+        assert!(glob("/home/*/.ssh/**", "/home/alice/.ssh/keys/old/id_rsa"));
+        assert!(!glob("/home/*/.ssh/**", "/home/alice/backup/.ssh"));
+
+        assert!(glob("/**/secret", "/secret"));
+        assert!(glob("/**/secret", "/a/b/c/secret"));
+        assert!(!glob("/**/secret", "/a/secret/b"));
+
+        assert!(glob("/srv/**/data/*.db", "/srv/data/app.db"));
+        assert!(glob("/srv/**/data/*.db", "/srv/a/b/data/app.db"));
+        assert!(!glob("/srv/**/data/*.db", "/srv/a/data/sub/app.db"));
+        assert!(glob("/srv/**/**/x", "/srv/x"));
+    }
+
+    #[test]
+    fn star_and_question_mark_stay_within_a_component() {
+        assert!(glob("/var/*/app.log", "/var/log/app.log"));
+        assert!(!glob("/var/*/app.log", "/var/log/old/app.log"));
+        assert!(glob("/tmp/?.txt", "/tmp/a.txt"));
+        assert!(!glob("/tmp/?.txt", "/tmp/ab.txt"));
+        assert!(!glob("/tmp/*", "/tmp"));
+        assert!(!glob("/tmp/*", "/tmp/a/b"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(glob("/var/log/app[0-9].log", "/var/log/app3.log"));
+        assert!(!glob("/var/log/app[0-9].log", "/var/log/appx.log"));
+        assert!(glob("/x/[abc]", "/x/b"));
+        assert!(!glob("/x/[abc]", "/x/d"));
+        assert!(glob("/x/[!a-c]", "/x/d"));
+        assert!(!glob("/x/[!a-c]", "/x/b"));
+        assert!(glob("/x/[^a-c]", "/x/z"));
+        // 紧跟在 `[` 之后的 `]` 是普通字符
+        assert!(glob("/x/[]a]", "/x/]"));
+        assert!(glob("/x/[a-]", "/x/-"));
+        // 没有闭合的 `[` 按普通字符处理
+        assert!(glob("/x/[ab", "/x/[ab"));
+        assert!(glob("/x/\\*", "/x/*"));
+        assert!(!glob("/x/\\*", "/x/a"));
+    }
+
+    #[test]
+    fn literal_prefix_stops_at_first_wildcard() {
+        assert_eq!(literal_prefix(&components(Path::new("/home/*/.ssh/**"))), PathBuf::from("/home"));
+        assert_eq!(literal_prefix(&components(Path::new("/srv/data/[0-9]*"))), PathBuf::from("/srv/data"));
+    }
+}
@@ -0,0 +1,333 @@
+// 目录树的一次性扫描：用量统计与按遍历顺序记录的条目
+//
+// 删除计划、进度条和跨文件系统移动共用同一份结果，不再对同一棵树反复 readdir/stat。
+// 移动时按记录的顺序逐项处理：扫描后消失的条目直接跳过；扫描后新出现的条目会让所在目录
+// 无法删除，该项失败并回滚，不会丢失。
+
+use crate::{at, mounts, DiskUsage, INTERRUPTED, MAX_RECURSION_DEPTH};
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+pub enum Step {
+    Dir(OsString, at::Stat),
+    Item(OsString, at::Stat),
+    // 文件系统边界的另一侧：不进入、不移动，连同其上级目录一起留在原处
+    Mount,
+    // 离开最近一个 Dir
+    Leave,
+}
+
+// 扫描中遇到的文件系统边界
+pub struct Boundary {
+    // 相对树根的路径
+    pub rel: PathBuf,
+    pub dev: u64,
+    // 边界另一侧的内容已计入扫描结果并会被移走；挂载点本身仍留在原处
+    pub included: bool,
+}
+
+pub struct Tree {
+    pub usage: DiskUsage,
+    // 根目录下的直接条目数
+    pub top_level: usize,
+    pub steps: Vec<Step>,
+    pub mounts: Vec<Boundary>,
+}
+
+// 给定 fence 时在文件系统边界处停下（或按 fence.include 进入），prefix 为 root 相对树根的路径
+fn collect(root: &at::Dir, prefix: &Path, max_depth: usize, fence: Option<&mounts::Fence>,
+           steps: &mut Vec<Step>, mounts: &mut Vec<Boundary>) -> io::Result<()> {
+    let mut devs = vec![root.metadata()?.dev()];
+    let pruned = Cell::new(false);
+    at::walk_pruned(root, max_depth, |_, _| pruned.get(), |event| {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "Operation interrupted"));
+        }
+        match event {
+            at::Event::Entry { name, rel, stat, .. } => {
+                let rel = prefix.join(rel);
+                let crossed = fence.is_some_and(|f| f.crosses(&rel, stat, *devs.last().unwrap()));
+                // 单个文件的挂载点无法移走，总是留在原处
+                let descend = stat.is_dir() && (!crossed || fence.is_some_and(|f| f.include));
+                pruned.set(!descend);
+                if crossed {
+                    mounts.push(Boundary { rel, dev: stat.dev(), included: descend });
+                    if !descend {
+                        steps.push(Step::Mount);
+                        return Ok(());
+                    }
+                }
+                if stat.is_dir() {
+                    devs.push(stat.dev());
+                    steps.push(Step::Dir(name.to_os_string(), *stat));
+                } else {
+                    steps.push(Step::Item(name.to_os_string(), *stat));
+                }
+            }
+            // 无法读取的目录按空目录记录，真正移动时再报错
+            at::Event::Leave { .. } | at::Event::Unreadable { .. } => {
+                devs.pop();
+                steps.push(Step::Leave);
+            }
+        }
+        Ok(())
+    })
+}
+
+// jobs > 1 时根目录下的各个子目录由多个线程分别扫描，结果按原顺序拼接
+fn collect_parallel(root: &at::Dir, jobs: usize, fence: Option<&mounts::Fence>,
+                    steps: &mut Vec<Step>, mounts: &mut Vec<Boundary>) -> io::Result<()> {
+    let root_dev = root.metadata()?.dev();
+    let mut children = Vec::new();
+    for name in root.entries()? {
+        match root.stat(&name) {
+            Ok(stat) => {
+                let crossed = fence.is_some_and(|f| f.crosses(Path::new(&name), &stat, root_dev));
+                let descend = stat.is_dir() && (!crossed || fence.is_some_and(|f| f.include));
+                children.push((name, stat, crossed, descend));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    type Collected = io::Result<(Vec<Step>, Vec<Boundary>)>;
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<Collected>>> = children.iter().map(|_| Mutex::new(None)).collect();
+    std::thread::scope(|s| {
+        for _ in 0..jobs.min(children.len()) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some((name, _, _, descend)) = children.get(i) else {
+                    break;
+                };
+                if !descend {
+                    continue;
+                }
+                let (mut sub, mut sub_mounts) = (Vec::new(), Vec::new());
+                let result = match root.open_dir(name) {
+                    Ok(dir) => collect(&dir, Path::new(name), MAX_RECURSION_DEPTH - 1, fence, &mut sub, &mut sub_mounts)
+                        .map(|_| (sub, sub_mounts)),
+                    Err(_) => Ok((Vec::new(), Vec::new())),
+                };
+                *results[i].lock().unwrap() = Some(result);
+            });
+        }
+    });
+
+    for ((name, stat, crossed, descend), result) in children.into_iter().zip(results) {
+        if crossed {
+            mounts.push(Boundary { rel: PathBuf::from(&name), dev: stat.dev(), included: descend });
+        }
+        if crossed && !descend {
+            steps.push(Step::Mount);
+            continue;
+        }
+        if !stat.is_dir() {
+            steps.push(Step::Item(name, stat));
+            continue;
+        }
+        steps.push(Step::Dir(name, stat));
+        if let Some(sub) = result.into_inner().unwrap() {
+            let (sub, sub_mounts) = sub?;
+            steps.extend(sub);
+            mounts.extend(sub_mounts);
+        }
+        steps.push(Step::Leave);
+    }
+    Ok(())
+}
+
+// fence 为 None 时跨越所有文件系统边界，不做区分
+pub fn scan(root: &at::Dir, jobs: usize, fence: Option<&mounts::Fence>) -> io::Result<Tree> {
+    let (mut steps, mut mounts) = (Vec::new(), Vec::new());
+    if jobs > 1 {
+        collect_parallel(root, jobs, fence, &mut steps, &mut mounts)?;
+    } else {
+        collect(root, Path::new(""), MAX_RECURSION_DEPTH, fence, &mut steps, &mut mounts)?;
+    }
+
+    let mut usage = DiskUsage { allocated: root.metadata()?.allocated(), ..Default::default() };
+    let mut seen = HashSet::new();
+    let mut depth = 0usize;
+    let mut top_level = 0usize;
+    for step in &steps {
+        match step {
+            Step::Dir(_, stat) | Step::Item(_, stat) => {
+                if depth == 0 {
+                    top_level += 1;
+                }
+                usage.add(stat, &mut seen);
+                if matches!(step, Step::Dir(..)) {
+                    depth += 1;
+                }
+            }
+            Step::Leave => depth -= 1,
+            Step::Mount => {}
+        }
+    }
+    Ok(Tree { usage, top_level, steps, mounts })
+}
+
+// 在 root 之下查找名为 names 之一的条目，返回相对 root 的路径；不进入匹配到的目录，最多深入 max_depth 层。
+// 有扫描结果时直接取用；没有时（整体 rename 的目录）按 d_type 只列出目录，只对目录 stat，不进入其他文件系统。
+pub fn find_names(root: &Path, tree: Option<&Tree>, names: &[&str], max_depth: usize) -> io::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    let Some(tree) = tree else {
+        let dir = at::Dir::open(root)?;
+        let dev = dir.metadata()?.dev();
+        find_unscanned(&dir, &mut PathBuf::new(), dev, names, max_depth, &mut found)?;
+        return Ok(found);
+    };
+
+    let mut rel = PathBuf::new();
+    let mut depth = 0usize;
+    // 匹配到的目录内部的层数
+    let mut hidden = 0usize;
+    for step in &tree.steps {
+        match step {
+            Step::Dir(name, _) | Step::Item(name, _) => {
+                let matched = hidden == 0 && depth <= max_depth && names.iter().any(|n| name == n);
+                if matched {
+                    found.push(rel.join(name));
+                }
+                if matches!(step, Step::Dir(..)) {
+                    if hidden > 0 || matched {
+                        hidden += 1;
+                    } else {
+                        rel.push(name);
+                    }
+                    depth += 1;
+                }
+            }
+            Step::Leave => {
+                if hidden > 0 {
+                    hidden -= 1;
+                } else {
+                    rel.pop();
+                }
+                depth -= 1;
+            }
+            Step::Mount => {}
+        }
+    }
+    Ok(found)
+}
+
+// 无法读取的目录直接跳过
+fn find_unscanned(dir: &at::Dir, rel: &mut PathBuf, dev: u64, names: &[&str], depth: usize,
+                  found: &mut Vec<PathBuf>) -> io::Result<()> {
+    if INTERRUPTED.load(Ordering::Relaxed) {
+        return Err(io::Error::new(io::ErrorKind::Interrupted, "Operation interrupted"));
+    }
+    let Ok(entries) = dir.typed_entries() else {
+        return Ok(());
+    };
+    for (name, kind) in entries {
+        if names.iter().any(|n| name == *n) {
+            found.push(rel.join(&name));
+            continue;
+        }
+        // 文件系统不提供 d_type 时由 stat 判断
+        if depth == 0 || !matches!(kind, libc::DT_DIR | libc::DT_UNKNOWN) {
+            continue;
+        }
+        let Ok(stat) = dir.stat(&name) else {
+            continue;
+        };
+        if !stat.is_dir() || stat.dev() != dev {
+            continue;
+        }
+        if let Ok(sub) = dir.open_dir(&name) {
+            rel.push(&name);
+            let result = find_unscanned(&sub, rel, dev, names, depth - 1, found);
+            rel.pop();
+            result?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dir_stats, scratch_dir};
+    use std::fs;
+
+    // 按扫描顺序展开为相对路径，目录以 / 结尾
+    fn paths(tree: &Tree) -> Vec<String> {
+        let (mut rel, mut out) = (PathBuf::new(), Vec::new());
+        for step in &tree.steps {
+            match step {
+                Step::Dir(name, _) => {
+                    rel.push(name);
+                    out.push(format!("{}/", rel.display()));
+                }
+                Step::Item(name, _) => out.push(rel.join(name).display().to_string()),
+                Step::Leave => {
+                    rel.pop();
+                }
+                Step::Mount => out.push("<mount>".into()),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn scans_once_with_the_same_result_in_parallel() {
+        let root = scratch_dir("scan-tree");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir_all(root.join("c")).unwrap();
+        fs::write(root.join("a/b/f"), "12345").unwrap();
+        fs::write(root.join("top"), "xyz").unwrap();
+        // 硬链接只计一次
+        fs::hard_link(root.join("a/b/f"), root.join("c/link")).unwrap();
+
+        let dir = at::Dir::open(&root).unwrap();
+        let single = scan(&dir, 1, None).unwrap();
+        let parallel = scan(&dir, 4, None).unwrap();
+        let stats = dir_stats(&dir).unwrap();
+        let _ = fs::remove_dir_all(&root);
+
+        let mut listed = paths(&single);
+        assert_eq!(listed, paths(&parallel));
+        listed.sort();
+        assert_eq!(listed, ["a/", "a/b/", "a/b/f", "c/", "c/link", "top"]);
+        assert_eq!((single.usage.apparent, single.usage.items, single.top_level), (8, 6, 3));
+        assert_eq!((parallel.usage.apparent, parallel.usage.allocated, parallel.usage.items), (8, single.usage.allocated, 6));
+        assert_eq!((stats.apparent, stats.allocated, stats.items), (8, single.usage.allocated, 6));
+        assert!(single.mounts.is_empty());
+    }
+
+    #[test]
+    fn finds_names_with_and_without_a_scan() {
+        let root = scratch_dir("scan-find");
+        fs::create_dir_all(root.join("proj/.git/objects")).unwrap();
+        fs::create_dir_all(root.join("proj/vendor/.git")).unwrap();
+        fs::create_dir_all(root.join("deep/x/y")).unwrap();
+        fs::write(root.join("deep/x/y/.srm-protect"), "").unwrap();
+        fs::write(root.join("deep/.srm-protect"), "").unwrap();
+
+        let dir = at::Dir::open(&root).unwrap();
+        let tree = scan(&dir, 1, None).unwrap();
+        let names = [".git", ".srm-protect"];
+        let mut scanned = find_names(&root, Some(&tree), &names, MAX_RECURSION_DEPTH).unwrap();
+        let mut unscanned = find_names(&root, None, &names, MAX_RECURSION_DEPTH).unwrap();
+        let mut shallow = find_names(&root, None, &names, 1).unwrap();
+        let _ = fs::remove_dir_all(&root);
+
+        scanned.sort();
+        unscanned.sort();
+        shallow.sort();
+        let expected: Vec<PathBuf> = ["deep/.srm-protect", "deep/x/y/.srm-protect", "proj/.git", "proj/vendor/.git"].iter().map(PathBuf::from).collect();
+        assert_eq!(scanned, expected);
+        assert_eq!(unscanned, expected);
+        // 深度限制之外的不再查找
+        assert_eq!(shallow, [PathBuf::from("deep/.srm-protect"), PathBuf::from("proj/.git")]);
+    }
+}
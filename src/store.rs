@@ -0,0 +1,431 @@
+// srm store move：把整个 .srm 存储迁移到新目录
+//
+// 元数据中的回收路径相对存储根目录，迁移只需搬动文件：同一文件系统时整体 rename，否则逐项
+// 移动（回收项连同其元数据、清单一起搬走，中断后重新执行同一命令会从断点继续）。最后把可执行
+// 文件旁的 .srm 换成指向新位置的软链接。短ID保存在元数据中，迁移前后保持不变。
+//
+// srm store reshard：把分片之前的扁平条目（trash/<ID>、meta/<ID>.meta）迁入分片子目录。
+
+use crate::{
+    atomic_save_meta, atomic_write, breaker, check_disk_space, is_shard_name, list_all_meta, load_all_meta, log_event, meta_file, path_usage,
+    remove_meta, rules, safe_move_with_progress, save_manifest, secure_create_dir, shard_of, srm_link_path, trash_rel, FileMeta,
+    VerifyMode, INTERRUPTED,
+};
+use bytesize::ByteSize;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::json;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+// 目标目录中允许已存在的条目：上次中断的迁移留下的部分存储（包括已经搬过去的规则与熔断文件）
+const STORE_ENTRIES: [&str; 8] = [
+    "trash", "meta", "quarantine", "srm.log", "srm.lock", rules::RULES_FILE, breaker::CONFIG_FILE, breaker::STATE_FILE,
+];
+
+fn resolve_dest(dest: &Path) -> io::Result<PathBuf> {
+    let abs = if dest.is_absolute() { dest.to_path_buf() } else { std::env::current_dir()?.join(dest) };
+    if abs.symlink_metadata().is_ok() {
+        return abs.canonicalize();
+    }
+    match (abs.parent(), abs.file_name()) {
+        (Some(parent), Some(name)) => Ok(parent.canonicalize()?.join(name)),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "not a directory path")),
+    }
+}
+
+// 目标不存在或为空时返回 false，是上次未完成的迁移目标时返回 true
+fn check_dest(dest: &Path) -> io::Result<bool> {
+    let entries = match fs::read_dir(dest) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let mut resuming = false;
+    for entry in entries {
+        let name = entry?.file_name();
+        if !STORE_ENTRIES.iter().any(|s| name == *s) {
+            return Err(io::Error::other(format!("destination is not empty (contains '{}')", name.to_string_lossy())));
+        }
+        resuming = true;
+    }
+    Ok(resuming)
+}
+
+// 把 src_dir 中剩余的条目原样搬到 dst_dir（孤儿项、未识别文件等留给新位置的 fsck 处理）；
+// 分片子目录逐项合并，搬空后删除
+fn move_remaining(src_dir: &Path, dst_dir: &Path, verify: VerifyMode) -> io::Result<usize> {
+    let entries = match fs::read_dir(src_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    secure_create_dir(dst_dir)?;
+    let mut moved = 0;
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let dst = dst_dir.join(&name);
+        if is_shard_name(&name) && entry.file_type()?.is_dir() {
+            if fs::remove_dir(entry.path()).is_ok() {
+                continue;
+            }
+            moved += move_remaining(&entry.path(), &dst, verify)?;
+            let _ = fs::remove_dir(entry.path());
+            continue;
+        }
+        if dst.symlink_metadata().is_ok() {
+            eprintln!("⚠️  Left {} in place: {} already exists", src_dir.join(&name).display(), dst.display());
+            continue;
+        }
+        safe_move_with_progress(&src_dir.join(&name), &dst, false, verify)?;
+        moved += 1;
+    }
+    Ok(moved)
+}
+
+fn move_items(base: &Path, trash_dir: &Path, meta_dir: &Path, dest: &Path, verify: VerifyMode) -> io::Result<(usize, u64)> {
+    let dest_meta = dest.join("meta");
+    secure_create_dir(dest)?;
+    secure_create_dir(&dest.join("trash"))?;
+    secure_create_dir(&dest_meta)?;
+
+    let mut metas: Vec<_> = load_all_meta(meta_dir, trash_dir, true).into_iter().collect();
+    metas.sort_by(|a, b| a.0.cmp(&b.0));
+    check_disk_space(dest, metas.iter().map(|(_, m)| m.allocated()).sum(), false)?;
+
+    let pb = ProgressBar::new(metas.len() as u64);
+    pb.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] {pos}/{len} items [{wide_bar:.cyan/blue}] {percent}% {msg}")
+        .unwrap()
+        .progress_chars("█▓▒░ "));
+
+    let mut moved = 0usize;
+    let mut bytes = 0u64;
+    for (trash_id, mut meta) in metas {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            pb.abandon_with_message("Interrupted");
+            return Err(io::Error::new(io::ErrorKind::Interrupted, format!("interrupted after {} item(s)", moved)));
+        }
+        pb.set_message(meta.short_id.clone());
+
+        let src = meta.trash(trash_dir);
+        let rel = src.strip_prefix(base).map(Path::to_path_buf).unwrap_or_else(|_| Path::new("trash").join(&trash_id));
+        let dst = dest.join(&rel);
+        if let Some(parent) = dst.parent().filter(|p| !p.exists()) {
+            secure_create_dir(parent)?;
+        }
+        bytes += safe_move_with_progress(&src, &dst, false, verify)?;
+
+        // 清单写入新位置，旧的随 remove_meta 一起删除
+        match fs::read(meta_file(meta_dir, &trash_id, "manifest")) {
+            Ok(manifest) => save_manifest(&trash_id, &manifest, &dest_meta)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        meta.set_trash(&rel);
+        if meta.allocated_bytes.is_some() {
+            meta.allocated_bytes = path_usage(&dst).map(|u| u.allocated).ok();
+        }
+        atomic_save_meta(&trash_id, &meta, &dest_meta)?;
+        remove_meta(&trash_id, meta_dir);
+
+        moved += 1;
+        pb.inc(1);
+    }
+    pb.finish_with_message("Done");
+
+    let mut leftovers = 0;
+    for sub in ["trash", "meta", "quarantine"] {
+        leftovers += move_remaining(&base.join(sub), &dest.join(sub), verify)?;
+    }
+    if leftovers > 0 {
+        println!("ℹ️  Moved {} other entr{} as-is (run `srm fsck` to review them)", leftovers, if leftovers == 1 { "y" } else { "ies" });
+    }
+
+    // 用户规则与熔断配置、计数随存储一起迁移；目标中已有同名文件时保留目标中的版本
+    for name in [rules::RULES_FILE, breaker::CONFIG_FILE, breaker::STATE_FILE] {
+        let file = base.join(name);
+        if file.exists() {
+            let dest_file = dest.join(name);
+            if dest_file.exists() {
+                eprintln!("⚠️  Left {} in place: {} already exists", file.display(), dest_file.display());
+            } else {
+                safe_move_with_progress(&file, &dest_file, false, verify)?;
+            }
+        }
+    }
+
+    // 续传时目标中可能已有日志，把旧日志追加过去
+    let log = base.join("srm.log");
+    if log.exists() {
+        let dest_log = dest.join("srm.log");
+        if dest_log.exists() {
+            let content = fs::read(&log)?;
+            io::Write::write_all(&mut fs::OpenOptions::new().append(true).open(&dest_log)?, &content)?;
+            fs::remove_file(&log)?;
+        } else {
+            safe_move_with_progress(&log, &dest_log, false, verify)?;
+        }
+    }
+    Ok((moved, bytes))
+}
+
+// 旧存储目录此时只剩锁文件和空目录；逐个删除，遇到意外内容就停下而不是递归删除
+fn remove_old_store(base: &Path) -> io::Result<()> {
+    let _ = fs::remove_file(base.join("srm.lock"));
+    for sub in ["trash", "meta", "quarantine"] {
+        match fs::remove_dir(base.join(sub)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    match fs::remove_dir(base) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+// 以 rename 原子地把 .srm 换成指向新存储的软链接
+fn point_link_to(link: &Path, dest: &Path) -> io::Result<()> {
+    let tmp = link.with_file_name(".srm.link.tmp");
+    let _ = fs::remove_file(&tmp);
+    std::os::unix::fs::symlink(dest, &tmp)?;
+    fs::rename(&tmp, link)
+}
+
+pub fn handle_store_move(dest: &Path, verify: VerifyMode, base: &Path, trash_dir: &Path, meta_dir: &Path) {
+    let dest = match resolve_dest(dest) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("❌  Invalid destination '{}': {}", dest.display(), e);
+            std::process::exit(1);
+        }
+    };
+    if dest.starts_with(base) || base.starts_with(&dest) {
+        eprintln!("❌  Destination {} overlaps the current store {}", dest.display(), base.display());
+        std::process::exit(1);
+    }
+    let resuming = match check_dest(&dest) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("❌  Cannot use {}: {}", dest.display(), e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("📦 Moving store {} → {}{}", base.display(), dest.display(), if resuming { " (resuming)" } else { "" });
+    let renamed = !resuming && fs::rename(base, &dest).is_ok();
+    let (items, bytes) = if renamed {
+        println!("   Same filesystem: renamed in place");
+        (list_all_meta(&dest.join("meta"), &dest.join("trash")).len(), 0)
+    } else {
+        match move_items(base, trash_dir, meta_dir, &dest, verify) {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("❌  Store move stopped: {}", e);
+                eprintln!("   Items not yet moved are still usable in {}; re-run `srm store move {}` to continue.",
+                          base.display(), dest.display());
+                std::process::exit(1);
+            }
+        }
+    };
+
+    if !renamed {
+        if let Err(e) = remove_old_store(base) {
+            eprintln!("⚠️  Could not remove old store {}: {}", base.display(), e);
+        }
+    }
+    let link = srm_link_path();
+    if let Err(e) = point_link_to(&link, &dest) {
+        eprintln!("❌  Store moved to {} but {} could not be updated: {}", dest.display(), link.display(), e);
+        eprintln!("   Create it manually: ln -sfn {} {}", dest.display(), link.display());
+        std::process::exit(1);
+    }
+
+    log_event("INFO", "Store moved", Some(json!({
+        "action": "store-move",
+        "from": base.display().to_string(),
+        "to": dest.display().to_string(),
+        "items": items,
+        "bytes_copied": bytes,
+        "renamed": renamed
+    })));
+    println!("✅ Store moved ({} item(s), {} copied); {} → {}", items, ByteSize(bytes), link.display(), dest.display());
+}
+
+// 单个条目迁入分片：先把清单和指向新位置的元数据写入分片，再移动回收项，最后删除扁平元数据。
+// 任何一步中断后，扁平元数据要么仍然有效、要么已被删除，重新执行即可继续
+fn reshard_item(trash_id: &str, meta: &mut FileMeta, base: &Path, trash_dir: &Path, meta_dir: &Path) -> io::Result<()> {
+    let src = meta.trash(trash_dir);
+    let rel = trash_rel(trash_id);
+    let dst = base.join(&rel);
+    if src != dst && !src.starts_with(trash_dir) {
+        return Err(io::Error::other(format!("trash entry {} is outside the store", src.display())));
+    }
+
+    let shard_meta = meta_dir.join(shard_of(trash_id));
+    for dir in [&shard_meta, dst.parent().unwrap_or(trash_dir)] {
+        if !dir.exists() {
+            secure_create_dir(dir)?;
+        }
+    }
+    let flat_manifest = meta_dir.join(format!("{}.manifest", trash_id));
+    if flat_manifest.exists() {
+        fs::rename(&flat_manifest, shard_meta.join(format!("{}.manifest", trash_id)))?;
+    }
+    meta.set_trash(&rel);
+    atomic_write(&shard_meta, &format!("{}.meta", trash_id), serde_json::to_string_pretty(meta)?.as_bytes())?;
+    if src != dst {
+        fs::rename(&src, &dst)?;
+    }
+    match fs::remove_file(meta_dir.join(format!("{}.meta", trash_id))) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+pub fn handle_store_reshard(base: &Path, trash_dir: &Path, meta_dir: &Path) {
+    let mut pending: Vec<_> = load_all_meta(meta_dir, trash_dir, true)
+        .into_iter()
+        .filter(|(id, meta)| meta.trash(trash_dir) != base.join(trash_rel(id)) || meta_dir.join(format!("{}.meta", id)).exists())
+        .collect();
+    if pending.is_empty() {
+        println!("✅ Store is already sharded");
+        return;
+    }
+    pending.sort_by(|a, b| a.0.cmp(&b.0));
+
+    println!("📦 Resharding {} item(s) in {}", pending.len(), base.display());
+    let pb = ProgressBar::new(pending.len() as u64);
+    pb.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] {pos}/{len} items [{wide_bar:.cyan/blue}] {percent}% {msg}")
+        .unwrap()
+        .progress_chars("█▓▒░ "));
+
+    let mut moved = 0usize;
+    let mut failed = 0usize;
+    for (trash_id, mut meta) in pending {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            pb.abandon_with_message("Interrupted");
+            break;
+        }
+        pb.set_message(meta.short_id.clone());
+        match reshard_item(&trash_id, &mut meta, base, trash_dir, meta_dir) {
+            Ok(()) => moved += 1,
+            Err(e) => {
+                pb.suspend(|| eprintln!("❌ Failed to reshard {} ({}): {}", meta.short_id, trash_id, e));
+                failed += 1;
+            }
+        }
+        pb.inc(1);
+    }
+    if !INTERRUPTED.load(Ordering::Relaxed) {
+        pb.finish_with_message("Done");
+    }
+
+    log_event("INFO", "Store resharded", Some(json!({
+        "action": "store-reshard",
+        "moved": moved,
+        "failed": failed,
+        "interrupted": INTERRUPTED.load(Ordering::Relaxed)
+    })));
+    if failed > 0 || INTERRUPTED.load(Ordering::Relaxed) {
+        eprintln!("⚠️  Resharded {} item(s), {} failed; re-run `srm store reshard` to continue", moved, failed);
+        std::process::exit(1);
+    }
+    println!("✅ Resharded {} item(s)", moved);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{load_meta, scratch_dir, test_meta};
+
+    const NEW_ID: &str = "01700000000000000001-0a1b2c3d";
+    const FLAT_ID: &str = "old.txt_1700000000000000002";
+
+    #[test]
+    fn refuses_foreign_destination_contents() {
+        let dest = scratch_dir("store-dest-check");
+        assert!(!check_dest(&dest).unwrap());
+        assert!(!check_dest(&dest.join("missing")).unwrap());
+        fs::write(dest.join("srm.log"), "").unwrap();
+        assert!(check_dest(&dest).unwrap());
+        fs::write(dest.join("notes.txt"), "").unwrap();
+        assert!(check_dest(&dest).unwrap_err().to_string().contains("notes.txt"));
+        let _ = fs::remove_dir_all(&dest);
+    }
+
+    #[test]
+    fn moves_items_keeping_short_ids_and_store_files() {
+        let base = scratch_dir("store-move-src");
+        let dest = scratch_dir("store-move-dst");
+        let (trash_dir, meta_dir) = (base.join("trash"), base.join("meta"));
+        fs::create_dir_all(trash_dir.join(shard_of(NEW_ID))).unwrap();
+        fs::create_dir_all(&meta_dir).unwrap();
+        fs::write(base.join(trash_rel(NEW_ID)), "new").unwrap();
+        atomic_save_meta(NEW_ID, &test_meta("fnew", &trash_rel(NEW_ID)), &meta_dir).unwrap();
+        save_manifest(NEW_ID, b"manifest", &meta_dir).unwrap();
+        // 旧版扁平条目，元数据记录绝对路径
+        fs::write(trash_dir.join(FLAT_ID), "flat").unwrap();
+        let flat = serde_json::to_string(&test_meta("fflat", &trash_dir.join(FLAT_ID))).unwrap();
+        atomic_write(&meta_dir, &format!("{}.meta", FLAT_ID), flat.as_bytes()).unwrap();
+        fs::write(trash_dir.join("stray"), "?").unwrap();
+        fs::write(base.join(rules::RULES_FILE), "{}").unwrap();
+        fs::write(base.join("srm.log"), "old\n").unwrap();
+        // 上次中断的迁移已在目标写过日志
+        fs::write(dest.join("srm.log"), "resumed\n").unwrap();
+        assert!(check_dest(&dest).unwrap());
+
+        let (moved, _) = move_items(&base, &trash_dir, &meta_dir, &dest, VerifyMode::Auto).unwrap();
+        assert_eq!(moved, 2);
+        let dest_meta = dest.join("meta");
+        let (new, _) = load_meta(&meta_file(&dest_meta, NEW_ID, "meta")).unwrap();
+        assert_eq!((new.short_id.as_str(), new.trash_path.clone()), ("fnew", trash_rel(NEW_ID).display().to_string()));
+        assert_eq!(fs::read_to_string(new.trash(&dest.join("trash"))).unwrap(), "new");
+        assert_eq!(fs::read(meta_file(&dest_meta, NEW_ID, "manifest")).unwrap(), b"manifest");
+        let (flat, _) = load_meta(&meta_file(&dest_meta, FLAT_ID, "meta")).unwrap();
+        assert_eq!((flat.short_id.as_str(), flat.trash_path.as_str()), ("fflat", "trash/old.txt_1700000000000000002"));
+        assert_eq!(fs::read_to_string(flat.trash(&dest.join("trash"))).unwrap(), "flat");
+        assert!(dest.join("trash/stray").exists() && dest.join(rules::RULES_FILE).exists());
+        assert_eq!(fs::read_to_string(dest.join("srm.log")).unwrap(), "resumed\nold\n");
+
+        remove_old_store(&base).unwrap();
+        assert!(!base.exists());
+        let _ = fs::remove_dir_all(&dest);
+    }
+
+    #[test]
+    fn reshards_flat_entries_and_resumes() {
+        let base = scratch_dir("store-reshard");
+        let (trash_dir, meta_dir) = (base.join("trash"), base.join("meta"));
+        fs::create_dir_all(&trash_dir).unwrap();
+        fs::create_dir_all(&meta_dir).unwrap();
+        fs::write(trash_dir.join(FLAT_ID), "flat").unwrap();
+        let mut meta = test_meta("fflat", &Path::new("trash").join(FLAT_ID));
+        atomic_write(&meta_dir, &format!("{}.meta", FLAT_ID), serde_json::to_string(&meta).unwrap().as_bytes()).unwrap();
+        fs::write(meta_dir.join(format!("{}.manifest", FLAT_ID)), "manifest").unwrap();
+
+        reshard_item(FLAT_ID, &mut meta, &base, &trash_dir, &meta_dir).unwrap();
+        let shard = meta_dir.join(shard_of(FLAT_ID));
+        assert!(!trash_dir.join(FLAT_ID).exists() && !meta_dir.join(format!("{}.meta", FLAT_ID)).exists());
+        assert_eq!(fs::read_to_string(base.join(trash_rel(FLAT_ID))).unwrap(), "flat");
+        assert_eq!(fs::read_to_string(shard.join(format!("{}.manifest", FLAT_ID))).unwrap(), "manifest");
+        let (sharded, _) = load_meta(&shard.join(format!("{}.meta", FLAT_ID))).unwrap();
+        assert_eq!((sharded.short_id.as_str(), sharded.trash(&trash_dir)), ("fflat", base.join(trash_rel(FLAT_ID))));
+
+        // 中断在删除扁平元数据之前：重新执行只需收尾
+        atomic_write(&meta_dir, &format!("{}.meta", FLAT_ID), serde_json::to_string(&sharded).unwrap().as_bytes()).unwrap();
+        let mut again = sharded.clone();
+        reshard_item(FLAT_ID, &mut again, &base, &trash_dir, &meta_dir).unwrap();
+        assert!(!meta_dir.join(format!("{}.meta", FLAT_ID)).exists());
+        assert_eq!(fs::read_to_string(base.join(trash_rel(FLAT_ID))).unwrap(), "flat");
+
+        // 存储之外的回收项不搬动
+        let mut outside = test_meta("fout", Path::new("/somewhere/else"));
+        assert!(reshard_item("x_1700000000000000003", &mut outside, &base, &trash_dir, &meta_dir).is_err());
+        let _ = fs::remove_dir_all(&base);
+    }
+}